@@ -0,0 +1,28 @@
+use std::fmt;
+
+/// Error type for awc client failures.
+///
+/// The errors returned by awc can't be sent between threads, so they are
+/// carried by their message.
+#[derive(Debug, Clone)]
+pub struct AwcError(pub String);
+
+impl fmt::Display for AwcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "awc error: {}", self.0)
+    }
+}
+
+impl std::error::Error for AwcError {}
+
+/// Error type for a request whose response was never sent back
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RequestDropped;
+
+impl fmt::Display for RequestDropped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("The request was dropped before a response was received")
+    }
+}
+
+impl std::error::Error for RequestDropped {}
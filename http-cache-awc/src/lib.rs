@@ -0,0 +1,268 @@
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    nonstandard_style,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    trivial_casts,
+    trivial_numeric_casts
+)]
+#![allow(clippy::doc_lazy_continuation)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+//! The awc client implementation for http-cache.
+//! ```no_run
+//! use awc::Client;
+//! use http_cache_awc::{Cache, CacheMode, CACacheManager, HttpCache, HttpCacheOptions};
+//!
+//! #[actix_rt::main]
+//! async fn main() -> http_cache::Result<()> {
+//!     let client = Client::default();
+//!     let cache = Cache(HttpCache {
+//!         mode: CacheMode::Default,
+//!         manager: CACacheManager::default(),
+//!         options: HttpCacheOptions::default(),
+//!     });
+//!     cache
+//!         .send(client.get("https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching"))
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! awc's middleware can't hand a cached response back to the client, so
+//! requests are sent through [`Cache::send`] instead, which buffers the
+//! response body and returns it as an [`HttpResponse`].
+mod error;
+
+use std::{collections::HashMap, time::SystemTime};
+
+use awc::{
+    http::{header, Version},
+    ClientRequest, FrozenClientRequest,
+};
+use bytes::Bytes;
+use futures_channel::{mpsc, oneshot};
+use futures_lite::{future, StreamExt};
+pub use http::request::Parts;
+use http::{header::CACHE_CONTROL, request, HeaderValue, Method};
+use http_cache::{
+    append_header, BadVersion, BoxError, HitOrMiss, HttpVersion, Middleware,
+    Result, XCACHE, XCACHELOOKUP,
+};
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+pub use error::{AwcError, RequestDropped};
+
+pub use http_cache::{
+    CacheControl, CacheEntry, CacheKeyFormat, CacheManager, CacheMode,
+    CacheOptions, FailedRequests, GlobPattern, HostIndex, HttpCache,
+    HttpCacheBuilder, HttpCacheOptions, HttpCacheOptionsBuilder, HttpResponse,
+    KeyBypass, RevalidationCounter, RevalidationReport, StaleReason,
+    StoreOutcome, StoredRequest, VariantsLock,
+};
+
+#[cfg(feature = "manager-cacache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
+pub use http_cache::{CACacheManager, DedupCACacheManager};
+
+#[cfg(feature = "manager-moka")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
+pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
+
+/// Wrapper for [`HttpCache`]
+#[derive(Debug)]
+pub struct Cache<T: CacheManager>(pub HttpCache<T>);
+
+// A request to the origin made by the middleware, along with the headers it
+// should be sent with.
+struct Fetch {
+    headers: http::HeaderMap,
+    respond: oneshot::Sender<Result<HttpResponse>>,
+}
+
+/// Implements ['Middleware'] for awc
+///
+/// awc's futures can't be sent between threads, which the middleware's must,
+/// so its fetches are sent over a channel to the task that owns the request.
+pub(crate) struct AwcMiddleware {
+    pub parts: Parts,
+    pub fetches: mpsc::UnboundedSender<Fetch>,
+}
+
+#[async_trait::async_trait]
+impl Middleware for AwcMiddleware {
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == Method::GET || self.parts.method == Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        Ok(CachePolicy::new(&self.parts, &response.parts()?))
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts,
+            &response.parts()?,
+            SystemTime::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+        for (name, value) in parts.headers.iter() {
+            self.parts.headers.insert(name.clone(), value.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        Ok(())
+    }
+    fn parts(&self) -> Result<Parts> {
+        Ok(self.parts.clone())
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(Url::parse(&self.parts.uri.to_string())?)
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.as_str().to_string())
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let (respond, response) = oneshot::channel();
+        self.fetches
+            .unbounded_send(Fetch {
+                headers: self.parts.headers.clone(),
+                respond,
+            })
+            .map_err(|_| RequestDropped)?;
+        response.await.map_err(|_| RequestDropped)?
+    }
+}
+
+fn awc_error(e: impl ToString) -> BoxError {
+    Box::new(AwcError(e.to_string()))
+}
+
+fn convert_version(version: Version) -> Result<HttpVersion> {
+    Ok(match version {
+        Version::HTTP_09 => HttpVersion::Http09,
+        Version::HTTP_10 => HttpVersion::Http10,
+        Version::HTTP_11 => HttpVersion::Http11,
+        Version::HTTP_2 => HttpVersion::H2,
+        Version::HTTP_3 => HttpVersion::H3,
+        _ => return Err(Box::new(BadVersion)),
+    })
+}
+
+// Builds the parts the middleware works with from the request awc will send.
+fn convert_request(req: &FrozenClientRequest) -> Result<Parts> {
+    let mut converted = request::Builder::new()
+        .method(req.get_method().as_str())
+        .uri(req.get_uri().to_string())
+        .body(())?;
+    let headers = converted.headers_mut();
+    for (name, value) in req.headers().iter() {
+        headers.append(
+            http::HeaderName::from_bytes(name.as_str().as_bytes())?,
+            HeaderValue::from_bytes(value.as_bytes())?,
+        );
+    }
+    Ok(converted.into_parts().0)
+}
+
+async fn fetch(
+    req: &FrozenClientRequest,
+    body: Bytes,
+    url: Url,
+    headers: &http::HeaderMap,
+) -> Result<HttpResponse> {
+    // Extra headers replace the request's own headers of the same name.
+    let mut extra_headers = header::HeaderMap::new();
+    for (name, value) in headers.iter() {
+        extra_headers.append(
+            header::HeaderName::from_bytes(name.as_str().as_bytes())?,
+            header::HeaderValue::from_bytes(value.as_bytes())?,
+        );
+    }
+    let mut res = req
+        .extra_headers(extra_headers)
+        .send_body(body)
+        .await
+        .map_err(awc_error)?;
+    let mut headers = HashMap::new();
+    for (name, value) in res.headers().iter() {
+        append_header(&mut headers, name.as_str(), value.to_str()?);
+    }
+    let body = res.body().limit(usize::MAX).await.map_err(awc_error)?;
+    Ok(HttpResponse {
+        body: body.to_vec(),
+        headers,
+        status: res.status().as_u16(),
+        url,
+        version: convert_version(res.version())?,
+    })
+}
+
+// Sends the fetches requested by the middleware until it is dropped.
+async fn send_fetches(
+    req: &FrozenClientRequest,
+    body: Bytes,
+    url: &Url,
+    mut fetches: mpsc::UnboundedReceiver<Fetch>,
+) {
+    while let Some(Fetch { headers, respond }) = fetches.next().await {
+        let res = fetch(req, body.clone(), url.clone(), &headers).await;
+        respond.send(res).ok();
+    }
+}
+
+impl<T: CacheManager + Clone> Cache<T> {
+    /// Sends the request without a body, answering it from cache when
+    /// possible.
+    pub async fn send(&self, req: ClientRequest) -> Result<HttpResponse> {
+        self.send_body(req, Bytes::new()).await
+    }
+
+    /// Sends the request with the body, answering it from cache when
+    /// possible.
+    ///
+    /// The response body is read in full, so the response can be stored.
+    pub async fn send_body(
+        &self,
+        req: ClientRequest,
+        body: impl Into<Bytes>,
+    ) -> Result<HttpResponse> {
+        let req = req.freeze().map_err(awc_error)?;
+        let body = body.into();
+        let url = Url::parse(&req.get_uri().to_string())?;
+        let (fetches, requested) = mpsc::unbounded();
+        let mut middleware =
+            AwcMiddleware { parts: convert_request(&req)?, fetches };
+        let sender = send_fetches(&req, body, &url, requested);
+        if let Some(cache_key) = self.0.cacheable_key(&middleware)? {
+            let (res, ()) =
+                future::zip(self.0.run_with_key(middleware, cache_key), sender)
+                    .await;
+            res
+        } else {
+            self.0.run_no_cache(&mut middleware).await?;
+            let fetched = async move { middleware.remote_fetch().await };
+            let (res, ()) = future::zip(fetched, sender).await;
+            let mut res = res?;
+            let miss = HitOrMiss::MISS.to_string();
+            res.headers.insert(XCACHE.to_string(), miss.clone());
+            res.headers.insert(XCACHELOOKUP.to_string(), miss);
+            Ok(res)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,323 @@
+use crate::{AwcError, Cache, RequestDropped};
+
+use awc::Client;
+use http_cache::*;
+use url::Url;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+pub(crate) fn build_mock(
+    cache_control_val: &str,
+    body: &[u8],
+    status: u16,
+    expect: u64,
+) -> Mock {
+    Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(status)
+                .insert_header("cache-control", cache_control_val)
+                .set_body_bytes(body),
+        )
+        .expect(expect)
+}
+
+const GET: &str = "GET";
+
+const TEST_BODY: &[u8] = b"test";
+
+const CACHEABLE_PUBLIC: &str = "max-age=86400, public";
+
+const MUST_REVALIDATE: &str = "public, must-revalidate";
+
+const HIT: &str = "HIT";
+
+const MISS: &str = "MISS";
+
+#[test]
+#[allow(clippy::default_constructed_unit_structs)]
+fn test_errors() -> Result<()> {
+    // Testing the Debug and Display traits for the error types
+    let err = AwcError("test".to_string());
+    assert_eq!(format!("{:?}", &err), "AwcError(\"test\")");
+    assert_eq!(err.to_string(), "awc error: test");
+    let err = RequestDropped::default();
+    assert_eq!(format!("{:?}", &err), "RequestDropped");
+    assert_eq!(
+        err.to_string(),
+        "The request was dropped before a response was received"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+mod with_moka {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn default_mode() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load cache
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.headers[XCACHELOOKUP], MISS);
+        assert_eq!(res.headers[XCACHE], MISS);
+
+        // Try to load cached object
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+
+        // Hot pass to make sure the expect response was returned
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert_eq!(res.headers[XCACHELOOKUP], HIT);
+        assert_eq!(res.headers[XCACHE], HIT);
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn default_mode_no_cache_response() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock("no-cache", TEST_BODY, 200, 2);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load cache
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.headers[XCACHELOOKUP], MISS);
+        assert_eq!(res.headers[XCACHE], MISS);
+
+        // Try to load cached object
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+
+        // Hot pass to make sure the expect response was returned
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert_eq!(res.headers[XCACHELOOKUP], HIT);
+        assert_eq!(res.headers[XCACHE], MISS);
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn no_store_mode() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache that never stores responses
+        let cache = Cache(HttpCache {
+            mode: CacheMode::NoStore,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Remote request but should not cache
+        cache.send(client.get(&url)).await?;
+
+        // Try to load cached object
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_none());
+
+        // To verify our endpoint receives the request rather than a cache hit
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.headers[XCACHELOOKUP], MISS);
+        assert_eq!(res.headers[XCACHE], MISS);
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn delete_after_non_get_head_method_request() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m_get = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let m_post = Mock::given(method("POST"))
+            .and(wiremock::matchers::body_string("payload"))
+            .respond_with(ResponseTemplate::new(201).set_body_bytes("created"))
+            .expect(1);
+        let _mock_guard_get = mock_server.register_as_scoped(m_get).await;
+        let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load cache
+        cache.send(client.get(&url)).await?;
+
+        // Try to load cached object
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+
+        // Post request to make sure the cache object at the same resource was deleted
+        let res = cache.send_body(client.post(&url), "payload").await?;
+        assert_eq!(res.status, 201);
+        assert_eq!(res.body, b"created");
+
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_none());
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn revalidation_304() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", MUST_REVALIDATE)
+                    .insert_header("etag", "\"v1\"")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(1);
+        let m_304 = Mock::given(method(GET))
+            .and(wiremock::matchers::header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1);
+        let mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load cache
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.headers[XCACHELOOKUP], MISS);
+        assert_eq!(res.headers[XCACHE], MISS);
+
+        drop(mock_guard);
+
+        let _mock_guard = mock_server.register_as_scoped(m_304).await;
+
+        // Hot pass to make sure the conditional request was sent
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert_eq!(res.headers[XCACHELOOKUP], HIT);
+        assert_eq!(res.headers[XCACHE], HIT);
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn revalidation_200() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+        let m_200 = build_mock(MUST_REVALIDATE, b"updated", 200, 1);
+        let mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load cache
+        cache.send(client.get(&url)).await?;
+
+        drop(mock_guard);
+
+        let _mock_guard = mock_server.register_as_scoped(m_200).await;
+
+        // Hot pass to make sure revalidation request was sent
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.body, b"updated");
+        assert_eq!(res.headers[XCACHELOOKUP], HIT);
+        assert_eq!(res.headers[XCACHE], MISS);
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn revalidation_500() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+        let m_500 = Mock::given(method(GET))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1);
+        let mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load cache
+        cache.send(client.get(&url)).await?;
+
+        drop(mock_guard);
+
+        let _mock_guard = mock_server.register_as_scoped(m_500).await;
+
+        // Hot pass to make sure revalidation request was sent
+        let res = cache.send(client.get(&url)).await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert!(res.headers.contains_key("warning"));
+        assert_eq!(res.headers[XCACHELOOKUP], HIT);
+        assert_eq!(res.headers[XCACHE], HIT);
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn unreachable_origin() -> Result<()> {
+        let manager = MokaManager::default();
+        let client = Client::default();
+
+        // Construct cache with defaults
+        let cache = Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager,
+            options: HttpCacheOptions::default(),
+        });
+
+        // Errors from awc are returned with their message
+        let err =
+            cache.send(client.get("http://127.0.0.1:1/")).await.unwrap_err();
+        assert!(err.is::<AwcError>());
+        Ok(())
+    }
+}
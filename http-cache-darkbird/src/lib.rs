@@ -1,8 +1,14 @@
 mod error;
 
-use http_cache::{CacheManager, HttpResponse, Result};
-
-use std::{fmt, sync::Arc, time::SystemTime};
+use http_cache::{CacheEntry, CacheManager, HttpResponse, Result};
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use darkbird::{
     document::{self, RangeField},
@@ -10,6 +16,10 @@ use darkbird::{
 };
 use http_cache_semantics::CachePolicy;
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+// Number of locks writes are striped across, see `DarkbirdManager::lock_for`.
+const LOCK_STRIPES: usize = 64;
 
 /// Implements [`CacheManager`] with [`darkbird`](https://github.com/Rustixir/darkbird) as the backend.
 #[derive(Clone)]
@@ -18,6 +28,7 @@ pub struct DarkbirdManager {
     pub cache: Arc<Storage<String, Store>>,
     /// Whether full text search should be enabled
     pub full_text: bool,
+    locks: Arc<Vec<Mutex<()>>>,
 }
 
 impl fmt::Debug for DarkbirdManager {
@@ -49,10 +60,22 @@ impl document::Indexer for Store {
 
 impl document::Tags for Store {
     fn get_tags(&self) -> Vec<String> {
-        vec![self.response.url.to_string()]
+        let mut tags = vec![self.response.url.to_string()];
+        if let Some(host) = self.response.url.host_str() {
+            tags.push(host_tag(host));
+        }
+        tags
     }
 }
 
+/// Returns the tag used to index entries by the host of the response url.
+///
+/// Entries are tagged with both their full url and this host tag, so they can be
+/// looked up with `cache.lookup_by_tag(&host_tag("example.com"))`.
+pub fn host_tag(host: &str) -> String {
+    format!("host:{host}")
+}
+
 impl document::Range for Store {
     fn get_fields(&self) -> Vec<RangeField> {
         vec![
@@ -84,8 +107,10 @@ impl document::MaterializedView for Store {
 
 impl document::FullText for Store {
     fn get_content(&self) -> Option<String> {
+        // Binary bodies aren't indexed, a lossy conversion would only add
+        // replacement characters to the index.
         if self.full_text {
-            Some(String::from_utf8_lossy(&self.response.body).to_string())
+            std::str::from_utf8(&self.response.body).ok().map(str::to_owned)
         } else {
             None
         }
@@ -98,6 +123,9 @@ impl DarkbirdManager {
         Ok(Self {
             cache: Arc::new(Storage::<String, Store>::open(options).await?),
             full_text,
+            locks: Arc::new(
+                (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+            ),
         })
     }
 
@@ -112,19 +140,51 @@ impl DarkbirdManager {
         );
         Self::new(ops, false).await
     }
+
+    /// Removes every entry whose response url has the provided host,
+    /// returning the number of entries removed.
+    pub async fn purge_host(&self, host: &str) -> Result<usize> {
+        let keys: Vec<String> = self
+            .cache
+            .lookup_by_tag(&host_tag(host))
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        for key in &keys {
+            self.delete(key).await?;
+        }
+        Ok(keys.len())
+    }
+
+    // Replacing an entry is a remove followed by an insert, which darkbird
+    // can't do atomically. Writes to the same key are serialized through one
+    // of a fixed set of locks picked by the key's hash, so unrelated keys
+    // rarely contend.
+    fn lock_for(&self, cache_key: &str) -> &Mutex<()> {
+        let mut hasher = DefaultHasher::new();
+        cache_key.hash(&mut hasher);
+        &self.locks[hasher.finish() as usize % self.locks.len()]
+    }
+
+    async fn remove(&self, cache_key: &str) -> Result<()> {
+        match self.cache.remove(cache_key.to_string()).await {
+            Ok(_) => {}
+            Err(e) => {
+                return Err(Box::new(error::Error::Delete(e.to_string())));
+            }
+        };
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
 impl CacheManager for DarkbirdManager {
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
         let store: Store = match self.cache.lookup(&cache_key.to_string()) {
             Some(d) => d.value().clone(),
             None => return Ok(None),
         };
-        Ok(Some((store.response, store.policy)))
+        Ok(Some(CacheEntry::new(store.response, store.policy)))
     }
 
     async fn put(
@@ -139,12 +199,13 @@ impl CacheManager for DarkbirdManager {
             cache_key: cache_key.clone(),
             full_text: self.full_text,
         };
+        let _guard = self.lock_for(&cache_key).lock().await;
         let mut exists = false;
         if self.cache.lookup(&cache_key.to_string()).is_some() {
             exists = true;
         }
         if exists {
-            self.delete(&cache_key).await?;
+            self.remove(&cache_key).await?;
         }
         match self.cache.insert(cache_key, data).await {
             Ok(_) => {}
@@ -156,13 +217,37 @@ impl CacheManager for DarkbirdManager {
     }
 
     async fn delete(&self, cache_key: &str) -> Result<()> {
-        match self.cache.remove(cache_key.to_string()).await {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(Box::new(error::Error::Delete(e.to_string())));
-            }
-        };
-        Ok(())
+        let _guard = self.lock_for(cache_key).lock().await;
+        self.remove(cache_key).await
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.lookup(&cache_key.to_string()).is_some())
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.stale_keys().await?;
+        for key in &stale {
+            self.delete(key).await?;
+        }
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(Some(self.cache.iter().map(|entry| entry.key().clone()).collect()))
+    }
+
+    // The `stale` view is only evaluated when an entry is inserted, so it
+    // misses entries that have gone stale since. Checking the policy of every
+    // entry finds those too.
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        Ok(self
+            .cache
+            .iter()
+            .filter(|entry| entry.value().policy.is_stale(now))
+            .map(|entry| entry.key().clone())
+            .collect())
     }
 }
 
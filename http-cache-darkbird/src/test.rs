@@ -1,4 +1,4 @@
-use crate::DarkbirdManager;
+use crate::{host_tag, DarkbirdManager};
 use std::sync::Arc;
 
 use http_cache::*;
@@ -39,27 +39,24 @@ async fn darkbird() -> Result<()> {
     );
     let url = Url::parse("http://example.com")?;
     let manager = Arc::new(DarkbirdManager::new_with_defaults().await?);
-    let http_res = HttpResponse {
-        body: TEST_BODY.to_vec(),
-        headers: Default::default(),
-        status: 200,
-        url: url.clone(),
-        version: HttpVersion::Http11,
-    };
     let req = http::Request::get("http://example.com").body(())?;
     let res = http::Response::builder().status(200).body(TEST_BODY.to_vec())?;
     let policy = CachePolicy::new(&req, &res);
+    let http_res = HttpResponse::try_from((res, url.clone()))?;
     manager
         .put(format!("{}:{}", GET, &url), http_res.clone(), policy.clone())
         .await?;
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_some());
-    assert_eq!(data.unwrap().0.body, TEST_BODY);
+    assert_eq!(data.unwrap().response.body, TEST_BODY);
+    assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+    assert!(!manager.contains(&format!("{}:{}/absent", GET, &url)).await?);
     assert!(manager.cache.lookup(&format!("{}:{}", GET, &url)).is_some());
     assert!(!manager.cache.lookup_by_tag(http_res.url.as_str()).is_empty());
     manager.delete(&format!("{}:{}", GET, &url)).await?;
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_none());
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
 
     let manager = Arc::new(
         DarkbirdManager::new(
@@ -151,6 +148,7 @@ async fn default_mode_with_options() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -192,3 +190,128 @@ async fn no_cache_mode() -> Result<()> {
     client.get(url).send().await?;
     Ok(())
 }
+
+#[tokio::test]
+async fn purge_host() -> Result<()> {
+    let manager = DarkbirdManager::new_with_defaults().await?;
+    let req = http::Request::get("http://example.com").body(())?;
+    let res = http::Response::builder().status(200).body(TEST_BODY.to_vec())?;
+    let policy = CachePolicy::new(&req, &res);
+    let urls = [
+        "http://purge.example.com/one",
+        "http://purge.example.com/two",
+        "http://keep.example.com/one",
+    ];
+    for url in urls {
+        let url = Url::parse(url)?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        manager
+            .put(format!("{}:{}", GET, &url), http_res, policy.clone())
+            .await?;
+    }
+    assert_eq!(
+        manager.cache.lookup_by_tag(&host_tag("purge.example.com")).len(),
+        2
+    );
+
+    // Only the entries for the purged host are removed
+    assert_eq!(manager.purge_host("purge.example.com").await?, 2);
+    assert!(manager
+        .cache
+        .lookup_by_tag(&host_tag("purge.example.com"))
+        .is_empty());
+    assert!(manager.get(&format!("{}:{}", GET, urls[0])).await?.is_none());
+    assert!(manager.get(&format!("{}:{}", GET, urls[1])).await?.is_none());
+    assert!(manager.get(&format!("{}:{}", GET, urls[2])).await?.is_some());
+    assert_eq!(manager.purge_host("purge.example.com").await?, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_puts_and_gets() -> Result<()> {
+    let manager = DarkbirdManager::new_with_defaults().await?;
+    let req = http::Request::get("http://example.com").body(())?;
+    let res = http::Response::builder().status(200).body(TEST_BODY.to_vec())?;
+    let policy = CachePolicy::new(&req, &res);
+
+    // Spawn many tasks hitting both their own keys and a shared key
+    let mut handles = Vec::new();
+    for task in 0..32 {
+        let manager = manager.clone();
+        let policy = policy.clone();
+        handles.push(tokio::spawn(async move {
+            for i in 0..25 {
+                let url =
+                    Url::parse(&format!("http://example.com/{task}/{i}"))?;
+                let http_res = HttpResponse {
+                    body: TEST_BODY.to_vec(),
+                    headers: Default::default(),
+                    status: 200,
+                    url: url.clone(),
+                    version: HttpVersion::Http11,
+                };
+                let cache_key = format!("{}:{}", GET, &url);
+                manager
+                    .put(cache_key.clone(), http_res.clone(), policy.clone())
+                    .await?;
+                manager
+                    .put(format!("{}:shared", GET), http_res, policy.clone())
+                    .await?;
+                assert!(manager.get(&cache_key).await?.is_some());
+                manager.get(&format!("{}:shared", GET)).await?;
+            }
+            Ok::<_, BoxError>(())
+        }));
+    }
+    for handle in handles {
+        handle.await??;
+    }
+    for task in 0..32 {
+        let url = Url::parse(&format!("http://example.com/{task}/24"))?;
+        assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn binary_body() -> Result<()> {
+    let body: &[u8] = &[0xff, 0xfe, 0x00, 0x80, 0xc3, 0x28];
+    let url = Url::parse("http://example.com/binary")?;
+    let manager = DarkbirdManager::new(
+        darkbird::Options::new(
+            ".",
+            "http-darkbird",
+            42,
+            darkbird::StorageType::RamCopies,
+            true,
+        ),
+        true,
+    )
+    .await?;
+    let req = http::Request::get("http://example.com/binary").body(())?;
+    let res = http::Response::builder().status(200).body(body.to_vec())?;
+    let policy = CachePolicy::new(&req, &res);
+    let http_res = HttpResponse::try_from((res, url.clone()))?;
+
+    // Full text indexing skips the body, but it is stored byte for byte
+    manager.put(format!("{}:{}", GET, &url), http_res, policy).await?;
+    let data = manager.get(&format!("{}:{}", GET, &url)).await?;
+    assert_eq!(data.unwrap().response.body, body);
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_stale() -> Result<()> {
+    let manager = DarkbirdManager::new_with_defaults().await?;
+    http_cache::test_util::put_fresh_and_stale(&manager).await?;
+    assert_eq!(manager.delete_stale().await?, 1);
+    assert!(manager.contains("GET:http://example.com/fresh").await?);
+    assert!(!manager.contains("GET:http://example.com/stale").await?);
+    Ok(())
+}
@@ -1,6 +1,6 @@
-use http_cache::{CacheManager, HttpResponse, Result};
+use http_cache::{CacheEntry, CacheManager, HttpResponse, Result};
 
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::SystemTime};
 
 use http_cache_semantics::CachePolicy;
 use moka::future::Cache;
@@ -41,15 +41,12 @@ impl MokaManager {
 
 #[async_trait::async_trait]
 impl CacheManager for MokaManager {
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
         let store: Store = match self.cache.get(cache_key).await {
             Some(d) => d,
             None => return Ok(None),
         };
-        Ok(Some((store.response, store.policy)))
+        Ok(Some(CacheEntry::new(store.response, store.policy)))
     }
 
     async fn put(
@@ -69,6 +66,33 @@ impl CacheManager for MokaManager {
         self.cache.run_pending_tasks().await;
         Ok(())
     }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.contains_key(cache_key))
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.stale_keys().await?;
+        for key in &stale {
+            self.cache.invalidate(key.as_str()).await;
+        }
+        self.cache.run_pending_tasks().await;
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(Some(self.cache.iter().map(|(key, _)| key.to_string()).collect()))
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        Ok(self
+            .cache
+            .iter()
+            .filter(|(_, store)| store.policy.is_stale(now))
+            .map(|(key, _)| key.to_string())
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -51,10 +51,13 @@ async fn moka() -> Result<()> {
         .await?;
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_some());
-    assert_eq!(data.unwrap().0.body, TEST_BODY);
+    assert_eq!(data.unwrap().response.body, TEST_BODY);
+    assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+    assert!(!manager.contains(&format!("{}:{}/absent", GET, &url)).await?);
     manager.delete(&format!("{}:{}", GET, &url)).await?;
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_none());
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
     Ok(())
 }
 
@@ -110,6 +113,7 @@ async fn default_mode_with_options() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -151,3 +155,13 @@ async fn no_cache_mode() -> Result<()> {
     client.get(url).send().await?;
     Ok(())
 }
+
+#[tokio::test]
+async fn delete_stale() -> Result<()> {
+    let manager = MokaManager::default();
+    http_cache::test_util::put_fresh_and_stale(&manager).await?;
+    assert_eq!(manager.delete_stale().await?, 1);
+    assert!(manager.contains("GET:http://example.com/fresh").await?);
+    assert!(!manager.contains("GET:http://example.com/stale").await?);
+    Ok(())
+}
@@ -1,12 +1,17 @@
-use http_cache::{CacheManager, HttpResponse, Result};
+use http_cache::{CacheEntry, CacheManager, HttpResponse, Result};
 
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::SystemTime};
 
 use http_cache_semantics::CachePolicy;
 use quick_cache::sync::Cache;
 use serde::{Deserialize, Serialize};
 
 /// Implements [`CacheManager`] with [`quick-cache`](https://github.com/arthurprs/quick-cache) as the backend.
+///
+/// Entries are evicted to stay within the cache's capacity. Stale entries are
+/// kept until then, since they can still be revalidated or served by
+/// `CacheMode::ForceCache`, `CacheMode::OnlyIfCached` and `max-stale`
+/// requests. [`CacheManager::delete_stale`] removes them on demand.
 #[derive(Clone)]
 pub struct QuickManager {
     /// The instance of `quick_cache::sync::Cache`
@@ -37,19 +42,21 @@ impl QuickManager {
     pub fn new(cache: Cache<String, Arc<Vec<u8>>>) -> Self {
         Self { cache: Arc::new(cache) }
     }
+
+    /// Create a new manager with a cache that holds up to `capacity` entries
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(Cache::new(capacity))
+    }
 }
 
 #[async_trait::async_trait]
 impl CacheManager for QuickManager {
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
         let store: Store = match self.cache.get(cache_key) {
             Some(d) => bincode::deserialize(&d)?,
             None => return Ok(None),
         };
-        Ok(Some((store.response, store.policy)))
+        Ok(Some(CacheEntry::new(store.response, store.policy)))
     }
 
     async fn put(
@@ -68,6 +75,34 @@ impl CacheManager for QuickManager {
         self.cache.remove(cache_key);
         Ok(())
     }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.contains_key(cache_key))
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.stale_keys().await?;
+        for key in &stale {
+            self.cache.remove(key);
+        }
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(Some(self.cache.iter().map(|(key, _)| key).collect()))
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        let mut stale = Vec::new();
+        for (key, bytes) in self.cache.iter() {
+            let store: Store = bincode::deserialize(&bytes)?;
+            if store.policy.is_stale(now) {
+                stale.push(key);
+            }
+        }
+        Ok(stale)
+    }
 }
 
 #[cfg(test)]
@@ -44,17 +44,23 @@ async fn quickcache() -> Result<()> {
         version: HttpVersion::Http11,
     };
     let req = http::Request::get("http://example.com").body(())?;
-    let res = http::Response::builder().status(200).body(TEST_BODY.to_vec())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .body(TEST_BODY.to_vec())?;
     let policy = CachePolicy::new(&req, &res);
     manager
         .put(format!("{}:{}", GET, &url), http_res.clone(), policy.clone())
         .await?;
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_some());
-    assert_eq!(data.unwrap().0.body, TEST_BODY);
+    assert_eq!(data.unwrap().response.body, TEST_BODY);
+    assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+    assert!(!manager.contains(&format!("{}:{}/absent", GET, &url)).await?);
     manager.delete(&format!("{}:{}", GET, &url)).await?;
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_none());
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
     Ok(())
 }
 
@@ -110,6 +116,7 @@ async fn default_mode_with_options() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -151,3 +158,127 @@ async fn no_cache_mode() -> Result<()> {
     client.get(url).send().await?;
     Ok(())
 }
+
+#[tokio::test]
+async fn delete_stale() -> Result<()> {
+    let manager = QuickManager::default();
+    http_cache::test_util::put_fresh_and_stale(&manager).await?;
+    assert_eq!(manager.delete_stale().await?, 1);
+    assert!(manager.contains("GET:http://example.com/fresh").await?);
+    assert!(!manager.contains("GET:http://example.com/stale").await?);
+    Ok(())
+}
+
+fn entry(
+    url: &Url,
+    cache_control: &str,
+) -> Result<(HttpResponse, CachePolicy)> {
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", cache_control)
+        .body(())?;
+    let policy = CachePolicy::new(&req, &res);
+    let mut headers = std::collections::HashMap::new();
+    headers.insert("cache-control".to_string(), cache_control.to_string());
+    let http_res = HttpResponse {
+        body: TEST_BODY.to_vec(),
+        headers,
+        status: 200,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+    };
+    Ok((http_res, policy))
+}
+
+#[tokio::test]
+async fn with_capacity() -> Result<()> {
+    let manager = QuickManager::with_capacity(5);
+    for i in 0..50 {
+        let url = Url::parse(&format!("http://example.com/{i}"))?;
+        let (res, policy) = entry(&url, CACHEABLE_PUBLIC)?;
+        manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+    }
+    // Entries were evicted to stay near the capacity
+    assert!(manager.cache.len() < 50);
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_kept_on_get() -> Result<()> {
+    let manager = QuickManager::default();
+    let url = Url::parse("http://example.com/stale")?;
+    let key = format!("{}:{}", GET, &url);
+    let (res, policy) = entry(&url, "max-age=0")?;
+    manager.put(key.clone(), res, policy).await?;
+
+    // A stale entry is still returned, even without validators
+    assert!(manager.get(&key).await?.is_some());
+    assert!(manager.contains(&key).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn force_cache_mode() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=0, public", TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = QuickManager::default();
+
+    // Construct reqwest client that prefers any cached response
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::ForceCache,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // The stale response without validators is served from cache
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_access() -> Result<()> {
+    let manager = QuickManager::with_capacity(1_000);
+    let mut tasks = Vec::new();
+    for task in 0..8 {
+        let manager = manager.clone();
+        tasks.push(tokio::spawn(async move {
+            for i in 0..100 {
+                let url =
+                    Url::parse(&format!("http://example.com/{task}/{i}"))?;
+                let key = format!("{}:{}", GET, &url);
+                let (res, policy) = entry(&url, CACHEABLE_PUBLIC)?;
+                manager.put(key.clone(), res, policy).await?;
+                let data = manager.get(&key).await?;
+                assert_eq!(data.unwrap().response.body, TEST_BODY);
+                // Read an entry written by another task, which may or may
+                // not have been written yet
+                let other = format!(
+                    "{}:http://example.com/{}/{i}",
+                    GET,
+                    (task + 1) % 8
+                );
+                manager.get(&other).await?;
+                if i % 10 == 0 {
+                    manager.delete(&key).await?;
+                }
+            }
+            Ok::<_, BoxError>(())
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap()?;
+    }
+    // Every entry that wasn't deleted is still readable
+    assert_eq!(manager.cache.len(), 8 * 90);
+    Ok(())
+}
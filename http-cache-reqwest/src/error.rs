@@ -11,3 +11,18 @@ impl fmt::Display for BadRequest {
 }
 
 impl std::error::Error for BadRequest {}
+
+/// Error type for a base url that can't have paths appended to it
+#[cfg(feature = "manager-http-api")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BadBaseUrl;
+
+#[cfg(feature = "manager-http-api")]
+impl fmt::Display for BadBaseUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Base url cannot be a base, it must be a hierarchical url")
+    }
+}
+
+#[cfg(feature = "manager-http-api")]
+impl std::error::Error for BadBaseUrl {}
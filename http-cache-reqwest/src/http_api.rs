@@ -0,0 +1,140 @@
+use crate::error::BadBaseUrl;
+
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_cache::{CacheEntry, CacheManager, HttpResponse, HttpVersion, Result};
+use http_cache_semantics::CachePolicy;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+/// Implements [`CacheManager`] by proxying to a cache service behind an HTTP
+/// API, so the cache can be shared between processes and languages.
+///
+/// Every entry lives at the base url with the cache key appended as a single,
+/// percent-encoded path segment:
+/// - `GET` returns the entry, or `404 Not Found` when there is none
+/// - `PUT` stores the entry sent in the request body
+/// - `DELETE` removes the entry
+///
+/// Entries are sent and received as JSON objects with a `response` and a
+/// `policy` field, where the response body is a base64 encoded string. Any
+/// other unsuccessful status is returned as an error.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-http-api")))]
+#[derive(Debug, Clone)]
+pub struct HttpApiManager {
+    client: Client,
+    base_url: Url,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: Response,
+    policy: CachePolicy,
+}
+
+// An `HttpResponse` whose body is sent as base64 rather than as an array of
+// numbers.
+#[derive(Debug, Deserialize, Serialize)]
+struct Response {
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    body: Vec<u8>,
+    headers: HashMap<String, String>,
+    status: u16,
+    url: Url,
+    version: HttpVersion,
+}
+
+impl From<HttpResponse> for Response {
+    fn from(res: HttpResponse) -> Self {
+        Self {
+            body: res.body,
+            headers: res.headers,
+            status: res.status,
+            url: res.url,
+            version: res.version,
+        }
+    }
+}
+
+impl From<Response> for HttpResponse {
+    fn from(res: Response) -> Self {
+        Self {
+            body: res.body,
+            headers: res.headers,
+            status: res.status,
+            url: res.url,
+            version: res.version,
+        }
+    }
+}
+
+fn to_base64<S: Serializer>(
+    body: &[u8],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(body))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+}
+
+impl HttpApiManager {
+    /// Create a new manager sending its requests to the cache service at the
+    /// base url with the provided client
+    pub fn new(client: Client, base_url: Url) -> Self {
+        Self { client, base_url }
+    }
+
+    fn entry_url(&self, cache_key: &str) -> Result<Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| BadBaseUrl)?
+            .pop_if_empty()
+            .push(cache_key);
+        Ok(url)
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for HttpApiManager {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        let res = self.client.get(self.entry_url(cache_key)?).send().await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = res.error_for_status()?.bytes().await?;
+        let store: Store = serde_json::from_slice(&body)?;
+        Ok(Some(CacheEntry::new(store.response.into(), store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response: response.into(), policy };
+        self.client
+            .put(self.entry_url(&cache_key)?)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&data)?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(data.response.into())
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let res = self.client.delete(self.entry_url(cache_key)?).send().await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+        }
+        Ok(())
+    }
+}
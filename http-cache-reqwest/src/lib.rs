@@ -46,17 +46,55 @@
 //!     .send()
 //!     .await?;
 //! ```
+//!
+//! `CacheMode::ForceCache` makes a single request cache first, serving
+//! whatever is cached without revalidating it, and fetching on a miss.
+//!
+//! ## Revalidating in the background
+//!
+//! With [`HttpCacheOptions::serve_stale_after`], a revalidation that takes too
+//! long is finished on a spawned task with the [`BackgroundClient`] attached to
+//! the request, while the stale response is served. With
+//! [`HttpCacheOptions::background_revalidate_always`], fresh hits are also
+//! revalidated with it, after the cached response is returned.
+//!
+//! ```no_run
+//! use reqwest_middleware::Extension;
+//!
+//! let background = ClientBuilder::new(Client::new()).build();
+//! let client = ClientBuilder::new(Client::new())
+//!     .with_init(Extension(BackgroundClient(background)))
+//!     .with(Cache(HttpCache {
+//!         mode: CacheMode::Default,
+//!         manager: CACacheManager::default(),
+//!         options: HttpCacheOptions {
+//!             serve_stale_after: Some(Duration::from_millis(200)),
+//!             ..Default::default()
+//!         },
+//!     }))
+//!     .build();
+//! ```
 mod error;
+#[cfg(feature = "manager-http-api")]
+mod http_api;
 
 use anyhow::anyhow;
 
 pub use error::BadRequest;
 
+#[cfg(feature = "manager-http-api")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-http-api")))]
+pub use error::BadBaseUrl;
+
+#[cfg(feature = "manager-http-api")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-http-api")))]
+pub use http_api::HttpApiManager;
+
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     str::FromStr,
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 pub use http::request::Parts;
@@ -65,21 +103,25 @@ use http::{
     Extensions, HeaderValue, Method,
 };
 use http_cache::{
-    BoxError, HitOrMiss, Middleware, Result, XCACHE, XCACHELOOKUP,
+    append_header, BackgroundFuture, BoxError, HitOrMiss, Middleware, Result,
+    XCACHE, XCACHELOOKUP,
 };
 use http_cache_semantics::CachePolicy;
 use reqwest::{Request, Response, ResponseBuilderExt};
-use reqwest_middleware::{Error, Next};
+use reqwest_middleware::{ClientWithMiddleware, Error, Next};
 use url::Url;
 
 pub use http_cache::{
-    CacheManager, CacheMode, CacheOptions, HttpCache, HttpCacheOptions,
-    HttpResponse,
+    CacheControl, CacheEntry, CacheKeyFormat, CacheManager, CacheMode,
+    CacheOptions, EffectiveCacheMode, EntryMetadata, FailedRequests,
+    GlobPattern, HostIndex, HttpCache, HttpCacheBuilder, HttpCacheOptions,
+    HttpCacheOptionsBuilder, HttpResponse, KeyBypass, RevalidationCounter,
+    RevalidationReport, StaleReason, StoreOutcome, StoredRequest, VariantsLock,
 };
 
 #[cfg(feature = "manager-cacache")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
-pub use http_cache::CACacheManager;
+pub use http_cache::{CACacheManager, DedupCACacheManager};
 
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
@@ -89,6 +131,32 @@ pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
 #[derive(Debug)]
 pub struct Cache<T: CacheManager>(pub HttpCache<T>);
 
+impl<T: CacheManager> Cache<T> {
+    /// Create a new middleware using the provided mode and manager, with the
+    /// default options
+    pub fn new(mode: CacheMode, manager: T) -> Self {
+        Self::with_options(mode, manager, HttpCacheOptions::default())
+    }
+
+    /// Create a new middleware using the provided mode, manager and options
+    pub fn with_options(
+        mode: CacheMode,
+        manager: T,
+        options: HttpCacheOptions,
+    ) -> Self {
+        Self(HttpCache { mode, manager, options })
+    }
+}
+
+/// The client revalidations that outlast
+/// [`HttpCacheOptions::serve_stale_after`], and those started for hits with
+/// [`HttpCacheOptions::background_revalidate_always`], are finished with, on
+/// a spawned tokio task. Attach it to requests as an extension, for instance with
+/// [`reqwest_middleware::Extension`]. It should be built without the cache,
+/// otherwise the revalidation is looked up in the cache again.
+#[derive(Clone, Debug)]
+pub struct BackgroundClient(pub ClientWithMiddleware);
+
 /// Implements ['Middleware'] for reqwest
 pub(crate) struct ReqwestMiddleware<'a> {
     pub req: Request,
@@ -144,7 +212,11 @@ impl Middleware for ReqwestMiddleware<'_> {
             Ok(r) => r,
             Err(e) => return Err(Box::new(e)),
         };
-        Ok(converted.into_parts().0)
+        let mut parts = converted.into_parts().0;
+        // Make extensions attached through reqwest-middleware visible to
+        // closures like `cache_key` that receive the request parts.
+        parts.extensions.extend(self.extensions.clone());
+        Ok(parts)
     }
     fn url(&self) -> Result<Url> {
         Ok(self.req.url().clone())
@@ -159,31 +231,56 @@ impl Middleware for ReqwestMiddleware<'_> {
             Ok(r) => r,
             Err(e) => return Err(Box::new(e)),
         };
-        let mut headers = HashMap::new();
-        for header in res.headers() {
-            headers.insert(
-                header.0.as_str().to_owned(),
-                header.1.to_str()?.to_owned(),
-            );
-        }
-        let url = res.url().clone();
-        let status = res.status().into();
-        let version = res.version();
-        let body: Vec<u8> = match res.bytes().await {
-            Ok(b) => b,
-            Err(e) => return Err(Box::new(e)),
-        }
-        .to_vec();
-        Ok(HttpResponse {
-            body,
-            headers,
-            status,
-            url,
-            version: version.try_into()?,
-        })
+        to_http_response(res).await
+    }
+    fn sleep(&self, duration: Duration) -> Option<BackgroundFuture<()>> {
+        Some(Box::pin(tokio::time::sleep(duration)))
+    }
+    fn background_fetch(
+        &self,
+    ) -> Result<Option<BackgroundFuture<Result<HttpResponse>>>> {
+        let Some(BackgroundClient(client)) =
+            self.extensions.get::<BackgroundClient>().cloned()
+        else {
+            return Ok(None);
+        };
+        let copied_req = clone_req(&self.req)?;
+        Ok(Some(Box::pin(async move {
+            let res = match client.execute(copied_req).await {
+                Ok(r) => r,
+                Err(e) => return Err(BoxError::from(e)),
+            };
+            to_http_response(res).await
+        })))
+    }
+    fn spawn(&self, future: BackgroundFuture<()>) {
+        tokio::spawn(future);
     }
 }
 
+// Converts a reqwest [`Response`] to an [`HttpResponse`]
+async fn to_http_response(res: Response) -> Result<HttpResponse> {
+    let mut headers = HashMap::new();
+    for header in res.headers() {
+        append_header(&mut headers, header.0.as_str(), header.1.to_str()?);
+    }
+    let url = res.url().clone();
+    let status = res.status().into();
+    let version = res.version();
+    let body: Vec<u8> = match res.bytes().await {
+        Ok(b) => b,
+        Err(e) => return Err(Box::new(e)),
+    }
+    .to_vec();
+    Ok(HttpResponse {
+        body,
+        headers,
+        status,
+        url,
+        version: version.try_into()?,
+    })
+}
+
 // Converts an [`HttpResponse`] to a reqwest [`Response`]
 fn convert_response(response: HttpResponse) -> anyhow::Result<Response> {
     let mut ret_res = http::Response::builder()
@@ -204,12 +301,21 @@ fn bad_header(e: reqwest::header::InvalidHeaderValue) -> Error {
     Error::Middleware(anyhow!(e))
 }
 
+// Errors raised by the rest of the middleware chain or while reading the
+// response body are passed through as-is rather than wrapped, so callers can
+// still inspect the underlying reqwest error.
 fn from_box_error(e: BoxError) -> Error {
-    Error::Middleware(anyhow!(e))
+    match e.downcast::<Error>() {
+        Ok(e) => *e,
+        Err(e) => match e.downcast::<reqwest::Error>() {
+            Ok(e) => Error::Reqwest(*e),
+            Err(e) => Error::Middleware(anyhow!(e)),
+        },
+    }
 }
 
 #[async_trait::async_trait]
-impl<T: CacheManager> reqwest_middleware::Middleware for Cache<T> {
+impl<T: CacheManager + Clone> reqwest_middleware::Middleware for Cache<T> {
     async fn handle(
         &self,
         req: Request,
@@ -217,13 +323,31 @@ impl<T: CacheManager> reqwest_middleware::Middleware for Cache<T> {
         next: Next<'_>,
     ) -> std::result::Result<Response, Error> {
         let mut middleware = ReqwestMiddleware { req, next, extensions };
-        if self
+        let mode = self
             .0
-            .can_cache_request(&middleware)
+            .cache_mode(&middleware)
+            .map_err(|e| Error::Middleware(anyhow!(e)))?;
+        if let Some(cache_key) = self
+            .0
+            .cacheable_key(&middleware)
             .map_err(|e| Error::Middleware(anyhow!(e)))?
         {
-            let res = self.0.run(middleware).await.map_err(from_box_error)?;
-            let converted = convert_response(res)?;
+            let parts = middleware.parts().map_err(from_box_error)?;
+            let res = self
+                .0
+                .run_with_key(middleware, cache_key.clone())
+                .await
+                .map_err(from_box_error)?;
+            let mut converted = convert_response(res)?;
+            converted.extensions_mut().insert(EffectiveCacheMode(mode));
+            if let Some(metadata) = self
+                .0
+                .entry_metadata(&cache_key, &parts)
+                .await
+                .map_err(from_box_error)?
+            {
+                converted.extensions_mut().insert(EntryMetadata(metadata));
+            }
             Ok(converted)
         } else {
             self.0
@@ -240,6 +364,7 @@ impl<T: CacheManager> reqwest_middleware::Middleware for Cache<T> {
                     .map_err(bad_header)?;
             res.headers_mut().insert(XCACHE, miss.clone());
             res.headers_mut().insert(XCACHELOOKUP, miss);
+            res.extensions_mut().insert(EffectiveCacheMode(mode));
             Ok(res)
         }
     }
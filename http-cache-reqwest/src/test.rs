@@ -1,9 +1,9 @@
-use crate::{error, Cache};
-use std::sync::Arc;
+use crate::{error, BackgroundClient, Cache};
+use std::{sync::Arc, time::Duration};
 
 use http_cache::*;
 use reqwest::Client;
-use reqwest_middleware::ClientBuilder;
+use reqwest_middleware::{ClientBuilder, Extension};
 use url::Url;
 use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
 
@@ -94,6 +94,7 @@ async fn default_mode_with_options() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -158,6 +159,7 @@ async fn reload_mode() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -175,6 +177,87 @@ async fn reload_mode() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn force_cache_mode() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=0, public", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that serves from the cache first
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::ForceCache,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // The stale response is served without being revalidated
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // A request without a cached response goes to the network
+    let res = client.get(format!("{url}other")).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let data = manager
+        .get(&format!("{}:{}", GET, &Url::parse(&format!("{url}other"))?))
+        .await?;
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn expires_without_cache_control() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let now = std::time::SystemTime::now();
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("date", httpdate::fmt_http_date(now).as_str())
+                .insert_header(
+                    "expires",
+                    httpdate::fmt_http_date(now + Duration::from_secs(3600))
+                        .as_str(),
+                )
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Freshness is derived from Expires minus Date
+    let data =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    let ttl = data.policy.time_to_live(std::time::SystemTime::now());
+    assert!((3598..=3600).contains(&ttl.as_secs()));
+
+    // Hot pass is answered from the cache without contacting the origin
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
 #[tokio::test]
 async fn custom_cache_key() -> Result<()> {
     let mock_server = MockServer::start().await;
@@ -196,6 +279,7 @@ async fn custom_cache_key() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -212,6 +296,40 @@ async fn custom_cache_key() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn cache_key_built_once() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .cache_key(move |req| {
+                            counted.fetch_add(1, Ordering::SeqCst);
+                            req.uri.to_string()
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The key closure runs once for a miss that's stored, and once for a hit
+    for (expected_status, expected_calls) in [("MISS", 1), ("HIT", 2)] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), expected_status);
+        assert_eq!(calls.load(Ordering::SeqCst), expected_calls);
+    }
+    Ok(())
+}
+
 #[tokio::test]
 async fn custom_cache_mode_fn() -> Result<()> {
     let mock_server = MockServer::start().await;
@@ -237,6 +355,7 @@ async fn custom_cache_mode_fn() -> Result<()> {
                 })),
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -278,6 +397,7 @@ async fn override_cache_mode() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -300,6 +420,86 @@ async fn override_cache_mode() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn force_cache_extension() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("public, max-age=0", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache with a response that's stale right away
+    client.get(&url).send().await?;
+
+    // The extension serves the stale response without revalidating it
+    let res =
+        client.get(&url).with_extension(CacheMode::ForceCache).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // Other requests still revalidate it
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    Ok(())
+}
+
+#[tokio::test]
+async fn effective_cache_mode() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+
+    // Construct reqwest client that doesn't cache the private paths
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .cache_mode_fn(|req| {
+                            if req.uri.path().starts_with("/private") {
+                                CacheMode::NoStore
+                            } else {
+                                CacheMode::Default
+                            }
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    let url = format!("{}/private", &mock_server.uri());
+    let res = client.get(url).send().await?;
+    assert_eq!(
+        res.extensions().get::<EffectiveCacheMode>(),
+        Some(&EffectiveCacheMode(CacheMode::NoStore))
+    );
+
+    let url = format!("{}/public", &mock_server.uri());
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(
+        res.extensions().get::<EffectiveCacheMode>(),
+        Some(&EffectiveCacheMode(CacheMode::Default))
+    );
+
+    // Per request overrides take precedence over cache_mode_fn
+    let res = client.get(url).with_extension(CacheMode::Reload).send().await?;
+    assert_eq!(
+        res.extensions().get::<EffectiveCacheMode>(),
+        Some(&EffectiveCacheMode(CacheMode::Reload))
+    );
+    Ok(())
+}
+
 #[tokio::test]
 async fn no_status_headers() -> Result<()> {
     let mock_server = MockServer::start().await;
@@ -319,6 +519,7 @@ async fn no_status_headers() -> Result<()> {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: false,
+                ..Default::default()
             },
         }))
         .build();
@@ -370,6 +571,7 @@ async fn cache_bust() -> Result<()> {
                     },
                 )),
                 cache_status_headers: true,
+                ..Default::default()
             },
         }))
         .build();
@@ -423,3 +625,3604 @@ async fn delete_after_non_get_head_method_request() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn proxy_revalidate_shared() -> Result<()> {
+    // Not pooled, so the server is shut down when dropped
+    let mock_server = MockServer::builder().start().await;
+    let m =
+        build_mock("public, max-age=0, proxy-revalidate", TEST_BODY, 200, 2);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults (shared cache)
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Try to load cached object
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // max-stale must not allow the stale entry to be served
+    client.get(url.clone()).header("cache-control", "max-stale").send().await?;
+
+    // Take the origin offline, the stale entry must not be served
+    drop(mock_guard);
+    drop(mock_server);
+    assert!(client.get(url).send().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn proxy_revalidate_private() -> Result<()> {
+    // Not pooled, so the server is shut down when dropped
+    let mock_server = MockServer::builder().start().await;
+    let m =
+        build_mock("public, max-age=0, proxy-revalidate", TEST_BODY, 200, 1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with a private cache
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                cache_options: Some(CacheOptions {
+                    shared: false,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // proxy-revalidate doesn't apply, so max-stale allows serving the stale entry
+    let res = client
+        .get(url.clone())
+        .header("cache-control", "max-stale")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // Take the origin offline, the stale entry is served with a warning
+    drop(mock_guard);
+    drop(mock_server);
+    let res = client.get(url).send().await?;
+    assert!(res.headers().get("warning").unwrap().to_str()?.starts_with("111"));
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn answer_client_conditionals() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"abc\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client answering conditional requests from cache
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                answer_client_conditionals: true,
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Matching validator is answered with a 304 from cache
+    let res = client
+        .get(url.clone())
+        .header("if-none-match", "\"abc\"")
+        .send()
+        .await?;
+    assert_eq!(res.status(), 304);
+    assert_eq!(res.headers().get("etag").unwrap(), "\"abc\"");
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.bytes().await?.is_empty());
+
+    // Validator that doesn't match gets the full cached response
+    let res = client.get(url).header("if-none-match", "\"xyz\"").send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_preflight() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method("OPTIONS"))
+        .respond_with(
+            ResponseTemplate::new(204)
+                .insert_header("access-control-allow-origin", "*")
+                .insert_header("access-control-max-age", "600"),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client caching preflight responses
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                cache_preflight: true,
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Cold pass to load cache
+    let res =
+        client.request(reqwest::Method::OPTIONS, url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // Try to load cached object
+    let data = manager.get(&format!("OPTIONS:{}", &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass is answered from cache within the max-age
+    let res = client.request(reqwest::Method::OPTIONS, url).send().await?;
+    assert_eq!(res.status(), 204);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.headers().get("access-control-max-age").unwrap(), "600");
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_unknown_length() -> Result<()> {
+    for (cache_unknown_length, expected) in [(true, 1), (false, 2)] {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("transfer-encoding", "chunked")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expected);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct reqwest client with unknown length caching configured
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    cache_unknown_length,
+                    ..Default::default()
+                },
+            }))
+            .build();
+
+        // Chunked response is only cached when the flag is enabled
+        let res = client.get(url.clone()).send().await?;
+        assert!(res.headers().get("content-length").is_none());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert_eq!(data.is_some(), cache_unknown_length);
+
+        let res = client.get(url).send().await?;
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn response_cache_control_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client downgrading public responses to private
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                response_cache_control_fn: Some(Arc::new(|res| {
+                    let mut cache_control = res.cache_control();
+                    cache_control.public = false;
+                    cache_control.private = true;
+                    Some(cache_control)
+                })),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Private responses aren't stored by a shared cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(
+        res.headers().get("cache-control").unwrap(),
+        "max-age=86400, private"
+    );
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    // To verify our endpoint receives the request rather than a cache hit
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    Ok(())
+}
+
+#[tokio::test]
+async fn honor_surrogate_control() -> Result<()> {
+    for (shared, expected) in [(true, 1), (false, 2)] {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", "no-store")
+                    .insert_header("surrogate-control", "max-age=3600")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expected);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct reqwest client honoring Surrogate-Control
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    cache_options: Some(CacheOptions {
+                        shared,
+                        ..Default::default()
+                    }),
+                    honor_surrogate_control: true,
+                    ..Default::default()
+                },
+            }))
+            .build();
+
+        // Surrogate-Control only takes precedence for a shared cache
+        let res = client.get(url.clone()).send().await?;
+        assert!(res.headers().get("surrogate-control").is_none());
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert_eq!(data.is_some(), shared);
+
+        // The header is stripped from responses served from cache too
+        let res = client.get(url).send().await?;
+        assert!(res.headers().get("surrogate-control").is_none());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn set_cookie() -> Result<()> {
+    for (shared, cache_set_cookie, stored) in
+        [(true, false, false), (true, true, true), (false, false, true)]
+    {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("set-cookie", "session=abc")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct reqwest client with the cache sharing configured
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    cache_options: Some(CacheOptions {
+                        shared,
+                        ..Default::default()
+                    }),
+                    cache_set_cookie,
+                    ..Default::default()
+                },
+            }))
+            .build();
+
+        // Shared caches only store Set-Cookie responses when allowed to
+        client.get(url.clone()).send().await?;
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert_eq!(data.is_some(), stored);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_key_from_extensions() -> Result<()> {
+    #[derive(Debug, Clone)]
+    struct TenantId(&'static str);
+
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with a cache key including the tenant id
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                cache_key: Some(Arc::new(|req: &http::request::Parts| {
+                    let tenant = req
+                        .extensions
+                        .get::<TenantId>()
+                        .map_or("none", |tenant| tenant.0);
+                    format!("{}:{}:{}", tenant, req.method, req.uri)
+                })),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Each tenant gets its own entry, and then a cache hit
+    for tenant in ["a", "b", "a", "b"] {
+        client.get(url.clone()).with_extension(TenantId(tenant)).send().await?;
+    }
+    for tenant in ["a", "b"] {
+        let data = manager
+            .get(&format!("{}:{}:{}", tenant, GET, &Url::parse(&url)?))
+            .await?;
+        assert!(data.is_some());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_cache_ttl() -> Result<()> {
+    // Not pooled, so the server is shut down when dropped
+    let mock_server = MockServer::builder().start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, b"error", 500, 1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client remembering failed requests
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                error_cache_ttl: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Rapid retries of a failing request only hit the origin once
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.status(), 500);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let res = client.get(url).send().await?;
+    assert_eq!(res.status(), 500);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, &b"error"[..]);
+    drop(mock_guard);
+
+    // Retries of a network error fail fast with a gateway timeout
+    let url = format!("{}/offline", &mock_server.uri());
+    drop(mock_server);
+    assert!(client.get(url.clone()).send().await.is_err());
+    let res = client.get(url).send().await?;
+    assert_eq!(res.status(), 504);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // The failures are remembered without storing anything in the manager
+    assert_eq!(manager.len().await?, Some(0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn binary_body() -> Result<()> {
+    let body: &[u8] = &[0xff, 0xfe, 0x00, 0x80, 0xc3, 0x28];
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, body, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.bytes().await?, body);
+
+    // Hot pass returns the invalid UTF-8 body byte for byte
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, body);
+    Ok(())
+}
+
+#[tokio::test]
+async fn truncated_body() -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Promise more body than is sent, then drop the connection
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf).await;
+        let _ = stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                cache-control: max-age=86400, public\r\n\
+                content-length: 100\r\n\r\ntest",
+            )
+            .await;
+    });
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // The body read error is surfaced as a reqwest error
+    let err = client.get(url.clone()).send().await.unwrap_err();
+    assert!(matches!(err, reqwest_middleware::Error::Reqwest(_)));
+
+    // Nothing was stored for the truncated response
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn builder() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // The builder produces the same cache as the struct literal
+    let cache = HttpCache::builder(manager.clone())
+        .mode(CacheMode::ForceCache)
+        .options(
+            HttpCacheOptions::builder().cache_status_headers(false).build(),
+        )
+        .build();
+    let literal = HttpCache {
+        mode: CacheMode::ForceCache,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            cache_status_headers: false,
+            ..Default::default()
+        },
+    };
+    assert_eq!(cache.mode, literal.mode);
+    assert_eq!(
+        format!("{:?}", cache.options),
+        format!("{:?}", literal.options)
+    );
+
+    let client = ClientBuilder::new(Client::new()).with(Cache(cache)).build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert!(res.headers().get(XCACHE).is_none());
+
+    // Hot pass is answered from the cache
+    let res = client.get(url).send().await?;
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn path_rules() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let _static_guard = mock_server
+        .register_as_scoped(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::path("/api/v1/static/logo.png"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", CACHEABLE_PUBLIC)
+                        .set_body_bytes(TEST_BODY),
+                )
+                .expect(1),
+        )
+        .await;
+    let _users_guard = mock_server
+        .register_as_scoped(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::path("/api/v1/users"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", CACHEABLE_PUBLIC)
+                        .set_body_bytes(TEST_BODY),
+                )
+                .expect(2),
+        )
+        .await;
+    let static_url = format!("{}/api/v1/static/logo.png", &mock_server.uri());
+    let users_url = format!("{}/api/v1/users", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Only cache the static paths
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .mode(CacheMode::NoStore)
+                .options(
+                    HttpCacheOptions::builder()
+                        .path_rule("/api/v1/static/*", CacheMode::Default)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The matching path is cached and answered from cache
+    client.get(static_url.clone()).send().await?;
+    let res = client.get(static_url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // The non-matching path falls back to NoStore and always hits the origin
+    client.get(users_url.clone()).send().await?;
+    let res = client.get(users_url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let data =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&users_url)?)).await?;
+    assert!(data.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+mod metrics_recorder {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName,
+        Metadata, Recorder, SharedString, Unit,
+    };
+
+    #[derive(Debug, Default)]
+    pub(crate) struct Count(AtomicU64);
+
+    impl CounterFn for Count {
+        fn increment(&self, value: u64) {
+            self.0.fetch_add(value, Ordering::Relaxed);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.fetch_max(value, Ordering::Relaxed);
+        }
+    }
+
+    impl HistogramFn for Count {
+        fn record(&self, _value: f64) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts the calls made to each metric, keyed by name and mode label
+    #[derive(Debug, Default)]
+    pub(crate) struct TestRecorder {
+        metrics: Mutex<HashMap<(String, String), Arc<Count>>>,
+    }
+
+    impl TestRecorder {
+        pub(crate) fn get(&self, name: &str, mode: &str) -> u64 {
+            self.metrics
+                .lock()
+                .unwrap()
+                .get(&(name.to_string(), mode.to_string()))
+                .map_or(0, |count| count.0.load(Ordering::Relaxed))
+        }
+
+        fn count(&self, key: &Key) -> Arc<Count> {
+            let mode = key
+                .labels()
+                .find(|label| label.key() == "mode")
+                .map(|label| label.value().to_string())
+                .unwrap_or_default();
+            self.metrics
+                .lock()
+                .unwrap()
+                .entry((key.name().to_string(), mode))
+                .or_default()
+                .clone()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(
+            &self,
+            _: KeyName,
+            _: Option<Unit>,
+            _: SharedString,
+        ) {
+        }
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {
+        }
+
+        fn describe_histogram(
+            &self,
+            _: KeyName,
+            _: Option<Unit>,
+            _: SharedString,
+        ) {
+        }
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.count(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.count(key))
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn metrics() -> Result<()> {
+    let recorder = metrics_recorder::TestRecorder::default();
+    let runtime =
+        tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    metrics::with_local_recorder(&recorder, || {
+        runtime.block_on(async {
+            let mock_server = MockServer::start().await;
+            let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+            let _mock_guard = mock_server.register_as_scoped(m).await;
+            let url = format!("{}/", &mock_server.uri());
+
+            // Construct reqwest client with cache defaults
+            let client = ClientBuilder::new(Client::new())
+                .with(Cache(HttpCache {
+                    mode: CacheMode::Default,
+                    manager: MokaManager::default(),
+                    options: HttpCacheOptions::default(),
+                }))
+                .build();
+
+            // Cold pass misses, hot pass hits
+            client.get(url.clone()).send().await?;
+            client.get(url.clone()).send().await?;
+
+            // Forcing validation of the cached response revalidates it
+            client.get(url).with_extension(CacheMode::NoCache).send().await?;
+            Ok::<_, BoxError>(())
+        })
+    })?;
+    assert_eq!(recorder.get("http_cache.misses", "default"), 1);
+    assert_eq!(recorder.get("http_cache.hits", "default"), 1);
+    assert_eq!(recorder.get("http_cache.revalidations", "no-cache"), 1);
+    assert_eq!(recorder.get("http_cache.lookup_latency", "default"), 2);
+    assert_eq!(recorder.get("http_cache.lookup_latency", "no-cache"), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn revalidation_request_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    // Registered first so it takes precedence once the header is present
+    let revalidate = Mock::given(method(GET))
+        .and(wiremock::matchers::header("authorization", "Bearer token"))
+        .and(wiremock::matchers::header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(304).insert_header("etag", "\"v1\""),
+        )
+        .expect(1);
+    let _revalidate_guard = mock_server.register_as_scoped(revalidate).await;
+    let initial = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-cache, public")
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _initial_guard = mock_server.register_as_scoped(initial).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Add an authorization header to revalidation requests only
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .revalidation_request_fn(|builder| {
+                            if let Some(headers) = builder.headers_mut() {
+                                headers.insert(
+                                    "authorization",
+                                    "Bearer token".parse().unwrap(),
+                                );
+                            }
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Revalidation carries the header and is answered with a 304
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_status_header() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let fresh = Mock::given(method(GET))
+        .and(wiremock::matchers::path("/fresh"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("cache-status", "origin-cdn; hit")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _fresh_guard = mock_server.register_as_scoped(fresh).await;
+    let stale = Mock::given(method(GET))
+        .and(wiremock::matchers::path("/stale"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-cache, public")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _stale_guard = mock_server.register_as_scoped(stale).await;
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with the cache-status header enabled
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder().add_cache_status(true).build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass forwards the request and stores the response, after the
+    // upstream entry
+    let url = format!("{}/fresh", &mock_server.uri());
+    let res = client.get(url.clone()).send().await?;
+    let status = res.headers().get(CACHESTATUS).unwrap().to_str()?;
+    let (status, ttl) = status.rsplit_once("; ttl=").unwrap();
+    assert_eq!(
+        status,
+        "origin-cdn; hit, http-cache; fwd=uri-miss; fwd-status=200; stored"
+    );
+    assert!((86399..=86400).contains(&ttl.parse::<u64>()?));
+
+    // Hot pass is a hit with the remaining freshness lifetime
+    let res = client.get(url).send().await?;
+    let status = res.headers().get(CACHESTATUS).unwrap().to_str()?;
+    assert!(status.starts_with("origin-cdn; hit, http-cache; hit; ttl="));
+    assert!(!status.contains("fwd="));
+
+    // A stale response is revalidated and replaced by the origin's response
+    let url = format!("{}/stale", &mock_server.uri());
+    client.get(url.clone()).send().await?;
+    let res = client.get(url).send().await?;
+    assert_eq!(
+        res.headers().get(CACHESTATUS).unwrap(),
+        "http-cache; fwd=stale; fwd-status=200; stored; ttl=0"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn serve_stale_after() -> Result<()> {
+    for background in [true, false] {
+        let mock_server = MockServer::start().await;
+        let initial = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", "max-age=0, public")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .up_to_n_times(1);
+        let _initial_guard = mock_server.register_as_scoped(initial).await;
+        let slow = Mock::given(method(GET)).respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(b"updated".to_vec())
+                .set_delay(Duration::from_millis(500)),
+        );
+        let _slow_guard = mock_server.register_as_scoped(slow).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        // Construct reqwest client that gives up on slow revalidations, and
+        // finishes them in the background when it has a client to do so
+        let mut builder = ClientBuilder::new(Client::new());
+        if background {
+            builder = builder.with_init(Extension(BackgroundClient(
+                ClientBuilder::new(Client::new()).build(),
+            )));
+        }
+        let client = builder
+            .with(Cache(
+                HttpCache::builder(MokaManager::default())
+                    .options(
+                        HttpCacheOptions::builder()
+                            .serve_stale_after(Duration::from_millis(100))
+                            .build(),
+                    )
+                    .build(),
+            ))
+            .build();
+
+        // Cold pass to load cache with a response that is immediately stale
+        client.get(url.clone()).send().await?;
+
+        let start = std::time::Instant::now();
+        let res = client.get(url.clone()).send().await?;
+        if !background {
+            // Without a background client the origin is waited for
+            assert!(start.elapsed() >= Duration::from_millis(500));
+            assert_eq!(res.bytes().await?, "updated");
+            continue;
+        }
+
+        // The stale response is served rather than waiting on the origin
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert!(res
+            .headers()
+            .get("warning")
+            .unwrap()
+            .to_str()?
+            .starts_with("110"));
+        assert_eq!(res.bytes().await?, TEST_BODY);
+
+        // The revalidation finishes in the background and updates the entry
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        let res = client.get(url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert!(res.headers().get("warning").is_none());
+        assert_eq!(res.bytes().await?, "updated");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn background_revalidate_always() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let initial = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .up_to_n_times(1);
+    let _initial_guard = mock_server.register_as_scoped(initial).await;
+    let revalidation = Mock::given(method(GET))
+        .and(wiremock::matchers::header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(304)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"v1\"")
+                .set_delay(Duration::from_millis(200)),
+        );
+    let _revalidation_guard =
+        mock_server.register_as_scoped(revalidation).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client that revalidates every hit in the background
+    let client = ClientBuilder::new(Client::new())
+        .with_init(Extension(BackgroundClient(
+            ClientBuilder::new(Client::new()).build(),
+        )))
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .background_revalidate_always(true)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Each hit serves the cached copy without waiting on the origin, and
+    // starts exactly one revalidation
+    for hit in 1..=3 {
+        let start = std::time::Instant::now();
+        let res = client.get(url.clone()).send().await?;
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.bytes().await?, TEST_BODY);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let requests = mock_server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1 + hit);
+        assert_eq!(requests[hit].headers["if-none-match"], "\"v1\"");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn constructors() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    let cache = Cache::new(CacheMode::ForceCache, manager.clone());
+    assert_eq!(cache.0.mode, CacheMode::ForceCache);
+    assert_eq!(
+        format!("{:?}", cache.0.options),
+        format!("{:?}", HttpCacheOptions::default())
+    );
+
+    let options =
+        HttpCacheOptions { cache_status_headers: false, ..Default::default() };
+    let cache =
+        Cache::with_options(CacheMode::Default, manager.clone(), options);
+    assert_eq!(cache.0.mode, CacheMode::Default);
+    assert!(!cache.0.options.cache_status_headers);
+
+    let client = ClientBuilder::new(Client::new()).with(cache).build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert!(res.headers().get(XCACHE).is_none());
+
+    // Hot pass is answered from the cache
+    let res = client.get(url).send().await?;
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn vary_normalize_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("vary", "accept-encoding")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Sort the codings so their order doesn't select a different variant
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .vary_normalize_fn(|name, value| {
+                            if name == "x-trace" {
+                                return Some(value.to_uppercase());
+                            }
+                            if name != "accept-encoding" {
+                                return None;
+                            }
+                            let mut codings: Vec<&str> =
+                                value.split(',').map(str::trim).collect();
+                            codings.sort_unstable();
+                            Some(codings.join(", "))
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client
+        .get(url.clone())
+        .header("accept-encoding", "gzip, deflate")
+        .header("x-trace", "abc")
+        .send()
+        .await?;
+
+    // Headers the response doesn't vary on are sent as they are
+    let requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(requests[0].headers.get("x-trace").unwrap(), "abc");
+
+    // The reordered value matches the cached response, as do the codings
+    // sent on separate lines
+    let res = client
+        .get(url.clone())
+        .header("accept-encoding", "deflate, gzip")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    let res = client
+        .get(url)
+        .header("accept-encoding", "gzip")
+        .header("accept-encoding", "deflate")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn entry_metadata_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let options = HttpCacheOptions::builder()
+        .entry_metadata_fn(|parts, res| {
+            std::collections::HashMap::from([
+                ("trace-id".to_string(), "abc123".to_string()),
+                (
+                    "source".to_string(),
+                    format!("{} {}", parts.method, res.status),
+                ),
+            ])
+        })
+        .build();
+    let cache = HttpCache::builder(manager.clone()).options(options).build();
+
+    // Construct reqwest client that stores metadata with each entry
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Hot pass is answered from the cache, which still has the metadata
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    let EntryMetadata(metadata) = res.extensions().get().cloned().unwrap();
+    assert_eq!(metadata["trace-id"], "abc123");
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let entry = cache.get_entry(&cache_key).await?.unwrap();
+    assert_eq!(entry.response.body, TEST_BODY);
+    assert_eq!(entry.metadata["trace-id"], "abc123");
+    assert_eq!(entry.metadata["source"], "GET 200");
+
+    // The metadata is removed along with the entry
+    client.delete(url).send().await?;
+    assert!(cache.get_entry(&cache_key).await?.is_none());
+    assert_eq!(manager.len().await?, Some(0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn honor_pragma() -> Result<()> {
+    // (honor_pragma, request cache-control, expected origin requests)
+    for (honor_pragma, cache_control, expect) in
+        [(true, None, 2), (false, None, 1), (true, Some("max-age=3600"), 1)]
+    {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, expect);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(MokaManager::default())
+                    .options(
+                        HttpCacheOptions::builder()
+                            .honor_pragma(honor_pragma)
+                            .build(),
+                    )
+                    .build(),
+            ))
+            .build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // Pragma forces revalidation only when honored and no Cache-Control
+        // header is present
+        let mut req = client.get(url).header("pragma", "no-cache");
+        if let Some(cache_control) = cache_control {
+            req = req.header("cache-control", cache_control);
+        }
+        let res = req.send().await?;
+        let expected = if expect == 2 { "MISS" } else { "HIT" };
+        assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn accept_revalidation_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let initial = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-cache, public")
+                .set_body_bytes(TEST_BODY),
+        )
+        .up_to_n_times(1);
+    let _initial_guard = mock_server.register_as_scoped(initial).await;
+    let error_page = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-cache, public")
+                .set_body_bytes(b"maintenance".to_vec()),
+        )
+        .expect(2);
+    let _error_guard = mock_server.register_as_scoped(error_page).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Reject revalidated responses that are maintenance pages
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .accept_revalidation_fn(|res| {
+                            res.body != b"maintenance"
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Each revalidation is rejected and the original response is served
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert!(res
+            .headers()
+            .get("warning")
+            .unwrap()
+            .to_str()?
+            .starts_with("111"));
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+
+    // The original entry is still the one in the cache
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert_eq!(data.unwrap().response.body, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn store_request() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("vary", "accept-language")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/?page=1", &mock_server.uri());
+    let manager = MokaManager::default();
+    let options = HttpCacheOptions::builder().store_request(true).build();
+    let cache = HttpCache::builder(manager.clone()).options(options).build();
+
+    // Construct reqwest client that stores the request with each entry
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    // Cold pass to load cache
+    client
+        .get(url.clone())
+        .header("accept-language", "en")
+        .header("accept-language", "fr")
+        .header("x-trace", "abc")
+        .send()
+        .await?;
+
+    // Hot pass is answered from the cache, which still has the request
+    let res = client
+        .get(url.clone())
+        .header("accept-language", "en")
+        .header("accept-language", "fr")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    let entry = cache
+        .get_entry(&format!("{}:{}", GET, &Url::parse(&url)?))
+        .await?
+        .unwrap();
+    let request = entry.request.unwrap();
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.uri, url);
+
+    // Only the headers the response varies on are kept
+    assert_eq!(
+        request.headers,
+        std::collections::HashMap::from([(
+            "accept-language".to_string(),
+            vec!["en".to_string(), "fr".to_string()]
+        )])
+    );
+    let parts = request.parts()?;
+    assert_eq!(parts.headers.get_all("accept-language").iter().count(), 2);
+    assert!(entry.metadata.is_empty());
+
+    // A touched entry keeps matching the requests it varies on
+    cache
+        .touch(
+            &format!("{}:{}", GET, &Url::parse(&url)?),
+            Duration::from_secs(60),
+        )
+        .await?;
+    let res = client
+        .get(url.clone())
+        .header("accept-language", "en")
+        .header("accept-language", "fr")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn override_must_revalidate_on_error() -> Result<()> {
+    // Reserve a port, then close it so the origin is unreachable
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+    drop(listener);
+
+    for enabled in [false, true] {
+        let manager = MokaManager::default();
+        let cache_control = "max-age=0, must-revalidate, public";
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header("cache-control", cache_control)
+            .body(())?;
+        manager
+            .put(
+                format!("{}:{}", GET, &url),
+                HttpResponse {
+                    body: TEST_BODY.to_vec(),
+                    headers: [(
+                        "cache-control".to_string(),
+                        cache_control.to_string(),
+                    )]
+                    .into(),
+                    status: 200,
+                    url: url.clone(),
+                    version: HttpVersion::Http11,
+                },
+                http_cache_semantics::CachePolicy::new(&req, &res),
+            )
+            .await?;
+
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager)
+                    .options(
+                        HttpCacheOptions::builder()
+                            .override_must_revalidate_on_error(enabled)
+                            .build(),
+                    )
+                    .build(),
+            ))
+            .build();
+
+        let res = client.get(url.clone()).send().await;
+        if enabled {
+            // The stale entry is served since the origin is unreachable
+            let res = res?;
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+            assert!(res
+                .headers()
+                .get("warning")
+                .unwrap()
+                .to_str()?
+                .starts_with("111"));
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        } else {
+            // The spec requires the error to be returned
+            assert!(res.is_err());
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn on_store() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (path, cache_control, body) in [
+        ("/cacheable", CACHEABLE_PUBLIC, TEST_BODY),
+        ("/uncacheable", "no-store", TEST_BODY),
+        ("/oversized", CACHEABLE_PUBLIC, b"oversized".as_slice()),
+    ] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::path(path))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("cache-control", cache_control)
+                            .set_body_bytes(body),
+                    ),
+            )
+            .await;
+    }
+    let outcomes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = outcomes.clone();
+
+    // Construct reqwest client that reports the result of every store
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .max_body_size(TEST_BODY.len())
+                        .on_store(move |key, outcome| {
+                            recorded
+                                .lock()
+                                .unwrap()
+                                .push((key.to_string(), outcome));
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    for path in ["cacheable", "uncacheable", "oversized"] {
+        client.get(format!("{}/{path}", mock_server.uri())).send().await?;
+    }
+    let key = |path: &str| format!("{}:{}/{path}", GET, mock_server.uri());
+    assert_eq!(
+        *outcomes.lock().unwrap(),
+        [
+            (key("cacheable"), StoreOutcome::Stored),
+            (key("uncacheable"), StoreOutcome::SkippedUncacheable),
+            (key("oversized"), StoreOutcome::SkippedTooLarge),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_store_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (path, cacheable) in [("/marked", "true"), ("/unmarked", "false")] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::path(path))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("cache-control", CACHEABLE_PUBLIC)
+                            .insert_header("x-cacheable", cacheable)
+                            .set_body_bytes(TEST_BODY),
+                    ),
+            )
+            .await;
+    }
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that only stores marked responses
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .should_store_fn(|_, res| {
+                            res.headers
+                                .get("x-cacheable")
+                                .is_some_and(|val| val == "true")
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    for (path, stored) in [("marked", true), ("unmarked", false)] {
+        // The response is served either way
+        let url = format!("{}/{path}", mock_server.uri());
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.bytes().await?, TEST_BODY);
+
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert_eq!(data.is_some(), stored);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn response_date_override() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=100, public", TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let date = std::time::SystemTime::now() - Duration::from_secs(50);
+
+    // Construct reqwest client that treats responses as received earlier
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .response_date_override(date)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // The just fetched response is already half expired
+    let data =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    let ttl = data.policy.time_to_live(std::time::SystemTime::now());
+    assert!((49..=50).contains(&ttl.as_secs()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_variants() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for encoding in ["gzip", "identity"] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::header(
+                        "accept-encoding",
+                        encoding,
+                    ))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("cache-control", CACHEABLE_PUBLIC)
+                            .insert_header("vary", "Accept-Encoding")
+                            .set_body_bytes(encoding.as_bytes()),
+                    )
+                    .expect(1),
+            )
+            .await;
+    }
+    let url = format!("{}/", &mock_server.uri());
+    let cache = HttpCache::builder(MokaManager::default())
+        .options(HttpCacheOptions::builder().cache_variants(true).build())
+        .build();
+
+    // Construct reqwest client that stores each representation separately
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    // Cold passes load both representations at once, hot passes select by
+    // header
+    let send = |encoding: &'static str| {
+        client.get(url.clone()).header("accept-encoding", encoding).send()
+    };
+    let (gzip, identity) = tokio::join!(send("gzip"), send("identity"));
+    for (res, encoding) in [(gzip?, "gzip"), (identity?, "identity")] {
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.bytes().await?, encoding.as_bytes());
+    }
+    for encoding in ["gzip", "identity"] {
+        let res = send(encoding).await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.bytes().await?, encoding.as_bytes());
+    }
+
+    let variants =
+        cache.variants(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert_eq!(variants.len(), 2);
+    // The representations are stored once each, next to their index
+    assert_eq!(cache.manager.len().await?, Some(3));
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_variants_vary_change() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (vary, cache_control, body) in [
+        ("Accept-Language", "max-age=0, public", "en"),
+        ("Accept-Encoding", CACHEABLE_PUBLIC, "gzip"),
+    ] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("cache-control", cache_control)
+                            .insert_header("vary", vary)
+                            .set_body_bytes(body.as_bytes()),
+                    )
+                    .up_to_n_times(1),
+            )
+            .await;
+    }
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let cache = HttpCache::builder(MokaManager::default())
+        .options(HttpCacheOptions::builder().cache_variants(true).build())
+        .build();
+
+    // Construct reqwest client that stores each representation separately
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    // The first representation varies by language and is immediately stale
+    let res =
+        client.get(url.clone()).header("accept-language", "en").send().await?;
+    assert_eq!(res.bytes().await?, "en".as_bytes());
+    assert_eq!(cache.variants(&cache_key).await?.len(), 1);
+
+    // Revalidation returns a representation that varies by encoding instead
+    let res = client
+        .get(url.clone())
+        .header("accept-language", "en")
+        .header("accept-encoding", "gzip")
+        .send()
+        .await?;
+    assert_eq!(res.bytes().await?, "gzip".as_bytes());
+
+    // The old representation is superseded rather than kept alongside
+    let variants = cache.variants(&cache_key).await?;
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].response.body, b"gzip");
+    let res = client
+        .get(url)
+        .header("accept-language", "fr")
+        .header("accept-encoding", "gzip")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, "gzip".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn max_entries_per_host() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    mock_server
+        .register(
+            Mock::given(method(GET)).respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_bytes(TEST_BODY),
+            ),
+        )
+        .await;
+    let port = mock_server.address().port();
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that stores at most two entries per host
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder().max_entries_per_host(2).build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    let url = |host: &str, path: &str| format!("http://{host}:{port}/{path}");
+    for (host, path) in [
+        ("localhost", "a"),
+        ("127.0.0.1", "a"),
+        ("127.0.0.1", "b"),
+        ("127.0.0.1", "c"),
+    ] {
+        client.get(url(host, path)).send().await?;
+    }
+
+    // Only the oldest entry of the host over the cap was evicted
+    for (host, path, stored) in [
+        ("localhost", "a", true),
+        ("127.0.0.1", "a", false),
+        ("127.0.0.1", "b", true),
+        ("127.0.0.1", "c", true),
+    ] {
+        let data = manager
+            .get(&format!("{}:{}", GET, &Url::parse(&url(host, path))?))
+            .await?;
+        assert_eq!(data.is_some(), stored);
+    }
+    // The hosts are tracked in memory rather than in the manager
+    assert_eq!(manager.len().await?, Some(3));
+    Ok(())
+}
+
+#[tokio::test]
+async fn optional_cache_key() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that only caches requests with a tenant id
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .optional_cache_key(|parts| {
+                            let tenant = parts.headers.get("x-tenant")?;
+                            Some(format!(
+                                "{}:{}",
+                                tenant.to_str().ok()?,
+                                parts.uri
+                            ))
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Requests with a tenant id are cached under the custom key
+    for _ in 0..2 {
+        client.get(url.clone()).header("x-tenant", "acme").send().await?;
+    }
+    let data = manager.get(&format!("acme:{url}")).await?;
+    assert!(data.is_some());
+
+    // Requests without one always go to the origin
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn max_background_revalidations() -> Result<()> {
+    // Past the limit only responses that allow it are served stale, the
+    // others wait for their turn
+    for (cache_control, expected_requests) in [
+        ("max-age=0, public, stale-while-revalidate=60", 3),
+        ("max-age=0, public", 6),
+    ] {
+        let mock_server = MockServer::start().await;
+        let response = ResponseTemplate::new(200)
+            .insert_header("cache-control", cache_control)
+            .set_body_bytes(TEST_BODY);
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .respond_with(response.clone())
+                    .up_to_n_times(1),
+            )
+            .await;
+        mock_server
+            .register(
+                Mock::given(method(GET)).respond_with(
+                    response.set_delay(Duration::from_millis(500)),
+                ),
+            )
+            .await;
+        let url = format!("{}/", &mock_server.uri());
+        let options =
+            HttpCacheOptions::builder().max_background_revalidations(2).build();
+        let revalidations = options.revalidations.clone();
+
+        // Construct reqwest client that revalidates at most two responses at
+        // once in the background
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(MokaManager::default())
+                    .options(options)
+                    .build(),
+            ))
+            .build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // A burst of stale hits, while the revalidations in flight are
+        // sampled
+        let sampled = revalidations.clone();
+        let sampler = tokio::spawn(async move {
+            let mut most_in_flight = 0;
+            for _ in 0..200 {
+                most_in_flight = most_in_flight.max(sampled.in_flight());
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            most_in_flight
+        });
+        let mut handles = Vec::new();
+        for _ in 0..5 {
+            let client = client.clone();
+            let url = url.clone();
+            handles.push(tokio::spawn(async move {
+                let res = client.get(url).send().await?;
+                Ok::<_, BoxError>(res.bytes().await?)
+            }));
+        }
+        for handle in handles {
+            assert_eq!(handle.await.unwrap()?, TEST_BODY);
+        }
+        let requests = mock_server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), expected_requests);
+        assert_eq!(revalidations.in_flight(), 0);
+        assert_eq!(sampler.await.unwrap(), 2);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn shared_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=86400, private", TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that acts as a shared cache for marked requests
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .shared_fn(|parts| {
+                            parts.headers.contains_key("x-shared")
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Private responses are rejected in shared mode
+    for _ in 0..2 {
+        let res =
+            client.get(url.clone()).header("x-shared", "1").send().await?;
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    // The same response is stored in private mode
+    client.get(url.clone()).send().await?;
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn cacheable_methods() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let head_mock = Mock::given(method("HEAD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC),
+        )
+        .expect(2);
+    let _head_guard = mock_server.register_as_scoped(head_mock).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that only caches GET requests
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .cacheable_methods([http::Method::GET])
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // HEAD requests bypass the cache
+    for _ in 0..2 {
+        let res = client.head(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    let data =
+        manager.get(&format!("{}:{}", "HEAD", &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    // GET requests are still cached
+    client.get(url.clone()).send().await?;
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn http3_version_round_trip() -> Result<()> {
+    // The origin is never contacted since the cached entry is fresh
+    let url = Url::parse("http://127.0.0.1:1/")?;
+    let manager = MokaManager::default();
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .version(http::Version::HTTP_3)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res =
+        HttpResponse::try_from((res.map(|_| TEST_BODY.to_vec()), url.clone()))?;
+    manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+    let data = manager.get(&format!("{}:{}", GET, &url)).await?.unwrap();
+    assert_eq!(data.response.version, HttpVersion::H3);
+
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::builder(manager).build()))
+        .build();
+
+    // The version of the cached response survives the conversion
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.version(), http::Version::HTTP_3);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunked_response_with_trailers() -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Serve a single chunked response that ends with a trailer
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buf = [0; 1024];
+        let mut read = 0;
+        while !buf[..read].windows(4).any(|w| w == b"\r\n\r\n") {
+            read += stream.read(&mut buf[read..]).await?;
+        }
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                cache-control: max-age=86400, public\r\n\
+                transfer-encoding: chunked\r\n\
+                trailer: x-checksum\r\n\
+                \r\n\
+                4\r\ntest\r\n\
+                0\r\nx-checksum: abc\r\n\r\n",
+            )
+            .await?;
+        Ok::<_, std::io::Error>(())
+    });
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::builder(manager.clone()).build()))
+        .build();
+
+    // The body is cached without the trailer
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    server.await??;
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    let data = data.unwrap();
+    assert_eq!(data.response.body, TEST_BODY);
+    assert!(!data.response.headers.contains_key("x-checksum"));
+
+    // Cache hits are served even though the origin is gone
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn default_max_age() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (path, cache_control, expected) in
+        [("/plain", None, 1), ("/no-store", Some("no-store"), 2)]
+    {
+        let mut template = ResponseTemplate::new(200).set_body_bytes(TEST_BODY);
+        if let Some(cache_control) = cache_control {
+            template = template.insert_header("cache-control", cache_control);
+        }
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::path(path))
+                    .respond_with(template)
+                    .expect(expected),
+            )
+            .await;
+    }
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that caches for a minute by default
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .default_max_age(Duration::from_secs(60))
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // A response without caching headers is cached for the default
+    let url = format!("{}/plain", mock_server.uri());
+    client.get(url.clone()).send().await?;
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    let ttl = data.unwrap().policy.time_to_live(std::time::SystemTime::now());
+    assert!((59..=60).contains(&ttl.as_secs()));
+
+    // A no-store response still isn't cached
+    let url = format!("{}/no-store", mock_server.uri());
+    client.get(url.clone()).send().await?;
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "content-encoding")]
+#[tokio::test]
+async fn content_encoding() -> Result<()> {
+    use std::io::Write;
+
+    let url = Url::parse("http://127.0.0.1:1/")?;
+    let manager = MokaManager::default();
+    let mut encoder =
+        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(TEST_BODY)?;
+    let compressed = encoder.finish()?;
+
+    // Store a gzip encoded entry that is still fresh
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .header("content-encoding", "gzip")
+        .header("content-length", compressed.len())
+        .header("etag", "\"abc\"")
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res =
+        HttpResponse::try_from((res.map(|_| compressed.clone()), url.clone()))?;
+    manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::builder(manager).build()))
+        .build();
+
+    // Clients accepting gzip, or any encoding, receive the stored bytes as
+    // they are
+    for accept_encoding in [None, Some("gzip, br"), Some("gzip;q=0.5, *;q=0")] {
+        let mut req = client.get(url.clone());
+        if let Some(accept_encoding) = accept_encoding {
+            req = req.header("accept-encoding", accept_encoding);
+        }
+        let res = req.send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.headers().get("content-encoding").unwrap(), "gzip");
+        assert_eq!(res.headers().get("etag").unwrap(), "\"abc\"");
+        assert_eq!(res.bytes().await?, compressed);
+    }
+
+    // Other clients receive the decompressed body under a weak ETag
+    for accept_encoding in ["br", "gzip;q=0", "gzip;q=0, *", "identity"] {
+        let res = client
+            .get(url.clone())
+            .header("accept-encoding", accept_encoding)
+            .send()
+            .await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(res.headers().get("content-length").unwrap(), "4");
+        assert_eq!(res.headers().get("etag").unwrap(), "W/\"abc\"");
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "content-encoding")]
+#[tokio::test]
+async fn compression_offload() -> Result<()> {
+    use std::{
+        io::Write,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    let url = Url::parse("http://127.0.0.1:1/")?;
+    let manager = MokaManager::default();
+    let body = vec![0; 8 * 1024 * 1024];
+    let mut encoder =
+        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(&body)?;
+    let compressed = encoder.finish()?;
+
+    // Store a large gzip encoded entry that is still fresh
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .header("content-encoding", "gzip")
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res = HttpResponse::try_from((res.map(|_| compressed), url.clone()))?;
+    manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+
+    // Count how often another task on this single threaded runtime gets to
+    // run while the body is decompressed inline and on a blocking thread
+    let mut ticks = Vec::new();
+    for threshold in [usize::MAX, 0] {
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager.clone())
+                    .options(
+                        HttpCacheOptions::builder()
+                            .compression_offload_threshold(threshold)
+                            .build(),
+                    )
+                    .build(),
+            ))
+            .build();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let counter = counter.clone();
+            async move {
+                loop {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        let res = client
+            .get(url.clone())
+            .header("accept-encoding", "br")
+            .send()
+            .await?;
+        ticker.abort();
+        assert_eq!(res.bytes().await?, body);
+        ticks.push(counter.load(Ordering::Relaxed));
+    }
+    assert!(ticks[1] > ticks[0], "{ticks:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn bypass_header() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that bypasses the cache for health checks
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .bypass_header(
+                            http::HeaderName::from_static("x-bypass-cache"),
+                            Some("1"),
+                        )
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Bypassed responses aren't stored
+    let res =
+        client.get(url.clone()).header("x-bypass-cache", "1").send().await?;
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    assert!(manager.get(&cache_key).await?.is_none());
+
+    // Bypassed requests go to the origin without touching the stored entry
+    client.get(url.clone()).send().await?;
+    let res =
+        client.get(url.clone()).header("x-bypass-cache", "1").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    assert!(manager.get(&cache_key).await?.is_some());
+
+    // Other values of the header are served from cache
+    let res = client.get(url).header("x-bypass-cache", "0").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn minimal_headers() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let etag = "\"abc\"";
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::header("if-none-match", etag))
+                .respond_with(
+                    ResponseTemplate::new(304)
+                        .insert_header("cache-control", "no-cache")
+                        .insert_header("etag", etag),
+                )
+                .expect(1),
+        )
+        .await;
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", "no-cache")
+                        .insert_header("etag", etag)
+                        .insert_header("content-type", "text/plain")
+                        .insert_header("x-request-id", "123")
+                        .set_body_bytes(TEST_BODY),
+                )
+                .expect(1),
+        )
+        .await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that only stores the headers it needs
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder().minimal_headers(true).build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The response is returned in full but stored without unrelated headers
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get("x-request-id").unwrap(), "123");
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    let headers = data.unwrap().response.headers;
+    assert!(!headers.contains_key("x-request-id"));
+    assert_eq!(headers.get("etag").unwrap(), etag);
+    assert_eq!(headers.get("content-type").unwrap(), "text/plain");
+
+    // Revalidation still works with the reduced header set
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("x-request-id").is_none());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn on_stale_served() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (status, expected) in [(200, 1), (500, 1)] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .respond_with(
+                        ResponseTemplate::new(status)
+                            .insert_header(
+                                "cache-control",
+                                "max-age=0, stale-if-error=60, public",
+                            )
+                            .set_body_bytes(TEST_BODY),
+                    )
+                    .up_to_n_times(1)
+                    .expect(expected),
+            )
+            .await;
+    }
+    let url = format!("{}/", &mock_server.uri());
+    let served = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reported = served.clone();
+
+    // Construct reqwest client that records the stale responses it serves
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .on_stale_served(move |key, reason| {
+                            reported
+                                .lock()
+                                .unwrap()
+                                .push((key.to_string(), reason));
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    client.get(url.clone()).send().await?;
+    assert!(served.lock().unwrap().is_empty());
+
+    // The origin fails, so the stale response is served instead
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    assert_eq!(
+        *served.lock().unwrap(),
+        [(format!("{}:{}", GET, &Url::parse(&url)?), StaleReason::IfError)]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn vary_hash_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    mock_server
+        .register(
+            Mock::given(method(GET)).respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("vary", "Cookie")
+                    .set_body_bytes(TEST_BODY),
+            ),
+        )
+        .await;
+    let url = format!("{}/", &mock_server.uri());
+    let cookie = "session=secret-token";
+
+    for vary_hash_fn in [false, true] {
+        let manager = MokaManager::default();
+        let mut options = HttpCacheOptions::builder().cache_variants(true);
+        if vary_hash_fn {
+            options =
+                options.vary_hash_fn(|value| format!("len{}", value.len()));
+        }
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager.clone())
+                    .options(options.build())
+                    .build(),
+            ))
+            .build();
+
+        // The representation is still selected by the cookie
+        for expected_status in ["MISS", "HIT"] {
+            let res =
+                client.get(url.clone()).header("cookie", cookie).send().await?;
+            assert_eq!(res.headers().get(XCACHE).unwrap(), expected_status);
+        }
+
+        // The cookie never appears in the stored keys
+        let keys: Vec<String> =
+            manager.cache.iter().map(|(key, _)| key.to_string()).collect();
+        assert!(keys.iter().all(|key| !key.contains("secret-token")));
+        let variant = keys.iter().find(|key| key.starts_with("variant:"));
+        if vary_hash_fn {
+            assert!(variant.unwrap().ends_with("cookie=len20"));
+        } else {
+            // The default is the value's SHA-256 digest
+            assert!(variant.unwrap().ends_with(
+                "cookie=07762f855a33bdd136ae166fbdb821d02983adad63158a828c2e993648599405"
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn partial_content() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    // Partial responses are served for any range request
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::header_exists("range"))
+                .respond_with(
+                    ResponseTemplate::new(206)
+                        .insert_header("cache-control", CACHEABLE_PUBLIC)
+                        .insert_header("content-range", "bytes 0-1/4")
+                        .insert_header("etag", "\"v1\"")
+                        .set_body_bytes(&TEST_BODY[..2]),
+                ),
+        )
+        .await;
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::path("/revalidated"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", "no-cache")
+                        .insert_header("etag", "\"v1\"")
+                        .set_body_bytes(TEST_BODY),
+                ),
+        )
+        .await;
+    let manager = MokaManager::default();
+
+    // A partial response isn't stored as the full resource, whatever the mode
+    for mode in [CacheMode::Default, CacheMode::IgnoreRules] {
+        let url = format!("{}/", &mock_server.uri());
+        let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode,
+                manager: manager.clone(),
+                options: HttpCacheOptions::default(),
+            }))
+            .build();
+        let res = client.get(url).header("range", "bytes=0-1").send().await?;
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.bytes().await?, &TEST_BODY[..2]);
+        assert!(manager.get(&cache_key).await?.is_none());
+    }
+
+    // A partial response to a revalidation doesn't replace the stored one
+    let url = format!("{}/revalidated", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+    client.get(url.clone()).send().await?;
+    let res = client.get(url).header("range", "bytes=0-1").send().await?;
+    assert_eq!(res.status(), 206);
+    assert_eq!(res.bytes().await?, &TEST_BODY[..2]);
+    let cached = manager.get(&cache_key).await?.unwrap().response;
+    assert_eq!(cached.status, 200);
+    assert_eq!(cached.body, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_cache_retry_after() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let retried_at = std::time::SystemTime::now() - Duration::from_secs(10);
+    for (path, retry_after) in [
+        ("/seconds", "1".to_string()),
+        ("/date", httpdate::fmt_http_date(retried_at)),
+    ] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::path(path))
+                    .respond_with(
+                        ResponseTemplate::new(503)
+                            .insert_header("retry-after", retry_after.as_str())
+                            .set_body_bytes(b"unavailable"),
+                    )
+                    .expect(2),
+            )
+            .await;
+    }
+    let url = format!("{}/seconds", &mock_server.uri());
+
+    // Construct reqwest client remembering failed requests for a minute
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions {
+                error_cache_ttl: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Retries are short-circuited for as long as Retry-After asks
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.status(), 503);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // A date that has already passed allows retrying right away
+    let url = format!("{}/date", &mock_server.uri());
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn policy_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("no-cache", TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client that keeps every response fresh for a year
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .policy_fn(|parts, res, options| {
+                            let res = http::Response::builder()
+                                .status(res.status)
+                                .header("cache-control", "max-age=31536000")
+                                .body(())
+                                .unwrap();
+                            http_cache_semantics::CachePolicy::new_options(
+                                parts,
+                                &res,
+                                std::time::SystemTime::now(),
+                                *options,
+                            )
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The no-cache response is served from cache without revalidation
+    client.get(url.clone()).send().await?;
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-http-api")]
+#[tokio::test]
+async fn http_api_manager() -> Result<()> {
+    use crate::HttpApiManager;
+    use wiremock::matchers::path;
+
+    let cache_server = MockServer::start().await;
+    let base_url = Url::parse(&format!("{}/cache/", &cache_server.uri()))?;
+    let manager = HttpApiManager::new(Client::new(), base_url);
+    let key = "GET:http://example.com/a?b=c";
+    let entry_path = "/cache/GET:http:%2F%2Fexample.com%2Fa%3Fb=c";
+
+    // Missing entries are reported as such
+    let missing = cache_server
+        .register_as_scoped(
+            Mock::given(method(GET))
+                .and(path(entry_path))
+                .respond_with(ResponseTemplate::new(404)),
+        )
+        .await;
+    assert!(manager.get(key).await?.is_none());
+    drop(missing);
+
+    // Entries are sent to the service as JSON under the percent-encoded key
+    cache_server
+        .register(
+            Mock::given(method("PUT"))
+                .and(path(entry_path))
+                .respond_with(ResponseTemplate::new(204))
+                .expect(1),
+        )
+        .await;
+    let url = Url::parse("http://example.com/a?b=c")?;
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .body(TEST_BODY.to_vec())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res = HttpResponse::try_from((res, url))?;
+    manager.put(key.to_string(), res, policy).await?;
+    let requests = cache_server.received_requests().await.unwrap();
+    let stored = requests.last().unwrap();
+    assert_eq!(stored.headers.get("content-type").unwrap(), "application/json");
+    let json: serde_json::Value = serde_json::from_slice(&stored.body)?;
+    assert_eq!(json["response"]["body"], "dGVzdA==");
+
+    // The stored JSON is read back into an entry
+    cache_server
+        .register(Mock::given(method(GET)).and(path(entry_path)).respond_with(
+            ResponseTemplate::new(200).set_body_bytes(stored.body.clone()),
+        ))
+        .await;
+    let entry = manager.get(key).await?.unwrap();
+    assert_eq!(entry.response.body, TEST_BODY);
+    assert!(!entry.is_stale(std::time::SystemTime::now()));
+
+    // Deleting succeeds whether or not the service had the entry
+    cache_server
+        .register(
+            Mock::given(method("DELETE"))
+                .and(path(entry_path))
+                .respond_with(ResponseTemplate::new(404)),
+        )
+        .await;
+    manager.delete(key).await?;
+
+    // Other failures of the service are errors
+    let failing = HttpApiManager::new(
+        Client::new(),
+        Url::parse(&format!("{}/down/", &cache_server.uri()))?,
+    );
+    cache_server
+        .register(
+            Mock::given(method(GET))
+                .and(path("/down/key"))
+                .respond_with(ResponseTemplate::new(500)),
+        )
+        .await;
+    assert!(failing.get("key").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn priority_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    mock_server
+        .register(
+            Mock::given(method(GET)).respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_bytes(TEST_BODY),
+            ),
+        )
+        .await;
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that stores at most two entries per host and
+    // prefers to keep the important ones
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .max_entries_per_host(2)
+                        .priority_fn(|req, _| {
+                            if req.uri.path().starts_with("/important") {
+                                10
+                            } else {
+                                0
+                            }
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    let url = |path: &str| format!("{}/{path}", &mock_server.uri());
+    for path in ["important", "a", "b", "c"] {
+        client.get(url(path)).send().await?;
+    }
+
+    // The low priority entries were evicted first, despite being newer
+    for (path, stored) in
+        [("important", true), ("a", false), ("b", false), ("c", true)]
+    {
+        let data = manager
+            .get(&format!("{}:{}", GET, &Url::parse(&url(path))?))
+            .await?;
+        assert_eq!(data.is_some(), stored);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn key_by_final_url() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let final_url = format!("{}/final", &mock_server.uri());
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::path("/start"))
+                .respond_with(
+                    ResponseTemplate::new(302)
+                        .insert_header("location", final_url.as_str()),
+                ),
+        )
+        .await;
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .and(wiremock::matchers::path("/final"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", CACHEABLE_PUBLIC)
+                        .set_body_bytes(TEST_BODY),
+                )
+                .expect(2),
+        )
+        .await;
+    mock_server
+        .register(
+            Mock::given(method("HEAD"))
+                .and(wiremock::matchers::path("/start"))
+                .respond_with(
+                    ResponseTemplate::new(307)
+                        .insert_header("location", final_url.as_str()),
+                ),
+        )
+        .await;
+    mock_server
+        .register(
+            Mock::given(method("HEAD"))
+                .and(wiremock::matchers::path("/final"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", CACHEABLE_PUBLIC),
+                )
+                .expect(1),
+        )
+        .await;
+    let start_url = format!("{}/start", &mock_server.uri());
+    let start_key = format!("{}:{}", GET, &Url::parse(&start_url)?);
+    let final_key = format!("{}:{}", GET, &Url::parse(&final_url)?);
+
+    for key_by_final_url in [false, true] {
+        let manager = MokaManager::default();
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager.clone())
+                    .options(
+                        HttpCacheOptions::builder()
+                            .key_by_final_url(key_by_final_url)
+                            .build(),
+                    )
+                    .build(),
+            ))
+            .build();
+
+        // The client follows the redirect before the response is stored
+        let res = client.get(&start_url).send().await?;
+        assert_eq!(res.url().as_str(), final_url);
+        assert_eq!(res.bytes().await?, TEST_BODY);
+        assert_eq!(manager.get(&start_key).await?.is_some(), !key_by_final_url);
+        assert_eq!(manager.get(&final_key).await?.is_some(), key_by_final_url);
+
+        if key_by_final_url {
+            // Requests for the final resource are answered from cache
+            let res = client.get(&final_url).send().await?;
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+            // Other methods keep their own keys for the final url
+            client.head(&start_url).send().await?;
+            let head_key = format!("{}:{}", "HEAD", &Url::parse(&final_url)?);
+            assert!(manager.get(&head_key).await?.is_some());
+            let entry = manager.get(&final_key).await?.unwrap();
+            assert_eq!(entry.response.body, TEST_BODY);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn permanent_redirect_ttl() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (path, status, cache_control, expect) in [
+        ("/moved", 301, None, 1),
+        ("/permanent", 308, None, 1),
+        ("/no-store", 301, Some("no-store"), 2),
+    ] {
+        let mut template =
+            ResponseTemplate::new(status).insert_header("location", "/new");
+        if let Some(cache_control) = cache_control {
+            template = template.insert_header("cache-control", cache_control);
+        }
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::path(path))
+                    .respond_with(template)
+                    .expect(expect),
+            )
+            .await;
+    }
+    let manager = MokaManager::default();
+    let ttl = Duration::from_secs(3600);
+
+    // Construct reqwest client that hands redirects back to the middleware
+    let client = ClientBuilder::new(
+        Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?,
+    )
+    .with(Cache(
+        HttpCache::builder(manager.clone())
+            .options(
+                HttpCacheOptions::builder().permanent_redirect_ttl(ttl).build(),
+            )
+            .build(),
+    ))
+    .build();
+
+    // Permanent redirects without Cache-Control are stored for the ttl
+    for path in ["moved", "permanent"] {
+        let url = format!("{}/{path}", &mock_server.uri());
+        let res = client.get(&url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        let res = client.get(&url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.headers().get("location").unwrap(), "/new");
+        let entry = manager
+            .get(&format!("{}:{}", GET, &Url::parse(&url)?))
+            .await?
+            .unwrap();
+        let remaining = entry.policy.time_to_live(std::time::SystemTime::now());
+        assert!(remaining <= ttl && remaining > ttl - Duration::from_secs(60));
+    }
+
+    // An explicit no-store still wins
+    let url = format!("{}/no-store", &mock_server.uri());
+    for _ in 0..2 {
+        let res = client.get(&url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn minimal_headers_permanent_redirect() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .respond_with(
+                    ResponseTemplate::new(301)
+                        .insert_header("location", "/new")
+                        .insert_header("content-language", "en")
+                        .insert_header("x-request-id", "123"),
+                )
+                .expect(1),
+        )
+        .await;
+    let url = format!("{}/moved", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that stores redirects with minimal headers
+    let client = ClientBuilder::new(
+        Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?,
+    )
+    .with(Cache(
+        HttpCache::builder(manager.clone())
+            .options(
+                HttpCacheOptions::builder()
+                    .minimal_headers(true)
+                    .permanent_redirect_ttl(Duration::from_secs(3600))
+                    .build(),
+            )
+            .build(),
+    ))
+    .build();
+
+    // The stored redirect keeps its target and content headers
+    client.get(&url).send().await?;
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.status(), 301);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.headers().get("location").unwrap(), "/new");
+    assert!(res.headers().get("x-request-id").is_none());
+    let entry =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    let headers = entry.response.headers;
+    assert_eq!(headers.get("location").unwrap(), "/new");
+    assert_eq!(headers.get("content-language").unwrap(), "en");
+    assert_eq!(headers.get("content-length").unwrap(), "0");
+    Ok(())
+}
+
+#[tokio::test]
+async fn vary_on_accept() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (accept, body, expect) in [
+        ("application/json", "{}", 1),
+        ("application/xml", "<a/>", 1),
+        ("*/*", "any", 2),
+    ] {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::header("accept", accept))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("cache-control", CACHEABLE_PUBLIC)
+                            .insert_header("vary", "accept")
+                            .set_body_string(body),
+                    )
+                    .expect(expect),
+            )
+            .await;
+    }
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that keys entries by their Accept header
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(HttpCacheOptions::builder().vary_on_accept().build())
+                .build(),
+        ))
+        .build();
+
+    // Each specific Accept gets its own entry with the right body
+    for (accept, body) in
+        [("application/json", "{}"), ("application/xml", "<a/>")]
+    {
+        for status in ["MISS", "HIT"] {
+            let res = client.get(&url).header("accept", accept).send().await?;
+            assert_eq!(res.headers().get(XCACHE).unwrap(), status);
+            assert_eq!(res.text().await?, body);
+        }
+        let key = format!("{}:{}:{accept}", GET, &Url::parse(&url)?);
+        assert!(manager.get(&key).await?.is_some());
+    }
+
+    // Wildcard requests always go to the origin
+    for _ in 0..2 {
+        let res = client.get(&url).header("accept", "*/*").send().await?;
+        assert_eq!(res.text().await?, "any");
+    }
+    assert!(manager.get(&format!("{}:{}", GET, &url)).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn validate_cached_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let signed = |signature: &str, expect: u64| {
+        Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("x-signature", signature)
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expect)
+    };
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that only serves correctly signed entries
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .validate_cached_fn(|res| {
+                            res.headers.get("x-signature").map(String::as_str)
+                                == Some("valid")
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass stores an entry that fails the validation
+    let invalid = mock_server.register_as_scoped(signed("invalid", 1)).await;
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    drop(invalid);
+
+    // The invalid entry is refetched instead of served
+    let _valid = mock_server.register_as_scoped(signed("valid", 1)).await;
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    assert_eq!(res.headers().get("x-signature").unwrap(), "valid");
+
+    // The refetched entry passes and is served from cache
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn heuristic_fraction() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let day = Duration::from_secs(86400);
+    let last_modified =
+        httpdate::fmt_http_date(std::time::SystemTime::now() - day);
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("last-modified", last_modified.as_str())
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let key = format!("{}:{}", GET, &Url::parse(&url)?);
+
+    // A day without modification is fresh for 10% of it, 2.4 hours
+    for (options, lifetime) in [
+        (
+            HttpCacheOptions::builder().heuristic_fraction(0.1).build(),
+            Duration::from_secs(8640),
+        ),
+        (
+            HttpCacheOptions::builder()
+                .heuristic_max_age(Duration::from_secs(3600))
+                .build(),
+            Duration::from_secs(3600),
+        ),
+    ] {
+        let manager = MokaManager::default();
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager.clone()).options(options).build(),
+            ))
+            .build();
+        client.get(&url).send().await?;
+        let res = client.get(&url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        let entry = manager.get(&key).await?.unwrap();
+        let remaining = entry.policy.time_to_live(std::time::SystemTime::now());
+        assert!(
+            remaining <= lifetime
+                && remaining > lifetime - Duration::from_secs(60)
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn disable_heuristics() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let last_modified = httpdate::fmt_http_date(
+        std::time::SystemTime::now() - Duration::from_secs(86400),
+    );
+    let m_304 = Mock::given(method(GET))
+        .and(wiremock::matchers::header_exists("if-modified-since"))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .expect(1);
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("last-modified", last_modified.as_str())
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard_304 = mock_server.register_as_scoped(m_304).await;
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with heuristic freshness disabled
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .disable_heuristics(true)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The response is stored, but is stale right away
+    client.get(&url).send().await?;
+    let entry =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    assert!(entry.is_stale(std::time::SystemTime::now()));
+
+    // So it's revalidated before it's used
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn hard_max_age() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let manager = MokaManager::default();
+
+    // Store the response as if it was received two hours ago
+    let two_hours_ago =
+        std::time::SystemTime::now() - Duration::from_secs(7200);
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .response_date_override(two_hours_ago)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+    client.get(&url).send().await?;
+    let entry = manager.get(&key).await?.unwrap();
+    assert!(!entry.is_stale(std::time::SystemTime::now()));
+
+    // Construct reqwest client that won't serve entries over an hour old
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .hard_max_age(Duration::from_secs(3600))
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The entry is still fresh, but too old to be served
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "MISS");
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // The refetched response replaced it
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn multiple_cache_control_headers() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("cache-control", "no-cache")
+                .append_header("cache-control", "max-age=60")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Both lines are merged into the stored response
+    client.get(&url).send().await?;
+    let entry =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    assert_eq!(entry.response.headers["cache-control"], "no-cache, max-age=60");
+    let cache_control = entry.response.cache_control();
+    assert!(cache_control.no_cache);
+    assert_eq!(cache_control.max_age, Some(Duration::from_secs(60)));
+
+    // no-cache makes the entry stale despite max-age, so it's revalidated
+    assert!(entry.is_stale(std::time::SystemTime::now()));
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    Ok(())
+}
+
+#[tokio::test]
+async fn conflicting_freshness_directives() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    // The path, the response's Cache-Control, whether it's stored, and
+    // whether a second request is answered without contacting the origin
+    let cases = [
+        ("/no-store", "no-store, no-cache, max-age=3600", false, false),
+        ("/no-cache", "no-cache, max-age=3600", true, false),
+        ("/must-revalidate", "must-revalidate, max-age=3600", true, true),
+        ("/s-maxage", "s-maxage=0, max-age=3600", true, false),
+        ("/immutable", "no-cache, immutable, max-age=3600", true, false),
+    ];
+    for (path, cache_control, _, hit) in cases {
+        mock_server
+            .register(
+                Mock::given(method(GET))
+                    .and(wiremock::matchers::path(path))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("cache-control", cache_control)
+                            .set_body_bytes(TEST_BODY),
+                    )
+                    .expect(if hit { 1 } else { 2 }),
+            )
+            .await;
+    }
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    for (path, _, stored, hit) in cases {
+        let url = format!("{}{path}", &mock_server.uri());
+        client.get(&url).send().await?;
+        let entry =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert_eq!(entry.is_some(), stored, "{path}");
+        let res = client.get(&url).send().await?;
+        let expected = if hit { "HIT" } else { "MISS" };
+        assert_eq!(res.headers().get(XCACHE).unwrap(), expected, "{path}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn no_cache_ignores_max_stale() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("no-cache, max-age=0", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(&url).send().await?;
+
+    // A client accepting stale responses still gets a revalidated one
+    let res = client
+        .get(&url)
+        .header("cache-control", "max-stale=3600")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    Ok(())
+}
+
+#[tokio::test]
+async fn head_refreshes_get() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_get = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=60")
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let m_head = Mock::given(method("HEAD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=86400")
+                .insert_header("etag", "\"v1\""),
+        )
+        .expect(1);
+    let _mock_guard_get = mock_server.register_as_scoped(m_get).await;
+    let _mock_guard_head = mock_server.register_as_scoped(m_head).await;
+    let url = format!("{}/", &mock_server.uri());
+    let key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that refreshes GET entries from HEAD responses
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .head_refreshes_get(true)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client.get(&url).send().await?;
+    let entry = manager.get(&key).await?.unwrap();
+    assert!(entry.time_to_live(std::time::SystemTime::now()).as_secs() <= 60);
+
+    // The HEAD response extends the freshness of the GET entry
+    client.head(&url).send().await?;
+    let entry = manager.get(&key).await?.unwrap();
+    let ttl = entry.time_to_live(std::time::SystemTime::now());
+    assert!(ttl > Duration::from_secs(86400 - 60));
+    assert_eq!(entry.response.headers["cache-control"], "max-age=86400");
+    assert_eq!(entry.response.body, TEST_BODY);
+
+    // And the body is still served from cache
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn on_key_bypass() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 4);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let bypasses = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    // Construct reqwest clients whose key closures fail to build a key
+    let panicking = {
+        let recorded = bypasses.clone();
+        HttpCacheOptions::builder()
+            .cache_key(|_| panic!("no key"))
+            .on_key_bypass(move |parts, bypass| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((parts.uri.path().to_string(), bypass));
+            })
+            .build()
+    };
+    let missing = {
+        let recorded = bypasses.clone();
+        HttpCacheOptions::builder()
+            .optional_cache_key(|_| None)
+            .on_key_bypass(move |parts, bypass| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((parts.uri.path().to_string(), bypass));
+            })
+            .build()
+    };
+    for (path, options) in [("panicking", panicking), ("missing", missing)] {
+        let manager = MokaManager::default();
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager.clone()).options(options).build(),
+            ))
+            .build();
+
+        // Every request still succeeds, fetched without the cache
+        let url = format!("{}/{path}", &mock_server.uri());
+        for _ in 0..2 {
+            let res = client.get(&url).send().await?;
+            assert_eq!(res.status(), 200);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        }
+        let key = format!("{}:{}", GET, &Url::parse(&url)?);
+        assert!(manager.get(&key).await?.is_none());
+    }
+    assert_eq!(
+        *bypasses.lock().unwrap(),
+        [
+            ("/panicking".to_string(), KeyBypass::Panicked),
+            ("/panicking".to_string(), KeyBypass::Panicked),
+            ("/missing".to_string(), KeyBypass::NoKey),
+            ("/missing".to_string(), KeyBypass::NoKey),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn recompute_content_length() -> Result<()> {
+    let url = Url::parse("http://127.0.0.1:1/")?;
+    let key = format!("{}:{}", GET, &url);
+    let manager = MokaManager::default();
+
+    // Store an entry whose body no longer matches its Content-Length, as
+    // after a transformation that didn't update it
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .header("content-length", "999")
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res =
+        HttpResponse::try_from((res.map(|_| TEST_BODY.to_vec()), url.clone()))?;
+    manager.put(key, res, policy).await?;
+
+    for (enabled, content_length) in [(false, "999"), (true, "4")] {
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(
+                HttpCache::builder(manager.clone())
+                    .options(
+                        HttpCacheOptions::builder()
+                            .recompute_content_length(enabled)
+                            .build(),
+                    )
+                    .build(),
+            ))
+            .build();
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(
+            res.headers().get("content-length").unwrap(),
+            content_length
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "content-encoding")]
+#[tokio::test]
+async fn recompute_content_length_decompressed() -> Result<()> {
+    use std::io::Write;
+
+    let url = Url::parse("http://127.0.0.1:1/")?;
+    let manager = MokaManager::default();
+    let mut encoder =
+        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(TEST_BODY)?;
+    let compressed = encoder.finish()?;
+
+    // Store a gzip encoded entry without a Content-Length
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", CACHEABLE_PUBLIC)
+        .header("content-encoding", "gzip")
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res = HttpResponse::try_from((res.map(|_| compressed), url.clone()))?;
+    manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager)
+                .options(
+                    HttpCacheOptions::builder()
+                        .recompute_content_length(true)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The decompressed hit reports the length of the decompressed body
+    let res = client.get(url).header("accept-encoding", "br").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.headers().get("content-length").unwrap(), "4");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn transform_for_storage_fn() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    mock_server
+        .register(
+            Mock::given(method(GET))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", CACHEABLE_PUBLIC)
+                        .insert_header("x-api-key", "secret")
+                        .set_body_string(r#"{"name":"test","token":"abc"}"#),
+                )
+                .expect(1),
+        )
+        .await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that redacts a header and a body field
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .transform_for_storage_fn(|mut res| {
+                            res.headers.remove("x-api-key");
+                            let body = String::from_utf8_lossy(&res.body)
+                                .replace(r#""token":"abc""#, r#""token":"""#);
+                            res.body = body.into_bytes();
+                            res
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The response is served as fetched
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get("x-api-key").unwrap(), "secret");
+    assert_eq!(res.text().await?, r#"{"name":"test","token":"abc"}"#);
+
+    // The stored entry is redacted
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    let stored = data.unwrap().response;
+    assert!(!stored.headers.contains_key("x-api-key"));
+    assert_eq!(stored.body, br#"{"name":"test","token":""}"#);
+
+    // Hits serve the redacted copy
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("x-api-key").is_none());
+    assert_eq!(res.text().await?, r#"{"name":"test","token":""}"#);
+    Ok(())
+}
+
+#[cfg(feature = "content-encoding")]
+#[tokio::test]
+async fn no_transform_content_encoding() -> Result<()> {
+    use std::io::Write;
+
+    let url = Url::parse("http://127.0.0.1:1/")?;
+    let manager = MokaManager::default();
+    let mut encoder =
+        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(TEST_BODY)?;
+    let compressed = encoder.finish()?;
+
+    // Store a gzip encoded entry that mustn't be transformed
+    let req = http::Request::get(url.as_str()).body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", "max-age=86400, public, no-transform")
+        .header("content-encoding", "gzip")
+        .header("content-length", compressed.len())
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let res =
+        HttpResponse::try_from((res.map(|_| compressed.clone()), url.clone()))?;
+    manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+
+    // Clients that don't accept gzip still receive the stored bytes
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::builder(manager.clone()).build()))
+        .build();
+    let res =
+        client.get(url.clone()).header("accept-encoding", "br").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.headers().get("content-encoding").unwrap(), "gzip");
+    assert_eq!(
+        res.headers().get("content-length").unwrap(),
+        &compressed.len().to_string()
+    );
+    assert_eq!(res.bytes().await?, compressed);
+
+    // Unless the directive isn't honored
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager)
+                .options(
+                    HttpCacheOptions::builder()
+                        .honor_no_transform(false)
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+    let res = client.get(url).header("accept-encoding", "br").send().await?;
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn no_transform_storage() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "cache-control",
+                    "max-age=86400, public, no-transform",
+                )
+                .insert_header("content-md5", "CY9rzUYh03PK3k6DJie09g==")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that would otherwise rewrite stored responses
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(
+            HttpCache::builder(manager.clone())
+                .options(
+                    HttpCacheOptions::builder()
+                        .minimal_headers(true)
+                        .transform_for_storage_fn(|mut res| {
+                            res.body = b"transformed".to_vec();
+                            res
+                        })
+                        .build(),
+                )
+                .build(),
+        ))
+        .build();
+
+    // The response is stored and served as it was received
+    client.get(url.clone()).send().await?;
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    let data = data.unwrap();
+    assert_eq!(data.response.body, TEST_BODY);
+    assert!(data.response.headers.contains_key("content-md5"));
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("content-md5").is_some());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
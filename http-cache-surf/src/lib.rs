@@ -40,7 +40,8 @@ use std::{
 pub use http::request::Parts;
 use http::{header::CACHE_CONTROL, request};
 use http_cache::{
-    BadHeader, BoxError, HitOrMiss, Middleware, Result, XCACHE, XCACHELOOKUP,
+    append_header, BadHeader, BoxError, HitOrMiss, Middleware, Result, XCACHE,
+    XCACHELOOKUP,
 };
 use http_cache_semantics::CachePolicy;
 use http_types::{headers::HeaderValue, Method, Response, StatusCode, Version};
@@ -48,13 +49,16 @@ use surf::{middleware::Next, Client, Request};
 use url::Url;
 
 pub use http_cache::{
-    CacheManager, CacheMode, CacheOptions, HttpCache, HttpCacheOptions,
-    HttpResponse,
+    CacheControl, CacheEntry, CacheKeyFormat, CacheManager, CacheMode,
+    CacheOptions, EffectiveCacheMode, EntryMetadata, FailedRequests,
+    GlobPattern, HostIndex, HttpCache, HttpCacheBuilder, HttpCacheOptions,
+    HttpCacheOptionsBuilder, HttpResponse, KeyBypass, RevalidationCounter,
+    RevalidationReport, StaleReason, StoreOutcome, StoredRequest, VariantsLock,
 };
 
 #[cfg(feature = "manager-cacache")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
-pub use http_cache::CACacheManager;
+pub use http_cache::{CACacheManager, DedupCACacheManager};
 
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
@@ -132,11 +136,10 @@ impl Middleware for SurfMiddleware<'_> {
         let mut res =
             self.next.run(self.req.clone(), self.client.clone()).await?;
         let mut headers = HashMap::new();
-        for header in res.iter() {
-            headers.insert(
-                header.0.as_str().to_owned(),
-                header.1.as_str().to_owned(),
-            );
+        for (name, values) in res.iter() {
+            for value in values {
+                append_header(&mut headers, name.as_str(), value.as_str());
+            }
         }
         let status = res.status().into();
         let version = res.version().unwrap_or(Version::Http1_1);
@@ -156,7 +159,7 @@ fn to_http_types_error(e: BoxError) -> http_types::Error {
 }
 
 #[surf::utils::async_trait]
-impl<T: CacheManager> surf::middleware::Middleware for Cache<T> {
+impl<T: CacheManager + Clone> surf::middleware::Middleware for Cache<T> {
     async fn handle(
         &self,
         req: Request,
@@ -164,13 +167,21 @@ impl<T: CacheManager> surf::middleware::Middleware for Cache<T> {
         next: Next<'_>,
     ) -> std::result::Result<surf::Response, http_types::Error> {
         let mut middleware = SurfMiddleware { req, client, next };
-        if self
+        let mode = self
+            .0
+            .cache_mode(&middleware)
+            .map_err(|e| http_types::Error::from(anyhow!(e)))?;
+        if let Some(cache_key) = self
             .0
-            .can_cache_request(&middleware)
+            .cacheable_key(&middleware)
             .map_err(|e| http_types::Error::from(anyhow!(e)))?
         {
-            let res =
-                self.0.run(middleware).await.map_err(to_http_types_error)?;
+            let parts = middleware.parts().map_err(to_http_types_error)?;
+            let res = self
+                .0
+                .run_with_key(middleware, cache_key.clone())
+                .await
+                .map_err(to_http_types_error)?;
             let mut converted = Response::new(StatusCode::Ok);
             for header in &res.headers {
                 let val =
@@ -180,6 +191,15 @@ impl<T: CacheManager> surf::middleware::Middleware for Cache<T> {
             converted.set_status(res.status.try_into()?);
             converted.set_version(Some(res.version.into()));
             converted.set_body(res.body);
+            converted.ext_mut().insert(EffectiveCacheMode(mode));
+            if let Some(metadata) = self
+                .0
+                .entry_metadata(&cache_key, &parts)
+                .await
+                .map_err(to_http_types_error)?
+            {
+                converted.ext_mut().insert(EntryMetadata(metadata));
+            }
             Ok(surf::Response::from(converted))
         } else {
             self.0
@@ -191,6 +211,7 @@ impl<T: CacheManager> surf::middleware::Middleware for Cache<T> {
             let miss = HitOrMiss::MISS.to_string();
             res.append_header(XCACHE, miss.clone());
             res.append_header(XCACHELOOKUP, miss);
+            res.insert_ext(EffectiveCacheMode(mode));
             Ok(res)
         }
     }
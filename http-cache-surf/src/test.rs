@@ -105,6 +105,7 @@ mod with_moka {
                 cache_mode_fn: None,
                 cache_bust: None,
                 cache_status_headers: true,
+                ..Default::default()
             },
         }));
 
@@ -229,6 +230,47 @@ mod with_moka {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn effective_cache_mode() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+
+        // Construct Surf client that doesn't cache the private paths
+        let client = Client::new().with(Cache(
+            HttpCache::builder(MokaManager::default())
+                .options(
+                    HttpCacheOptions::builder()
+                        .cache_mode_fn(|req| {
+                            if req.uri.path().starts_with("/private") {
+                                CacheMode::NoStore
+                            } else {
+                                CacheMode::Default
+                            }
+                        })
+                        .build(),
+                )
+                .build(),
+        ));
+
+        let url = format!("{}/private", &mock_server.uri());
+        let req = Request::new(Method::Get, Url::parse(&url)?);
+        let res = client.send(req).await?;
+        assert_eq!(
+            res.ext::<EffectiveCacheMode>(),
+            Some(&EffectiveCacheMode(CacheMode::NoStore))
+        );
+
+        let url = format!("{}/public", &mock_server.uri());
+        let req = Request::new(Method::Get, Url::parse(&url)?);
+        let res = client.send(req).await?;
+        assert_eq!(
+            res.ext::<EffectiveCacheMode>(),
+            Some(&EffectiveCacheMode(CacheMode::Default))
+        );
+        Ok(())
+    }
+
     #[async_std::test]
     async fn no_cache_mode() -> Result<()> {
         let mock_server = MockServer::start().await;
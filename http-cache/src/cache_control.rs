@@ -0,0 +1,167 @@
+use std::{fmt, time::Duration};
+
+use http::{header::CACHE_CONTROL, HeaderMap};
+
+/// The directives of a `Cache-Control` header, as understood by the cache.
+///
+/// Directive names are matched case-insensitively and values may be quoted.
+/// When a directive is repeated, the first occurrence wins, and directives
+/// that aren't listed here or have invalid values are ignored.
+///
+/// It formats as a header value holding the directives that are set, so
+/// only the directives listed here survive a round trip.
+///
+/// ```
+/// use http_cache::CacheControl;
+/// use std::time::Duration;
+///
+/// let cache_control = CacheControl::parse("public, max-age=60, immutable");
+/// assert!(cache_control.public);
+/// assert!(cache_control.immutable);
+/// assert_eq!(cache_control.max_age, Some(Duration::from_secs(60)));
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    /// The `max-age` directive
+    pub max_age: Option<Duration>,
+    /// The `s-maxage` directive
+    pub s_maxage: Option<Duration>,
+    /// The `stale-while-revalidate` directive
+    pub stale_while_revalidate: Option<Duration>,
+    /// The `stale-if-error` directive
+    pub stale_if_error: Option<Duration>,
+    /// Whether the `no-cache` directive is present
+    pub no_cache: bool,
+    /// Whether the `no-store` directive is present
+    pub no_store: bool,
+    /// Whether the `must-revalidate` directive is present
+    pub must_revalidate: bool,
+    /// Whether the `proxy-revalidate` directive is present
+    pub proxy_revalidate: bool,
+    /// Whether the `immutable` directive is present
+    pub immutable: bool,
+    /// Whether the `public` directive is present
+    pub public: bool,
+    /// Whether the `private` directive is present
+    pub private: bool,
+    /// Whether the `no-transform` directive is present
+    pub no_transform: bool,
+}
+
+impl CacheControl {
+    /// Parses the directives of a single `Cache-Control` header value
+    pub fn parse(value: &str) -> Self {
+        let mut cache_control = Self::default();
+        let mut seen = Vec::new();
+        for directive in split_directives(value) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => {
+                    (name.trim(), Some(value.trim().trim_matches('"')))
+                }
+                None => (directive.trim(), None),
+            };
+            let name = name.to_ascii_lowercase();
+            if name.is_empty() || seen.contains(&name) {
+                continue;
+            }
+            let seconds = || {
+                value
+                    .and_then(|value| value.parse().ok())
+                    .map(Duration::from_secs)
+            };
+            match name.as_str() {
+                "max-age" => cache_control.max_age = seconds(),
+                "s-maxage" => cache_control.s_maxage = seconds(),
+                "stale-while-revalidate" => {
+                    cache_control.stale_while_revalidate = seconds()
+                }
+                "stale-if-error" => cache_control.stale_if_error = seconds(),
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                "must-revalidate" => cache_control.must_revalidate = true,
+                "proxy-revalidate" => cache_control.proxy_revalidate = true,
+                "immutable" => cache_control.immutable = true,
+                "public" => cache_control.public = true,
+                "private" => cache_control.private = true,
+                "no-transform" => cache_control.no_transform = true,
+                _ => {}
+            }
+            seen.push(name);
+        }
+        cache_control
+    }
+
+    /// Parses the `Cache-Control` headers of a request or response, which
+    /// may be split across several header lines
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let values: Vec<&str> = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        Self::parse(&values.join(","))
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = [
+            ("max-age", self.max_age),
+            ("s-maxage", self.s_maxage),
+            ("stale-while-revalidate", self.stale_while_revalidate),
+            ("stale-if-error", self.stale_if_error),
+        ];
+        let flags = [
+            ("no-cache", self.no_cache),
+            ("no-store", self.no_store),
+            ("must-revalidate", self.must_revalidate),
+            ("proxy-revalidate", self.proxy_revalidate),
+            ("immutable", self.immutable),
+            ("public", self.public),
+            ("private", self.private),
+            ("no-transform", self.no_transform),
+        ];
+        let directives = seconds
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| format!("{name}={}", value.as_secs()))
+            })
+            .chain(
+                flags
+                    .into_iter()
+                    .filter(|(_, set)| *set)
+                    .map(|(name, _)| name.to_owned()),
+            );
+        for (i, directive) in directives.enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(&directive)?;
+        }
+        Ok(())
+    }
+}
+
+// Splits a header value on the commas between directives, leaving the commas
+// inside quoted values such as `no-cache="set-cookie, x-foo"` alone.
+// (https://www.rfc-editor.org/rfc/rfc9110#section-5.6.4)
+fn split_directives(value: &str) -> Vec<&str> {
+    let mut directives = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                directives.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    directives.push(&value[start..]);
+    directives
+}
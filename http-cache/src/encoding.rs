@@ -0,0 +1,97 @@
+use std::io::{self, Read};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use http::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, ETAG},
+    request,
+};
+
+use crate::{HttpResponse, Result};
+
+/// Decompresses a gzip or deflate encoded response when the request doesn't
+/// accept its encoding, so clients that can't decode it never receive it.
+/// Requests without an `Accept-Encoding` header accept any encoding, so they
+/// are served the response as it is, as are responses with any other or
+/// multiple encodings. A strong `ETag` is weakened on a decompressed
+/// response, since its bytes no longer match the tagged representation.
+/// Bodies larger than `offload_threshold` are decompressed on a blocking
+/// thread.
+pub(crate) async fn negotiate(
+    parts: &request::Parts,
+    mut res: HttpResponse,
+    offload_threshold: usize,
+) -> Result<HttpResponse> {
+    let Some(encoding) = res.headers.get(CONTENT_ENCODING.as_str()) else {
+        return Ok(res);
+    };
+    let encoding = encoding.trim().to_ascii_lowercase();
+    if !matches!(encoding.as_str(), "gzip" | "x-gzip" | "deflate")
+        || accepts(parts, &encoding)
+    {
+        return Ok(res);
+    }
+    let body = std::mem::take(&mut res.body);
+    let body = if body.len() > offload_threshold {
+        blocking::unblock(move || decode(&encoding, &body)).await?
+    } else {
+        decode(&encoding, &body)?
+    };
+    res.headers.remove(CONTENT_ENCODING.as_str());
+    if res.headers.contains_key(CONTENT_LENGTH.as_str()) {
+        res.headers
+            .insert(CONTENT_LENGTH.as_str().to_owned(), body.len().to_string());
+    }
+    if let Some(etag) = res.headers.get_mut(ETAG.as_str()) {
+        if !etag.starts_with("W/") {
+            etag.insert_str(0, "W/");
+        }
+    }
+    res.body = body;
+    Ok(res)
+}
+
+// Decodes a gzip or deflate encoded body.
+fn decode(encoding: &str, body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    if encoding == "deflate" {
+        ZlibDecoder::new(body).read_to_end(&mut decoded)?;
+    } else {
+        GzDecoder::new(body).read_to_end(&mut decoded)?;
+    }
+    Ok(decoded)
+}
+
+// Whether the request's Accept-Encoding header allows the encoding. Without
+// the header any encoding is acceptable. Otherwise the encoding must be listed,
+// or `*` must be, without a zero quality value.
+fn accepts(parts: &request::Parts, encoding: &str) -> bool {
+    if !parts.headers.contains_key(ACCEPT_ENCODING) {
+        return true;
+    }
+    let mut wildcard = false;
+    for coding in parts
+        .headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let accepted = !params.any(|param| {
+            param.trim().strip_prefix("q=").is_some_and(|q| {
+                q.trim().parse::<f32>().is_ok_and(|q| q == 0.0)
+            })
+        });
+        if name.eq_ignore_ascii_case(encoding)
+            || (encoding == "x-gzip" && name.eq_ignore_ascii_case("gzip"))
+            || (encoding == "gzip" && name.eq_ignore_ascii_case("x-gzip"))
+        {
+            return accepted;
+        }
+        if name == "*" {
+            wildcard = accepted;
+        }
+    }
+    wildcard
+}
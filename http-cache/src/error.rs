@@ -29,3 +29,43 @@ impl fmt::Display for BadHeader {
 }
 
 impl std::error::Error for BadHeader {}
+
+/// Error type for unknown cache mode names
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BadCacheMode;
+
+impl fmt::Display for BadCacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Unknown cache mode")
+    }
+}
+
+impl std::error::Error for BadCacheMode {}
+
+/// Error type for snapshots of managers that can't list their records
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SnapshotUnsupported;
+
+impl fmt::Display for SnapshotUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Cache manager can't list its records for a snapshot")
+    }
+}
+
+impl std::error::Error for SnapshotUnsupported {}
+
+/// Error type for cache entries that couldn't be encrypted
+#[cfg(feature = "manager-encrypted")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-encrypted")))]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct EncryptionError;
+
+#[cfg(feature = "manager-encrypted")]
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Error encrypting cache entry")
+    }
+}
+
+#[cfg(feature = "manager-encrypted")]
+impl std::error::Error for EncryptionError {}
@@ -0,0 +1,60 @@
+//! Emits cache metrics to the [`metrics`](https://docs.rs/metrics) facade
+//! when the `metrics` feature is enabled, otherwise every call is a no-op.
+use std::time::Duration;
+
+use crate::{CacheMode, Op};
+
+/// Counts requests answered from the cache without contacting the origin.
+const HITS: &str = "http_cache.hits";
+/// Counts requests that had no usable cached response.
+const MISSES: &str = "http_cache.misses";
+/// Counts cached responses that were revalidated with the origin.
+const REVALIDATIONS: &str = "http_cache.revalidations";
+/// Records how long looking up a cached response took, in seconds.
+const LOOKUP_LATENCY: &str = "http_cache.lookup_latency";
+/// Records how long a call to a timed cache manager took, in seconds.
+const MANAGER_LATENCY: &str = "http_cache.manager_latency";
+
+#[cfg(feature = "metrics")]
+fn increment(name: &'static str, mode: CacheMode) {
+    metrics::counter!(name, "mode" => mode.to_string()).increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+fn increment(_name: &'static str, _mode: CacheMode) {}
+
+#[cfg(feature = "metrics")]
+fn record(name: &'static str, mode: CacheMode, elapsed: Duration) {
+    metrics::histogram!(name, "mode" => mode.to_string()).record(elapsed);
+}
+
+#[cfg(not(feature = "metrics"))]
+fn record(_name: &'static str, _mode: CacheMode, _elapsed: Duration) {}
+
+#[cfg(feature = "metrics")]
+fn record_op(name: &'static str, op: Op, elapsed: Duration) {
+    metrics::histogram!(name, "op" => op.as_str()).record(elapsed);
+}
+
+#[cfg(not(feature = "metrics"))]
+fn record_op(_name: &'static str, _op: Op, _elapsed: Duration) {}
+
+pub(crate) fn hit(mode: CacheMode) {
+    increment(HITS, mode);
+}
+
+pub(crate) fn miss(mode: CacheMode) {
+    increment(MISSES, mode);
+}
+
+pub(crate) fn revalidation(mode: CacheMode) {
+    increment(REVALIDATIONS, mode);
+}
+
+pub(crate) fn lookup_latency(mode: CacheMode, elapsed: Duration) {
+    record(LOOKUP_LATENCY, mode, elapsed);
+}
+
+pub(crate) fn manager_latency(op: Op, elapsed: Duration) {
+    record_op(MANAGER_LATENCY, op, elapsed);
+}
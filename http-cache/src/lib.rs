@@ -15,6 +15,8 @@
 //! A caching middleware that follows HTTP caching rules, thanks to
 //! [`http-cache-semantics`](https://github.com/kornelski/rusty-http-cache-semantics).
 //! By default, it uses [`cacache`](https://github.com/zkat/cacache-rs) as the backend cache manager.
+//! [`InMemoryManager`], a `HashMap` backed manager without eviction, is always
+//! available for tests and small apps.
 //!
 //! ## Features
 //!
@@ -28,31 +30,97 @@
 //! a high-performance in-memory cache, backend manager.
 //! - `with-http-types` (disabled): enable [http-types](https://github.com/http-rs/http-types)
 //! type conversion support
+//! - `with-http-body` (disabled): enable collecting [http-body](https://github.com/hyperium/http-body)
+//! bodies into an [`HttpResponse`].
+//! - `metrics` (disabled): record the `http_cache.hits`, `http_cache.misses` and
+//! `http_cache.revalidations` counters, and the `http_cache.lookup_latency` histogram,
+//! through the [metrics](https://github.com/metrics-rs/metrics) facade. Each is labeled
+//! with the `mode` the request was made with. [`TimedManager`] also records the
+//! `http_cache.manager_latency` histogram, labeled with the `op`.
+//! - `content-encoding` (disabled): decompress gzip and deflate encoded responses
+//! served from cache when the request's `Accept-Encoding` header doesn't accept them.
+//! - `value-cache` (disabled): enable [`ValueCache`], a cache of arbitrary serializable
+//! values with a time to live that is backed by any [`CacheManager`].
+//! - `test-util` (disabled): enable [`RecordingManager`], a manager wrapper that records
+//! the calls made to it for use in tests, and the fixtures in [`test_util`].
+mod cache_control;
+#[cfg(feature = "content-encoding")]
+mod encoding;
 mod error;
+mod instrument;
 mod managers;
+#[cfg(any(test, feature = "test-util"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod test_util;
+#[cfg(feature = "value-cache")]
+mod value;
 
 use std::{
     collections::HashMap,
     convert::TryFrom,
     fmt::{self, Debug},
+    future::Future,
+    io::{Read, Write},
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
     str::FromStr,
-    sync::Arc,
-    time::SystemTime,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Poll, Waker},
+    time::{Duration, Instant, SystemTime},
 };
 
-use http::{header::CACHE_CONTROL, request, response, StatusCode};
+use futures_util::future::{self as future_util, Either};
+use http::{
+    header::{
+        ACCEPT, ACCESS_CONTROL_MAX_AGE, AGE, CACHE_CONTROL, CONTENT_ENCODING,
+        CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_LOCATION, CONTENT_RANGE,
+        CONTENT_TYPE, DATE, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        LAST_MODIFIED, LOCATION, PRAGMA, RETRY_AFTER, SET_COOKIE, VARY,
+    },
+    request, response, HeaderName, Method, StatusCode,
+};
 use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use url::Url;
 
-pub use error::{BadHeader, BadVersion, BoxError, Result};
+pub use cache_control::CacheControl;
+pub use error::{
+    BadCacheMode, BadHeader, BadVersion, BoxError, Result, SnapshotUnsupported,
+};
+
+#[cfg(feature = "manager-encrypted")]
+pub use error::EncryptionError;
 
 #[cfg(feature = "manager-cacache")]
 pub use managers::cacache::CACacheManager;
 
+#[cfg(feature = "manager-cacache")]
+pub use managers::cacache_dedup::DedupCACacheManager;
+
 #[cfg(feature = "manager-moka")]
 pub use managers::moka::MokaManager;
 
+pub use managers::{
+    blocking::{Blocking, BlockingCacheManager},
+    in_memory::InMemoryManager,
+    size_limited::SizeLimitedManager,
+    timed::{ManagerTimingFn, TimedManager},
+    Op,
+};
+
+#[cfg(feature = "test-util")]
+pub use managers::recording::RecordingManager;
+
+#[cfg(feature = "manager-encrypted")]
+pub use managers::encrypted::EncryptedManager;
+
+#[cfg(feature = "value-cache")]
+pub use value::ValueCache;
+
 // Exposing the moka cache for convenience, renaming to avoid naming conflicts
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
@@ -63,6 +131,18 @@ pub use moka::future::{Cache as MokaCache, CacheBuilder as MokaCacheBuilder};
 pub const XCACHE: &str = "x-cache";
 /// `x-cache-lookup` header: Value will be HIT if a response existed in cache, MISS if not
 pub const XCACHELOOKUP: &str = "x-cache-lookup";
+/// `cache-status` header: Describes how the cache handled the request, see
+/// [RFC 9211](https://www.rfc-editor.org/rfc/rfc9211)
+pub const CACHESTATUS: &str = "cache-status";
+
+// The name this cache identifies itself with in the `cache-status` header
+const CACHE_STATUS_NAME: &str = "http-cache";
+
+const SURROGATE_CONTROL: &str = "surrogate-control";
+
+// Marks the index of representations stored with `cache_variants`, giving the
+// number of them.
+const VARIANTS_INDEX: &str = "x-http-cache-variants";
 
 /// Represents a basic cache status
 /// Used in the custom headers `x-cache` and `x-cache-lookup`
@@ -83,6 +163,72 @@ impl fmt::Display for HitOrMiss {
     }
 }
 
+/// The result of attempting to store a response, reported to
+/// [`HttpCacheOptions::on_store`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StoreOutcome {
+    /// The response was stored
+    Stored,
+    /// The response can't be cached
+    SkippedUncacheable,
+    /// The response body exceeds [`HttpCacheOptions::max_body_size`]
+    SkippedTooLarge,
+    /// The cache manager failed to store the response
+    Error,
+}
+
+/// Why a request bypassed the cache because no cache key could be built for
+/// it, reported to [`HttpCacheOptions::on_key_bypass`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyBypass {
+    /// [`HttpCacheOptions::optional_cache_key`] returned [`None`]
+    NoKey,
+    /// A cache key closure panicked
+    Panicked,
+}
+
+/// Why a stale response was served, reported to
+/// [`HttpCacheOptions::on_stale_served`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StaleReason {
+    /// Served without revalidating, because
+    /// [`HttpCacheOptions::max_background_revalidations`] revalidations were
+    /// already running
+    SwrWindow,
+    /// The origin didn't answer within
+    /// [`HttpCacheOptions::serve_stale_after`], the revalidation carries on
+    /// in the background
+    Timeout,
+    /// Revalidation failed, or the origin answered with an error or a
+    /// response that was rejected
+    IfError,
+    /// The request allowed stale responses with the `max-stale` directive
+    MaxStale,
+    /// The cache mode serves cached responses without revalidating them
+    ForcedOffline,
+}
+
+/// How many of the stale entries found by [`HttpCache::revalidate_stale`]
+/// were refreshed, removed or failed to revalidate
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RevalidationReport {
+    /// Entries that were revalidated or replaced by the origin's response
+    pub refreshed: usize,
+    /// Entries that were removed because the origin's response can't be
+    /// stored
+    pub removed: usize,
+    /// Entries that were kept as they were because the request failed or the
+    /// origin answered with a server error
+    pub failed: usize,
+}
+
+// What became of a stale entry sent to the origin by `revalidate_stale`.
+enum Revalidated {
+    Refreshed,
+    Removed,
+    Failed,
+}
+
 /// Represents an HTTP version
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[non_exhaustive]
@@ -121,7 +267,17 @@ impl fmt::Display for HttpVersion {
 pub struct HttpResponse {
     /// HTTP response body
     pub body: Vec<u8>,
-    /// HTTP response headers
+    /// HTTP response headers, trailers sent after a chunked body aren't
+    /// captured so cache hits only carry the headers sent before the body.
+    /// Names are lowercase, as the clients hand them over, and neither their
+    /// original casing nor their order is kept, so a cache hit isn't a
+    /// byte-exact replay of the origin's header block.
+    ///
+    /// There is no raw capture mode: the supported clients only hand the
+    /// middleware parsed header maps, so the original header block never
+    /// reaches the cache. Record/replay harnesses that need the wire bytes
+    /// should capture them below the HTTP client, for instance with a
+    /// recording proxy.
     pub headers: HashMap<String, String>,
     /// HTTP response status code
     pub status: u16,
@@ -131,6 +287,28 @@ pub struct HttpResponse {
     pub version: HttpVersion,
 }
 
+/// Adds a header line to the headers of an [`HttpResponse`], joining it to
+/// an earlier line of the same name with a comma, as repeated lines are
+/// combined into one list
+/// ([RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-5.3)).
+///
+/// `Set-Cookie` can't be combined this way, so only its last line is kept.
+pub fn append_header(
+    headers: &mut HashMap<String, String>,
+    name: &str,
+    value: &str,
+) {
+    match headers.get_mut(name) {
+        Some(joined) if name != SET_COOKIE.as_str() => {
+            joined.push_str(", ");
+            joined.push_str(value);
+        }
+        _ => {
+            headers.insert(name.to_owned(), value.to_owned());
+        }
+    }
+}
+
 impl HttpResponse {
     /// Returns `http::response::Parts`
     pub fn parts(&self) -> Result<response::Parts> {
@@ -140,7 +318,7 @@ impl HttpResponse {
             let headers = converted.headers_mut();
             for header in &self.headers {
                 headers.insert(
-                    http::header::HeaderName::from_str(header.0.as_str())?,
+                    HeaderName::from_str(header.0.as_str())?,
                     http::HeaderValue::from_str(header.1.as_str())?,
                 );
             }
@@ -186,21 +364,104 @@ impl HttpResponse {
 
     /// Update the headers from `http::response::Parts`
     pub fn update_headers(&mut self, parts: &response::Parts) -> Result<()> {
+        for name in parts.headers.keys() {
+            self.headers.remove(name.as_str());
+        }
         for header in parts.headers.iter() {
-            self.headers.insert(
-                header.0.as_str().to_string(),
-                header.1.to_str()?.to_string(),
+            append_header(
+                &mut self.headers,
+                header.0.as_str(),
+                header.1.to_str()?,
             );
         }
         Ok(())
     }
 
+    /// Returns the parsed directives of the Cache-Control header
+    #[must_use]
+    pub fn cache_control(&self) -> CacheControl {
+        self.headers
+            .get(CACHE_CONTROL.as_str())
+            .map(|val| CacheControl::parse(val))
+            .unwrap_or_default()
+    }
+
     /// Checks if the Cache-Control header contains the must-revalidate directive
     #[must_use]
     pub fn must_revalidate(&self) -> bool {
-        self.headers.get(CACHE_CONTROL.as_str()).is_some_and(|val| {
-            val.as_str().to_lowercase().contains("must-revalidate")
-        })
+        self.cache_control().must_revalidate
+    }
+
+    /// Checks if the Cache-Control header contains the proxy-revalidate directive
+    #[must_use]
+    pub fn proxy_revalidate(&self) -> bool {
+        self.cache_control().proxy_revalidate
+    }
+
+    /// Returns a `304 Not Modified` response for this response, keeping only
+    /// the headers that are sent with a 304 and dropping the body.
+    /// (https://www.rfc-editor.org/rfc/rfc9110#section-15.4.5)
+    #[must_use]
+    pub fn not_modified(&self) -> Self {
+        let keep = [
+            CACHE_CONTROL,
+            CONTENT_LOCATION,
+            DATE,
+            ETAG,
+            EXPIRES,
+            LAST_MODIFIED,
+            VARY,
+        ];
+        Self {
+            body: Vec::new(),
+            headers: self
+                .headers
+                .iter()
+                .filter(|(name, _)| {
+                    keep.iter().any(|keep| keep.as_str() == name.as_str())
+                })
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            status: 304,
+            url: self.url.clone(),
+            version: self.version,
+        }
+    }
+
+    /// Returns a copy of this response that only keeps the headers needed to
+    /// cache and revalidate it, to interpret its body, and to follow it when
+    /// it's a redirect.
+    #[must_use]
+    pub fn minimal_headers(&self) -> Self {
+        let keep = [
+            AGE,
+            CACHE_CONTROL,
+            CONTENT_ENCODING,
+            CONTENT_LANGUAGE,
+            CONTENT_LENGTH,
+            CONTENT_RANGE,
+            CONTENT_TYPE,
+            DATE,
+            ETAG,
+            EXPIRES,
+            LAST_MODIFIED,
+            LOCATION,
+            VARY,
+        ];
+        Self {
+            body: self.body.clone(),
+            headers: self
+                .headers
+                .iter()
+                .filter(|(name, _)| {
+                    keep.iter().any(|keep| keep.as_str() == name.as_str())
+                })
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            status: self.status,
+            url: self.url.clone(),
+            version: self.version,
+        }
     }
 
     /// Adds the custom `x-cache` header to the response
@@ -214,14 +475,137 @@ impl HttpResponse {
     }
 }
 
+/// A cached response along with the policy it was stored with.
+///
+/// Converts to and from a `(HttpResponse, CachePolicy)` tuple, which is
+/// what [`CacheManager::get`] returned before this type was introduced.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// The cached response
+    pub response: HttpResponse,
+    /// The cache policy generated for the response
+    pub policy: CachePolicy,
+    /// Metadata stored alongside the response by
+    /// [`HttpCacheOptions::entry_metadata_fn`], only populated by
+    /// [`HttpCache::get_entry`].
+    pub metadata: HashMap<String, String>,
+    /// The request that produced the response, only populated by
+    /// [`HttpCache::get_entry`] when [`HttpCacheOptions::store_request`] is
+    /// set.
+    pub request: Option<StoredRequest>,
+}
+
+impl CacheEntry {
+    /// Create a new entry from a response and its policy
+    pub fn new(response: HttpResponse, policy: CachePolicy) -> Self {
+        Self { response, policy, metadata: HashMap::new(), request: None }
+    }
+
+    /// Returns the cached response
+    #[must_use]
+    pub fn response(&self) -> &HttpResponse {
+        &self.response
+    }
+
+    /// Returns the cache policy of the response
+    #[must_use]
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    /// Splits the entry into its response and policy
+    #[must_use]
+    pub fn into_parts(self) -> (HttpResponse, CachePolicy) {
+        (self.response, self.policy)
+    }
+
+    /// Checks if the response can't be used without revalidation at `now`
+    #[must_use]
+    pub fn is_stale(&self, now: SystemTime) -> bool {
+        self.policy.is_stale(now)
+    }
+
+    /// Returns how much longer the response stays fresh from `now`
+    #[must_use]
+    pub fn time_to_live(&self, now: SystemTime) -> Duration {
+        self.policy.time_to_live(now)
+    }
+
+    /// Returns the current age of the response at `now`
+    #[must_use]
+    pub fn age(&self, now: SystemTime) -> Duration {
+        self.policy.age(now)
+    }
+}
+
+/// The request a cached response was produced by, stored with it when
+/// [`HttpCacheOptions::store_request`] is set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StoredRequest {
+    /// Method of the request
+    pub method: String,
+    /// Uri of the request
+    pub uri: String,
+    /// The values of the request headers named by the `Vary` header of the
+    /// response, one per line. They're enough to select the response again,
+    /// other headers aren't kept.
+    pub headers: HashMap<String, Vec<String>>,
+}
+
+impl StoredRequest {
+    /// Records the request, keeping the headers the response varies on
+    pub fn new(parts: &request::Parts, res: &HttpResponse) -> Self {
+        let headers = vary_names(res)
+            .into_iter()
+            .filter(|name| parts.headers.contains_key(name.as_str()))
+            .map(|name| {
+                let values = parts
+                    .headers
+                    .get_all(name.as_str())
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .collect();
+                (name, values)
+            })
+            .collect();
+        Self {
+            method: parts.method.to_string(),
+            uri: parts.uri.to_string(),
+            headers,
+        }
+    }
+
+    /// Builds the parts of an equivalent request
+    pub fn parts(&self) -> Result<request::Parts> {
+        let mut builder =
+            request::Builder::new().method(self.method.as_str()).uri(&self.uri);
+        for (name, values) in &self.headers {
+            for value in values {
+                builder = builder.header(name, value);
+            }
+        }
+        Ok(builder.body(())?.into_parts().0)
+    }
+}
+
+impl From<(HttpResponse, CachePolicy)> for CacheEntry {
+    fn from((response, policy): (HttpResponse, CachePolicy)) -> Self {
+        Self::new(response, policy)
+    }
+}
+
+impl From<CacheEntry> for (HttpResponse, CachePolicy) {
+    fn from(entry: CacheEntry) -> Self {
+        entry.into_parts()
+    }
+}
+
 /// A trait providing methods for storing, reading, and removing cache records.
 #[async_trait::async_trait]
 pub trait CacheManager: Send + Sync + 'static {
     /// Attempts to pull a cached response and related policy from cache.
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>>;
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>>;
     /// Attempts to cache a response and related policy.
     async fn put(
         &self,
@@ -231,8 +615,182 @@ pub trait CacheManager: Send + Sync + 'static {
     ) -> Result<HttpResponse>;
     /// Attempts to remove a record from cache.
     async fn delete(&self, cache_key: &str) -> Result<()>;
+    /// Attempts to pull a cached response's status, headers and policy
+    /// without its body, for callers that only need to inspect the entry.
+    /// The body of the returned response may be left empty.
+    ///
+    /// The default implementation pulls the full record with [`CacheManager::get`],
+    /// managers that store bodies apart from headers should override it.
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        self.get(cache_key).await
+    }
+    /// Checks if a record exists in cache without needing to return it.
+    ///
+    /// The default implementation pulls the full record with [`CacheManager::get`],
+    /// managers that can check for a key more cheaply should override it.
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.get(cache_key).await?.is_some())
+    }
+    /// Reports the total number of bytes currently stored, if the backend
+    /// can provide it cheaply. Returns `None` when the size is unknown.
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+    /// Reports the number of records currently stored, including the index
+    /// entries kept by some options, if the backend can count them cheaply.
+    /// Returns `None` when the count is unknown.
+    async fn len(&self) -> Result<Option<usize>> {
+        Ok(None)
+    }
+    /// Reports whether no records are stored, going by
+    /// [`CacheManager::len`]. Returns `None` when the count is unknown.
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        Ok(self.len().await?.map(|len| len == 0))
+    }
+    /// Removes every record whose response is no longer fresh, returning the
+    /// number of records removed.
+    ///
+    /// The default implementation removes nothing and returns `Ok(0)`, so a
+    /// return of `0` from a manager that doesn't override it doesn't mean
+    /// nothing was stale. Managers that can enumerate their records should
+    /// override it.
+    async fn delete_stale(&self) -> Result<usize> {
+        Ok(0)
+    }
+    /// Returns the key of every stored record, if the backend can list them.
+    /// Returns `None` when it can't, which is the default.
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(None)
+    }
+    /// Returns the keys of every record whose response is no longer fresh.
+    ///
+    /// The default implementation returns an empty list, whether or not any
+    /// records are stale. Managers that can enumerate their records should
+    /// override it.
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+    /// Writes every record, along with its policy, to the writer.
+    ///
+    /// The default implementation lists the records with
+    /// [`CacheManager::keys`] and reads them with [`CacheManager::get`], so
+    /// the snapshot can be imported into any other manager. It fails with
+    /// [`SnapshotUnsupported`] when the manager can't list its keys.
+    async fn export_snapshot(
+        &self,
+        writer: &mut (dyn Write + Send),
+    ) -> Result<()> {
+        let Some(keys) = self.keys().await? else {
+            return Err(Box::new(SnapshotUnsupported));
+        };
+        let mut records = Vec::new();
+        for key in keys {
+            if let Some(entry) = self.get(&key).await? {
+                records.push(SnapshotRecord {
+                    key,
+                    response: entry.response,
+                    policy: entry.policy,
+                });
+            }
+        }
+        serde_json::to_writer(writer, &records)?;
+        Ok(())
+    }
+    /// Loads the records of a snapshot written by
+    /// [`CacheManager::export_snapshot`], skipping any that are already
+    /// stale.
+    ///
+    /// The default implementation stores each record with
+    /// [`CacheManager::put`].
+    async fn import_snapshot(
+        &self,
+        reader: &mut (dyn Read + Send),
+    ) -> Result<()> {
+        let records: Vec<SnapshotRecord> = serde_json::from_reader(reader)?;
+        let now = SystemTime::now();
+        for record in records {
+            if record.policy.is_stale(now) {
+                continue;
+            }
+            self.put(record.key, record.response, record.policy).await?;
+        }
+        Ok(())
+    }
+}
+
+// A record in the snapshots written by `CacheManager::export_snapshot`.
+#[derive(Deserialize, Serialize)]
+struct SnapshotRecord {
+    key: String,
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for Arc<M> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        (**self).get(cache_key).await
+    }
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        (**self).put(cache_key, res, policy).await
+    }
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        (**self).delete(cache_key).await
+    }
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        (**self).get_metadata(cache_key).await
+    }
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        (**self).contains(cache_key).await
+    }
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        (**self).size_bytes().await
+    }
+    async fn len(&self) -> Result<Option<usize>> {
+        (**self).len().await
+    }
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        (**self).is_empty().await
+    }
+    async fn delete_stale(&self) -> Result<usize> {
+        (**self).delete_stale().await
+    }
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        (**self).keys().await
+    }
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        (**self).stale_keys().await
+    }
+    async fn export_snapshot(
+        &self,
+        writer: &mut (dyn Write + Send),
+    ) -> Result<()> {
+        (**self).export_snapshot(writer).await
+    }
+    async fn import_snapshot(
+        &self,
+        reader: &mut (dyn Read + Send),
+    ) -> Result<()> {
+        (**self).import_snapshot(reader).await
+    }
 }
 
+/// A future that owns everything it uses, so it can keep running after the
+/// request that started it has been answered.
+pub type BackgroundFuture<T> =
+    Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
 /// Describes the functionality required for interfacing with HTTP client middleware
 #[async_trait::async_trait]
 pub trait Middleware: Send {
@@ -264,11 +822,39 @@ pub trait Middleware: Send {
     fn method(&self) -> Result<String>;
     /// Attempts to fetch an upstream resource and return an [`HttpResponse`]
     async fn remote_fetch(&mut self) -> Result<HttpResponse>;
+    /// Returns a future that completes after `duration` on the client's
+    /// runtime, for [`HttpCacheOptions::serve_stale_after`]. The default
+    /// returns `None`, so the origin is always waited for.
+    fn sleep(&self, _duration: Duration) -> Option<BackgroundFuture<()>> {
+        None
+    }
+    /// Returns a fetch of the request as it currently is that doesn't borrow
+    /// the middleware, so a revalidation that outlasts
+    /// [`HttpCacheOptions::serve_stale_after`], or one started by
+    /// [`HttpCacheOptions::background_revalidate_always`], can finish in the
+    /// background. The default returns `None`, so the origin is always waited
+    /// for and hits aren't revalidated.
+    fn background_fetch(
+        &self,
+    ) -> Result<Option<BackgroundFuture<Result<HttpResponse>>>> {
+        Ok(None)
+    }
+    /// Runs the future to completion on the client's runtime. It's only
+    /// called by middleware whose [`Middleware::background_fetch`] returns a
+    /// fetch, the default drops the future.
+    fn spawn(&self, _future: BackgroundFuture<()>) {}
 }
 
 /// Similar to [make-fetch-happen cache options](https://github.com/npm/make-fetch-happen#--optscache).
 /// Passed in when the [`HttpCache`] struct is being built.
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+///
+/// Modes can also be parsed from and displayed as their kebab-case names
+/// (`"default"`, `"no-store"`, `"only-if-cached"`, ...), which is also the
+/// form used when serializing.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum CacheMode {
     /// Will inspect the HTTP cache on the way to the network.
     /// If there is a fresh response it will be used.
@@ -290,6 +876,7 @@ pub enum CacheMode {
     /// Uses any response in the HTTP cache matching the request,
     /// not paying attention to staleness. If there was no response,
     /// it creates a normal request and updates the HTTP cache with the response.
+    /// This gives "cache first, network fallback" behavior.
     ForceCache,
     /// Uses any response in the HTTP cache matching the request,
     /// not paying attention to staleness. If there was no response,
@@ -302,6 +889,56 @@ pub enum CacheMode {
     IgnoreRules,
 }
 
+impl CacheMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::NoStore => "no-store",
+            Self::Reload => "reload",
+            Self::NoCache => "no-cache",
+            Self::ForceCache => "force-cache",
+            Self::OnlyIfCached => "only-if-cached",
+            Self::IgnoreRules => "ignore-rules",
+        }
+    }
+}
+
+impl fmt::Display for CacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for CacheMode {
+    type Err = BadCacheMode;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "default" => Self::Default,
+            "no-store" => Self::NoStore,
+            "reload" => Self::Reload,
+            "no-cache" => Self::NoCache,
+            "force-cache" => Self::ForceCache,
+            "only-if-cached" => Self::OnlyIfCached,
+            "ignore-rules" => Self::IgnoreRules,
+            _ => return Err(BadCacheMode),
+        })
+    }
+}
+
+/// The metadata stored by [`HttpCacheOptions::entry_metadata_fn`] with the
+/// cache entry for a request. The client middlewares attach it to the
+/// extensions of the responses they return, whether the response was served
+/// from the entry or just stored as it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryMetadata(pub HashMap<String, String>);
+
+/// The cache mode that applied to a request, once the per-request override,
+/// `cache_mode_fn` and `path_rules` are taken into account. The client
+/// middlewares attach it to the extensions of the responses they return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveCacheMode(pub CacheMode);
+
 impl TryFrom<http::Version> for HttpVersion {
     type Error = BoxError;
 
@@ -329,6 +966,61 @@ impl From<HttpVersion> for http::Version {
     }
 }
 
+impl TryFrom<(http::Response<Vec<u8>>, Url)> for HttpResponse {
+    type Error = BoxError;
+
+    fn try_from(
+        (response, url): (http::Response<Vec<u8>>, Url),
+    ) -> Result<Self> {
+        let (parts, body) = response.into_parts();
+        let mut headers = HashMap::new();
+        for header in &parts.headers {
+            append_header(&mut headers, header.0.as_str(), header.1.to_str()?);
+        }
+        Ok(Self {
+            body,
+            headers,
+            status: parts.status.as_u16(),
+            url,
+            version: parts.version.try_into()?,
+        })
+    }
+}
+
+impl TryFrom<HttpResponse> for http::Response<Vec<u8>> {
+    type Error = BoxError;
+
+    fn try_from(value: HttpResponse) -> Result<Self> {
+        let mut response =
+            http::Response::from_parts(value.parts()?, value.body);
+        *response.version_mut() = value.version.into();
+        Ok(response)
+    }
+}
+
+#[cfg(feature = "with-http-body")]
+impl HttpResponse {
+    /// Collects a response with any [`http_body::Body`] into an [`HttpResponse`],
+    /// buffering the full body in memory.
+    #[cfg_attr(docsrs, doc(cfg(feature = "with-http-body")))]
+    pub async fn from_body<B>(
+        response: http::Response<B>,
+        url: Url,
+    ) -> Result<Self>
+    where
+        B: http_body::Body,
+        B::Error: Into<BoxError>,
+    {
+        let (parts, body) = response.into_parts();
+        let body = http_body_util::BodyExt::collect(body)
+            .await
+            .map_err(Into::into)?
+            .to_bytes()
+            .to_vec();
+        Self::try_from((http::Response::from_parts(parts, body), url))
+    }
+}
+
 #[cfg(feature = "http-types")]
 impl TryFrom<http_types::Version> for HttpVersion {
     type Error = BoxError;
@@ -364,8 +1056,23 @@ pub use http_cache_semantics::CacheOptions;
 
 /// A closure that takes [`http::request::Parts`] and returns a [`String`].
 /// By default, the cache key is a combination of the request method and uri with a colon in between.
+/// The uri is put in the canonical form `scheme://host[:port]/path?query`, with the scheme and host
+/// lowercased and the port left out only when it is the default one for the scheme, so
+/// `http://h:80/` and `http://h/` share a key while `https://h:443/` never collides with them.
+/// Any typed extensions attached to the request are available through `parts.extensions`,
+/// for example to include a per-request tenant id in the key.
 pub type CacheKey = Arc<dyn Fn(&request::Parts) -> String + Send + Sync>;
 
+/// A closure that takes [`http::request::Parts`] and returns an optional
+/// [`String`] to use as the cache key, where [`None`] means the request should
+/// bypass the cache.
+pub type OptionalCacheKey =
+    Arc<dyn Fn(&request::Parts) -> Option<String> + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and returns whether the
+/// cache acts as a shared cache for the request.
+pub type SharedFn = Arc<dyn Fn(&request::Parts) -> bool + Send + Sync>;
+
 /// A closure that takes [`http::request::Parts`] and returns a [`CacheMode`]
 pub type CacheModeFn = Arc<dyn Fn(&request::Parts) -> CacheMode + Send + Sync>;
 
@@ -377,288 +1084,3223 @@ pub type CacheBust = Arc<
         + Sync,
 >;
 
-/// Can be used to override the default [`CacheOptions`] and cache key.
-/// The cache key is a closure that takes [`http::request::Parts`] and returns a [`String`].
-#[derive(Clone)]
-pub struct HttpCacheOptions {
-    /// Override the default cache options.
-    pub cache_options: Option<CacheOptions>,
-    /// Override the default cache key generator.
-    pub cache_key: Option<CacheKey>,
-    /// Override the default cache mode.
-    pub cache_mode_fn: Option<CacheModeFn>,
-    /// Bust the caches of the returned keys.
-    pub cache_bust: Option<CacheBust>,
-    /// Determines if the cache status headers should be added to the response.
-    pub cache_status_headers: bool,
+/// A closure that takes an [`HttpResponse`] and returns the [`CacheControl`]
+/// to store it with, or `None` to keep the upstream value. The directives are
+/// written back as the `Cache-Control` header, so directives [`CacheControl`]
+/// doesn't know about are dropped.
+pub type ResponseCacheControlFn =
+    Arc<dyn Fn(&HttpResponse) -> Option<CacheControl> + Send + Sync>;
+
+/// A closure applied to the [`http::request::Builder`] of a revalidation
+/// request before it is sent, for example to add authentication headers.
+/// The builder starts with the method, uri, version and headers of the
+/// request being revalidated.
+pub type RevalidationRequestFn =
+    Arc<dyn Fn(&mut request::Builder) + Send + Sync>;
+
+/// A closure that takes a request header name and value and returns the
+/// normalized value to use instead, or `None` to keep the value as is.
+pub type VaryNormalizeFn =
+    Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and the [`HttpResponse`]
+/// being cached, and returns metadata to store alongside it.
+pub type EntryMetadataFn = Arc<
+    dyn Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>
+        + Send
+        + Sync,
+>;
+
+/// A closure that takes [`http::request::Parts`] and the [`HttpResponse`]
+/// about to be cached, and returns whether it should be stored.
+pub type ShouldStoreFn =
+    Arc<dyn Fn(&request::Parts, &HttpResponse) -> bool + Send + Sync>;
+
+/// A closure that takes the cache key of a response and the result of
+/// attempting to store it.
+pub type OnStoreFn = Arc<dyn Fn(&str, StoreOutcome) + Send + Sync>;
+
+/// A closure that takes the value of a request header listed by `Vary` and
+/// returns the digest used for it in the keys of stored representations.
+pub type VaryHashFn = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// A closure that takes a request that bypassed the cache and why no cache
+/// key could be built for it.
+pub type OnKeyBypassFn = Arc<dyn Fn(&request::Parts, KeyBypass) + Send + Sync>;
+
+/// A closure that takes the cache key of a stale response that was served
+/// and the reason it was served.
+pub type OnStaleServedFn = Arc<dyn Fn(&str, StaleReason) + Send + Sync>;
+
+/// A closure that takes a cached [`HttpResponse`] and returns whether it may
+/// still be served.
+pub type ValidateCachedFn = Arc<dyn Fn(&HttpResponse) -> bool + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and returns the partition
+/// the request's cache entries belong to.
+pub type PartitionFn = Arc<dyn Fn(&request::Parts) -> String + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and the [`HttpResponse`]
+/// being cached, and returns its eviction priority.
+pub type PriorityFn =
+    Arc<dyn Fn(&request::Parts, &HttpResponse) -> u8 + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`], the [`HttpResponse`] and the
+/// configured [`CacheOptions`], and returns the policy the response is cached
+/// with.
+pub type PolicyFn = Arc<
+    dyn Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy
+        + Send
+        + Sync,
+>;
+
+/// A closure that takes an [`HttpResponse`] about to be stored and returns the
+/// response to store in its place.
+pub type TransformForStorageFn =
+    Arc<dyn Fn(HttpResponse) -> HttpResponse + Send + Sync>;
+
+/// A closure that takes the `200 OK` response to a revalidation request and
+/// returns whether it should replace the cached response.
+pub type AcceptRevalidationFn =
+    Arc<dyn Fn(&HttpResponse) -> bool + Send + Sync>;
+
+/// Controls how the components of the default cache key (the request method
+/// and uri) are joined together.
+///
+/// The default format joins the components with a colon and doesn't escape
+/// them, producing keys such as `GET:http://example.com/`. When `escape` is
+/// enabled, any occurrence of the delimiter characters or `%` within a
+/// component is percent-encoded, so two different sets of components can
+/// never produce the same key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKeyFormat {
+    /// The delimiter placed between the key components, must not be empty.
+    pub delimiter: String,
+    /// Whether the delimiter characters should be escaped within the components.
+    pub escape: bool,
 }
 
-impl Default for HttpCacheOptions {
+impl Default for CacheKeyFormat {
     fn default() -> Self {
-        Self {
-            cache_options: None,
-            cache_key: None,
-            cache_mode_fn: None,
-            cache_bust: None,
-            cache_status_headers: true,
+        Self { delimiter: ":".to_string(), escape: false }
+    }
+}
+
+impl CacheKeyFormat {
+    /// Create a new format using the provided delimiter with escaping enabled
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delimiter is empty, since the components of a key
+    /// couldn't be told apart without one.
+    pub fn new(delimiter: impl Into<String>) -> Self {
+        let delimiter = delimiter.into();
+        assert!(
+            !delimiter.is_empty(),
+            "CacheKeyFormat delimiter must not be empty"
+        );
+        Self { delimiter, escape: true }
+    }
+
+    /// Joins the components into a cache key
+    #[must_use]
+    pub fn format(&self, components: &[&str]) -> String {
+        if !self.escape {
+            return components.join(&self.delimiter);
+        }
+        components
+            .iter()
+            .map(|component| self.escape_component(component))
+            .collect::<Vec<_>>()
+            .join(&self.delimiter)
+    }
+
+    fn escape_component(&self, component: &str) -> String {
+        let mut escaped = String::with_capacity(component.len());
+        for c in component.chars() {
+            if c == '%' || self.delimiter.contains(c) {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    escaped.push_str(&format!("%{byte:02X}"));
+                }
+            } else {
+                escaped.push(c);
+            }
         }
+        escaped
     }
 }
 
-impl Debug for HttpCacheOptions {
+/// Counts the revalidation requests in flight, which
+/// [`HttpCacheOptions::max_background_revalidations`] is checked against.
+///
+/// The count is shared by every clone of the counter, so it applies across
+/// clones of the [`HttpCacheOptions`] it is set on.
+#[derive(Clone, Default)]
+pub struct RevalidationCounter {
+    in_flight: Arc<AtomicUsize>,
+    // Woken when a revalidation finishes, to take its slot of the limit.
+    waiting: Arc<Mutex<Vec<Waker>>>,
+}
+
+impl Debug for RevalidationCounter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HttpCacheOptions")
-            .field("cache_options", &self.cache_options)
-            .field("cache_key", &"Fn(&request::Parts) -> String")
-            .field("cache_mode_fn", &"Fn(&request::Parts) -> CacheMode")
-            .field("cache_bust", &"Fn(&request::Parts) -> Vec<String>")
-            .field("cache_status_headers", &self.cache_status_headers)
+        f.debug_struct("RevalidationCounter")
+            .field("in_flight", &self.in_flight())
             .finish()
     }
 }
 
-impl HttpCacheOptions {
+impl RevalidationCounter {
+    /// Returns the number of revalidations currently in flight
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    fn try_acquire(&self, max: usize) -> Option<RevalidationPermit> {
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                (in_flight < max).then_some(in_flight + 1)
+            })
+            .ok()
+            .map(|_| RevalidationPermit(self.clone()))
+    }
+
+    // Waits for a slot of the limit to free up. A limit of 0 is waited on as
+    // a limit of 1, so the wait always ends.
+    async fn acquire(&self, max: usize) -> RevalidationPermit {
+        let max = max.max(1);
+        future_util::poll_fn(|cx| {
+            if let Some(permit) = self.try_acquire(max) {
+                return Poll::Ready(permit);
+            }
+            self.waiting
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(cx.waker().clone());
+            // The slot may have been released before the waker was added.
+            match self.try_acquire(max) {
+                Some(permit) => Poll::Ready(permit),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+// Releases its slot of the limit when dropped, waking the revalidations
+// waiting for one.
+struct RevalidationPermit(RevalidationCounter);
+
+impl Drop for RevalidationPermit {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+        let waiting = std::mem::take(
+            &mut *self.0.waiting.lock().unwrap_or_else(|e| e.into_inner()),
+        );
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+/// Remembers the requests that recently failed, which
+/// [`HttpCacheOptions::error_cache_ttl`] answers retries with.
+///
+/// The failures are kept in memory rather than in the cache manager, so they
+/// never take the place of cached responses. Like [`RevalidationCounter`],
+/// they're shared by every clone.
+#[derive(Clone, Default)]
+pub struct FailedRequests {
+    failures: Arc<Mutex<HashMap<String, (HttpResponse, SystemTime)>>>,
+}
+
+impl Debug for FailedRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FailedRequests").field("len", &self.len()).finish()
+    }
+}
+
+impl FailedRequests {
+    /// Returns the number of failures remembered, including any that have
+    /// expired but haven't been dropped yet
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.failures().len()
+    }
+
+    /// Returns whether no failures are remembered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.failures().is_empty()
+    }
+
+    /// Forgets every failure
+    pub fn clear(&self) {
+        self.failures().clear();
+    }
+
+    fn failures(
+        &self,
+    ) -> MutexGuard<'_, HashMap<String, (HttpResponse, SystemTime)>> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Returns the failed response for the key while it hasn't expired.
+    fn get(&self, cache_key: &str, now: SystemTime) -> Option<HttpResponse> {
+        let mut failures = self.failures();
+        match failures.get(cache_key) {
+            Some((res, expires)) if *expires > now => Some(res.clone()),
+            Some(_) => {
+                failures.remove(cache_key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Remembers the failure until `expires`, dropping those that have
+    // expired so failures for keys that are never retried don't pile up.
+    fn insert(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        expires: SystemTime,
+    ) {
+        let now = SystemTime::now();
+        let mut failures = self.failures();
+        failures.retain(|_, (_, expires)| *expires > now);
+        failures.insert(cache_key, (res, expires));
+    }
+
+    fn remove(&self, cache_key: &str) {
+        self.failures().remove(cache_key);
+    }
+}
+
+/// Tracks the keys stored for each host, which
+/// [`HttpCacheOptions::max_entries_per_host`] evicts from.
+///
+/// Like [`RevalidationCounter`], the index is shared by every clone. It's kept
+/// in memory, so it only knows of the entries stored by this process since it
+/// was created.
+#[derive(Clone, Default)]
+pub struct HostIndex {
+    inner: Arc<Mutex<HostEntries>>,
+}
+
+#[derive(Default)]
+struct HostEntries {
+    // The keys of each host oldest first, along with their priority.
+    keys: HashMap<String, Vec<(String, u8)>>,
+    hosts: HashMap<String, String>,
+}
+
+impl Debug for HostIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostIndex")
+            .field("hosts", &self.lock().keys.len())
+            .finish()
+    }
+}
+
+impl HostIndex {
+    fn lock(&self) -> MutexGuard<'_, HostEntries> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Adds the key to the entries of the host, returning the ones evicted to
+    // keep it within `max_entries`. The key being added is never evicted.
+    fn insert(
+        &self,
+        host: &str,
+        (cache_key, priority): (&str, u8),
+        max_entries: usize,
+    ) -> Vec<String> {
+        let mut entries = self.lock();
+        let HostEntries { keys, hosts } = &mut *entries;
+        if let Some(previous) =
+            hosts.insert(cache_key.to_string(), host.to_string())
+        {
+            if let Some(host_keys) = keys.get_mut(&previous) {
+                host_keys.retain(|(key, _)| key != cache_key);
+            }
+        }
+        let host_keys = keys.entry(host.to_string()).or_default();
+        let mut evicted = Vec::new();
+        while host_keys.len() >= max_entries.max(1) {
+            let Some(oldest) = host_keys
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, priority))| *priority)
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            let (key, _) = host_keys.remove(oldest);
+            hosts.remove(&key);
+            evicted.push(key);
+        }
+        host_keys.push((cache_key.to_string(), priority));
+        evicted
+    }
+
+    fn remove(&self, cache_key: &str) {
+        let mut entries = self.lock();
+        let HostEntries { keys, hosts } = &mut *entries;
+        let Some(host) = hosts.remove(cache_key) else {
+            return;
+        };
+        if let Some(host_keys) = keys.get_mut(&host) {
+            host_keys.retain(|(key, _)| key != cache_key);
+            if host_keys.is_empty() {
+                keys.remove(&host);
+            }
+        }
+    }
+}
+
+/// Serializes the updates to the indexes of representations stored with
+/// [`HttpCacheOptions::cache_variants`], so concurrent stores of different
+/// representations don't drop each other from the index.
+///
+/// Like [`RevalidationCounter`], the lock is shared by every clone, but it
+/// only coordinates the stores made by this process.
+#[derive(Clone, Default)]
+pub struct VariantsLock(Arc<futures_util::lock::Mutex<()>>);
+
+impl Debug for VariantsLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("VariantsLock")
+    }
+}
+
+/// A glob pattern matched against the path of a request url.
+///
+/// `*` matches any run of characters within a single path segment, `**`
+/// matches across segments (`**/` also matching no segments at all) and `?`
+/// matches a single character other than `/`.
+/// All other characters match themselves.
+///
+/// ```
+/// use http_cache::GlobPattern;
+///
+/// let pattern = GlobPattern::new("/api/v1/static/*");
+/// assert!(pattern.matches("/api/v1/static/logo.png"));
+/// assert!(!pattern.matches("/api/v1/static/img/logo.png"));
+/// assert!(GlobPattern::new("/api/**").matches("/api/v1/static/img/logo.png"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobPattern {
+    pattern: String,
+}
+
+impl GlobPattern {
+    /// Create a new pattern
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self { pattern: pattern.into() }
+    }
+
+    /// Returns the pattern as provided
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Determines if the path matches the pattern
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool {
+        glob_match(self.pattern.as_bytes(), path.as_bytes())
+    }
+}
+
+impl From<&str> for GlobPattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        // `**/` may also match no segments at all
+        [b'*', b'*', b'/', rest @ ..] if glob_match(rest, path) => true,
+        [b'*', b'*', rest @ ..] => {
+            (0..=path.len()).any(|i| glob_match(rest, &path[i..]))
+        }
+        [b'*', rest @ ..] => {
+            let segment =
+                path.iter().position(|&b| b == b'/').unwrap_or(path.len());
+            (0..=segment).any(|i| glob_match(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => match path {
+            [b, tail @ ..] if *b != b'/' => glob_match(rest, tail),
+            _ => false,
+        },
+        [c, rest @ ..] => match path {
+            [b, tail @ ..] if b == c => glob_match(rest, tail),
+            _ => false,
+        },
+    }
+}
+
+/// Can be used to override the default [`CacheOptions`] and cache key.
+/// The cache key is a closure that takes [`http::request::Parts`] and returns a [`String`].
+#[derive(Clone)]
+pub struct HttpCacheOptions {
+    /// Override the default cache options.
+    pub cache_options: Option<CacheOptions>,
+    /// Override the default cache key generator.
+    pub cache_key: Option<CacheKey>,
+    /// Override the default cache mode.
+    pub cache_mode_fn: Option<CacheModeFn>,
+    /// Bust the caches of the returned keys.
+    pub cache_bust: Option<CacheBust>,
+    /// Determines if the cache status headers should be added to the response.
+    pub cache_status_headers: bool,
+    /// Determines if conditional requests made by the caller should be answered
+    /// from cache. When enabled, a request carrying `If-None-Match` or
+    /// `If-Modified-Since` validators that match a fresh cached response is
+    /// answered with a `304 Not Modified` without contacting the origin.
+    pub answer_client_conditionals: bool,
+    /// Determines how the default cache key is formatted.
+    /// Not used when `cache_key` is provided.
+    pub cache_key_format: CacheKeyFormat,
+    /// Determines if CORS preflight (`OPTIONS`) responses should be cached.
+    /// When the response has no `Cache-Control` header, `Access-Control-Max-Age`
+    /// is used as its freshness lifetime.
+    pub cache_preflight: bool,
+    /// Determines if responses without a `Content-Length` header, such as
+    /// chunked responses, should be cached. When disabled they are passed
+    /// through without being stored.
+    pub cache_unknown_length: bool,
+    /// Rewrite the `Cache-Control` header of responses before their cache
+    /// policy is built and they are stored.
+    pub response_cache_control_fn: Option<ResponseCacheControlFn>,
+    /// Determines if the `Surrogate-Control` header should be honored. When
+    /// enabled and the cache is shared, its `max-age` takes precedence over
+    /// `Cache-Control` for freshness. The header is always removed from
+    /// responses when enabled.
+    pub honor_surrogate_control: bool,
+    /// Determines if a shared cache may store responses carrying a
+    /// `Set-Cookie` header. Disabled by default so one user's session isn't
+    /// served to another, private caches always store them.
+    pub cache_set_cookie: bool,
+    /// Remember failed requests (a status of 500 or above, or a network error)
+    /// that had no cached response for this long, so that immediate retries
+    /// fail fast without contacting the origin. Retried network errors are
+    /// answered with a `504 Gateway Timeout`. A `Retry-After` header on the
+    /// failed response, in delta-seconds or as an HTTP-date, takes precedence
+    /// over this duration. The failures are remembered in
+    /// `failed_requests`, not in the cache manager.
+    pub error_cache_ttl: Option<Duration>,
+    /// The failed requests remembered for `error_cache_ttl`.
+    pub failed_requests: FailedRequests,
+    /// Select the cache mode by matching the request path against these
+    /// patterns, the first match wins. Requests matching none of them use the
+    /// cache's mode, use [`CacheMode::NoStore`] to skip caching for a pattern.
+    /// Takes precedence over the cache's mode but not over `cache_mode_fn`.
+    pub path_rules: Vec<(GlobPattern, CacheMode)>,
+    /// Customize the requests sent to the origin to revalidate stale cached
+    /// responses. Headers set on the builder are added to the request,
+    /// replacing any existing values.
+    pub revalidation_request_fn: Option<RevalidationRequestFn>,
+    /// Determines if an RFC 9211 `Cache-Status` header should be added to the
+    /// response, describing whether it was a hit, why the request was
+    /// forwarded to the origin and the remaining freshness lifetime.
+    pub add_cache_status: bool,
+    /// Serve a stale cached response if revalidating it with the origin takes
+    /// longer than this, instead of waiting for the origin. The revalidation
+    /// finishes in the background and updates the cached entry. It only
+    /// applies to middleware that provides a timer and can fetch and spawn
+    /// in the background, see [`Middleware::background_fetch`], and the
+    /// origin is waited for otherwise. Responses that must be revalidated
+    /// are never served stale.
+    ///
+    /// Only stale entries are revalidated, fresh ones are served without
+    /// contacting the origin, unless `background_revalidate_always` is set.
+    pub serve_stale_after: Option<Duration>,
+    /// Revalidate the cached response in the background on every hit,
+    /// regardless of its freshness, so the entry is kept warm while the
+    /// cached copy is served right away. Fresh hits in the default cache
+    /// mode start one revalidation each, which updates the entry when it
+    /// finishes. It only applies to middleware that can fetch and spawn in
+    /// the background, see [`Middleware::background_fetch`]. Hits are served
+    /// without revalidating while `max_background_revalidations` are in
+    /// flight. Stale hits are revalidated as usual.
+    pub background_revalidate_always: bool,
+    /// Normalize request header values before they are compared against the
+    /// `Vary` header of cached responses, so equivalent values such as
+    /// reordered `Accept-Encoding` lists select the same cached response.
+    /// Only the headers named by the response's `Vary` header are
+    /// normalized, with repeated lines joined into one comma separated
+    /// list. Requests revalidating a cached response send the normalized
+    /// values to the origin.
+    pub vary_normalize_fn: Option<VaryNormalizeFn>,
+    /// Store metadata, such as a trace id, alongside every response that is
+    /// cached. It can be read back with [`HttpCache::get_entry`] or
+    /// [`HttpCache::entry_metadata`], and the client middlewares attach it to
+    /// their responses as an [`EntryMetadata`] extension. The metadata is
+    /// kept in an entry of its own next to the cached response, and removed
+    /// along with it.
+    pub entry_metadata_fn: Option<EntryMetadataFn>,
+    /// Determines if a `Pragma: no-cache` request header forces cached
+    /// responses to be revalidated, as HTTP/1.0 clients expect. It only
+    /// applies to requests without a `Cache-Control` header.
+    pub honor_pragma: bool,
+    /// Decide whether a `200 OK` response to a revalidation request replaces
+    /// the cached response. When rejected, the cached response is kept and
+    /// served with a `111 Revalidation failed` warning.
+    pub accept_revalidation_fn: Option<AcceptRevalidationFn>,
+    /// Store the request that produced every response that is cached, as a
+    /// [`StoredRequest`] with its method, uri and the headers the response
+    /// varies on. It can be read back with [`HttpCache::get_entry`], and is
+    /// what [`HttpCache::revalidate_stale`] revalidates the response with.
+    pub store_request: bool,
+    /// Serve a stale response that must be revalidated when the origin can't
+    /// be reached, instead of returning the error. This deviates from the
+    /// spec, which forbids serving these responses stale under any
+    /// circumstance (https://www.rfc-editor.org/rfc/rfc9111#section-5.2.2.2),
+    /// in favor of availability. Error responses from the origin are not
+    /// affected.
+    pub override_must_revalidate_on_error: bool,
+    /// The largest response body, in bytes, that will be stored. Larger
+    /// responses are passed through without being cached.
+    pub max_body_size: Option<usize>,
+    /// Called with the cache key whenever a response is stored, or isn't
+    /// because it can't be cached, it's too large or the manager failed.
+    pub on_store: Option<OnStoreFn>,
+    /// Decide whether a response that could be cached is stored. When it
+    /// returns false the response is still served, it just isn't stored.
+    pub should_store_fn: Option<ShouldStoreFn>,
+    /// Treat fetched responses as if they were received at this time, rather
+    /// than now, making freshness deterministic for replays and tests. It
+    /// replaces the `Date` header of the response and is used as the
+    /// response time of its policy.
+    pub response_date_override: Option<SystemTime>,
+    /// Store every representation of a response that has a `Vary` header
+    /// separately, keyed by the request header values it was selected by,
+    /// instead of each one replacing the last. Header names are matched
+    /// case-insensitively and whitespace in values is collapsed, so values
+    /// that differ only in formatting share a key. Requests are then answered
+    /// with the representation matching their headers.
+    ///
+    /// Each representation is stored once, under a key derived from the
+    /// request's cache key. The request's key itself only holds an index of
+    /// them, which is kept until its representations are deleted, regardless
+    /// of their freshness. The representations can be listed with
+    /// [`HttpCache::variants`].
+    pub cache_variants: bool,
+    /// Serializes the updates to the indexes of `cache_variants`.
+    pub variants_lock: VariantsLock,
+    /// The most entries stored for any one host. Once a host is over the cap
+    /// its oldest entry is evicted, so one host can't fill the entire cache.
+    /// See `priority_fn` to keep some entries longer than others. A cap of 0
+    /// is treated as 1, keeping only the entry stored last.
+    ///
+    /// The entries are counted by `host_index`, in memory, so entries stored
+    /// by other processes or before it was created aren't counted, and
+    /// entries a manager expires on its own keep their place until they're
+    /// evicted.
+    pub max_entries_per_host: Option<usize>,
+    /// Tracks the entries stored for each host for `max_entries_per_host`.
+    pub host_index: HostIndex,
+    /// Override the default cache key generator with one that can decide a
+    /// request shouldn't be cached at all by returning [`None`], in which
+    /// case the request bypasses the cache. Takes precedence over
+    /// `cache_key`.
+    pub optional_cache_key: Option<OptionalCacheKey>,
+    /// Limit how many stale responses are revalidated with the origin at
+    /// once. While the limit is reached, stale responses that may be served
+    /// stale, within the response's `stale-while-revalidate` window or the
+    /// request's `max-stale`, are served without contacting the origin.
+    /// Other stale responses wait for a revalidation to finish before
+    /// they're revalidated, so no more than this many are ever in flight.
+    /// With a limit of 0, responses that can't be served stale are
+    /// revalidated one at a time.
+    pub max_background_revalidations: Option<usize>,
+    /// The count of revalidations in flight that
+    /// `max_background_revalidations` is checked against.
+    pub revalidations: RevalidationCounter,
+    /// Decide per request whether the cache acts as a shared or a private
+    /// cache, overriding `CacheOptions::shared`. Responses cached for one
+    /// kind of request are served to the other, so the cache key should tell
+    /// them apart.
+    pub shared_fn: Option<SharedFn>,
+    /// Request methods whose responses may be cached, defaults to GET and
+    /// HEAD. Requests using any other method bypass the cache, unless the
+    /// mode is [`CacheMode::IgnoreRules`].
+    pub cacheable_methods: Vec<Method>,
+    /// Freshness lifetime given to responses that carry no explicit one,
+    /// meaning no `max-age` or `s-maxage` directive and no `Expires` header.
+    /// It's added to the `Cache-Control` header as a `max-age` directive, so
+    /// directives such as `no-store` and `private` still apply.
+    pub default_max_age: Option<Duration>,
+    /// Bypass the cache entirely for requests carrying this header, or only
+    /// when it has the given value. Bypassed requests neither read from nor
+    /// write to the cache, and don't invalidate what's stored.
+    pub bypass_header: Option<(HeaderName, Option<String>)>,
+    /// Only store the headers needed to cache and revalidate a response, and
+    /// to interpret its body: `Cache-Control`, `ETag`, `Last-Modified`,
+    /// `Vary`, `Content-Type`, `Content-Encoding`, `Content-Language`,
+    /// `Content-Length`, `Content-Range`, `Location`, `Date`, `Expires` and
+    /// `Age`. Responses served from cache only carry these headers. Preflight
+    /// responses, and responses with `no-transform` while
+    /// `honor_no_transform` is enabled, are always stored in full.
+    pub minimal_headers: bool,
+    /// Called with the cache key whenever a stale response is served, for
+    /// example to alert on a degraded origin.
+    pub on_stale_served: Option<OnStaleServedFn>,
+    /// Hash the request header values that the keys of representations
+    /// stored with `cache_variants` are made of, so headers like `Cookie`
+    /// never appear in plain text in the keys. Defaults to the hex encoded
+    /// SHA-256 digest of the value, which is stable across Rust releases
+    /// so keys stay valid in persistent caches.
+    pub vary_hash_fn: Option<VaryHashFn>,
+    /// Cached bodies larger than this many bytes are decompressed for clients
+    /// that don't accept their encoding on a blocking thread, so large bodies
+    /// don't stall the async runtime. Requires the `content-encoding`
+    /// feature, defaults to 64 KiB.
+    pub compression_offload_threshold: usize,
+    /// Build the policy of fetched responses with this closure instead of
+    /// from their caching headers, for full control over whether they are
+    /// stored and how long they stay fresh. CORS preflight responses and
+    /// [`HttpCache::touch`] keep building their own policies.
+    pub policy_fn: Option<PolicyFn>,
+    /// Assign a priority to every stored response for `max_entries_per_host`,
+    /// which evicts the oldest entry among those of the host with the lowest
+    /// priority. Entries stored without a priority have a priority of 0. The
+    /// priority is only kept in `host_index`, not stored with the entry, so
+    /// it has no effect on how the cache manager evicts entries.
+    pub priority_fn: Option<PriorityFn>,
+    /// Store responses the client reached by following redirects under the
+    /// key of the same request for their final url, instead of the key of
+    /// the request that was made. The request method is kept, since the
+    /// redirects that were followed, and whether they changed it, aren't
+    /// known. Requests for the original url then always go
+    /// to the origin, while requests for the final url are answered from
+    /// cache. Defaults to false, keying by the request that was made.
+    pub key_by_final_url: bool,
+    /// Partition every cache key by the string this closure returns for the
+    /// request, such as a tenant id. The partition is prefixed to the key
+    /// along with its length, joined by the delimiter of `cache_key_format`,
+    /// so requests in different partitions never share entries, even with
+    /// identical urls or custom cache keys. The keys of the metadata and
+    /// representations stored alongside entries have the same prefix, and
+    /// `max_entries_per_host` caps the entries of each host within each
+    /// partition.
+    pub partition_fn: Option<PartitionFn>,
+    /// Store `301 Moved Permanently` and `308 Permanent Redirect` responses,
+    /// giving those without an explicit freshness lifetime this one, in the
+    /// same way as `default_max_age`. Only redirects the client hands back
+    /// to the middleware can be stored, so the client mustn't follow them
+    /// itself. Defaults to `None`, storing no redirects.
+    pub permanent_redirect_ttl: Option<Duration>,
+    /// Check a cached response before it's used to answer a request, such as
+    /// by verifying a signature. When it returns false the entry is deleted,
+    /// along with its other representations, and the request is handled as
+    /// a miss.
+    pub validate_cached_fn: Option<ValidateCachedFn>,
+    /// The fraction of the time since a response's `Last-Modified` date that
+    /// it's considered fresh for, when it has no explicit freshness lifetime.
+    /// When this or `heuristic_max_age` is set, the lifetime is added to the
+    /// `Cache-Control` header as a `max-age` directive, like
+    /// `default_max_age`. Defaults to `None`, leaving the heuristic to the
+    /// cache policy, which uses the `cache_heuristic` of the cache options,
+    /// 10% by default.
+    pub heuristic_fraction: Option<f32>,
+    /// The longest heuristic freshness lifetime given to a response, see
+    /// `heuristic_fraction`.
+    pub heuristic_max_age: Option<Duration>,
+    /// Whether responses without explicit freshness directives are treated
+    /// as stale, so they're revalidated before use, instead of being given a
+    /// heuristic freshness lifetime from their `Last-Modified` date. This
+    /// takes precedence over `heuristic_fraction` and `heuristic_max_age`.
+    /// Defaults to `false`.
+    pub disable_heuristics: bool,
+    /// The oldest a cached entry may be. Entries older than this are deleted
+    /// when they're looked up and fetched again, even if they're still
+    /// fresh. The age includes the response's `Age` header, so it's never
+    /// less than the time the entry has been stored for. Defaults to `None`.
+    pub hard_max_age: Option<Duration>,
+    /// Whether a `HEAD` response from the origin updates the headers and
+    /// freshness of the cached `GET` response for the same resource, like a
+    /// `304` would, when their validators match. The cached body is kept.
+    /// Defaults to `false`.
+    pub head_refreshes_get: bool,
+    /// Called with the request whenever it bypasses the cache because
+    /// `optional_cache_key` returned [`None`] or a cache key closure
+    /// panicked. A panicking closure never fails the request, it's fetched
+    /// without the cache instead. The panic is still reported by the panic
+    /// hook, which prints its message by default, and builds with
+    /// `panic = "abort"` still abort.
+    pub on_key_bypass: Option<OnKeyBypassFn>,
+    /// Whether the `Content-Length` header of every response served for a
+    /// cached entry is set to the length of the body that's served, whether
+    /// or not the body was transformed. Decompressed bodies always have it
+    /// updated when it's present. Responses to `HEAD` requests are left as
+    /// they are. Defaults to `false`.
+    pub recompute_content_length: bool,
+    /// Transforms responses right before they're stored, for example to
+    /// minify a body or redact fields. It runs after `minimal_headers`. The
+    /// response served for the request that stored it is the original, later
+    /// hits serve the transformed copy. The transform is also applied to the
+    /// response the cache policy is built from, and to revalidated entries
+    /// when they're stored again, so it should be idempotent.
+    pub transform_for_storage_fn: Option<TransformForStorageFn>,
+    /// Determines if responses with the `no-transform` directive are served
+    /// and stored as they are, skipping the decompression done with the
+    /// `content-encoding` feature, `minimal_headers` and
+    /// `transform_for_storage_fn`. Defaults to `true`.
+    pub honor_no_transform: bool,
+}
+
+impl Default for HttpCacheOptions {
+    fn default() -> Self {
+        Self {
+            cache_options: None,
+            cache_key: None,
+            cache_mode_fn: None,
+            cache_bust: None,
+            cache_status_headers: true,
+            answer_client_conditionals: false,
+            cache_key_format: CacheKeyFormat::default(),
+            cache_preflight: false,
+            cache_unknown_length: true,
+            response_cache_control_fn: None,
+            honor_surrogate_control: false,
+            cache_set_cookie: false,
+            error_cache_ttl: None,
+            failed_requests: FailedRequests::default(),
+            path_rules: Vec::new(),
+            revalidation_request_fn: None,
+            add_cache_status: false,
+            serve_stale_after: None,
+            background_revalidate_always: false,
+            vary_normalize_fn: None,
+            entry_metadata_fn: None,
+            honor_pragma: true,
+            accept_revalidation_fn: None,
+            store_request: false,
+            override_must_revalidate_on_error: false,
+            max_body_size: None,
+            on_store: None,
+            should_store_fn: None,
+            response_date_override: None,
+            cache_variants: false,
+            variants_lock: VariantsLock::default(),
+            max_entries_per_host: None,
+            host_index: HostIndex::default(),
+            optional_cache_key: None,
+            max_background_revalidations: None,
+            revalidations: RevalidationCounter::default(),
+            shared_fn: None,
+            cacheable_methods: vec![Method::GET, Method::HEAD],
+            default_max_age: None,
+            bypass_header: None,
+            minimal_headers: false,
+            on_stale_served: None,
+            vary_hash_fn: None,
+            compression_offload_threshold: 64 * 1024,
+            policy_fn: None,
+            priority_fn: None,
+            key_by_final_url: false,
+            partition_fn: None,
+            permanent_redirect_ttl: None,
+            validate_cached_fn: None,
+            heuristic_fraction: None,
+            heuristic_max_age: None,
+            disable_heuristics: false,
+            hard_max_age: None,
+            head_refreshes_get: false,
+            on_key_bypass: None,
+            recompute_content_length: false,
+            transform_for_storage_fn: None,
+            honor_no_transform: true,
+        }
+    }
+}
+
+impl Debug for HttpCacheOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpCacheOptions")
+            .field("cache_options", &self.cache_options)
+            .field("cache_key", &"Fn(&request::Parts) -> String")
+            .field("cache_mode_fn", &"Fn(&request::Parts) -> CacheMode")
+            .field("cache_bust", &"Fn(&request::Parts) -> Vec<String>")
+            .field("cache_status_headers", &self.cache_status_headers)
+            .field(
+                "answer_client_conditionals",
+                &self.answer_client_conditionals,
+            )
+            .field("cache_key_format", &self.cache_key_format)
+            .field("cache_preflight", &self.cache_preflight)
+            .field("cache_unknown_length", &self.cache_unknown_length)
+            .field(
+                "response_cache_control_fn",
+                &"Fn(&HttpResponse) -> Option<CacheControl>",
+            )
+            .field("honor_surrogate_control", &self.honor_surrogate_control)
+            .field("cache_set_cookie", &self.cache_set_cookie)
+            .field("error_cache_ttl", &self.error_cache_ttl)
+            .field("failed_requests", &self.failed_requests)
+            .field("path_rules", &self.path_rules)
+            .field("revalidation_request_fn", &"Fn(&mut request::Builder)")
+            .field("add_cache_status", &self.add_cache_status)
+            .field("serve_stale_after", &self.serve_stale_after)
+            .field(
+                "background_revalidate_always",
+                &self.background_revalidate_always,
+            )
+            .field("vary_normalize_fn", &"Fn(&str, &str) -> Option<String>")
+            .field("entry_metadata_fn", &"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>")
+            .field("honor_pragma", &self.honor_pragma)
+            .field("accept_revalidation_fn", &"Fn(&HttpResponse) -> bool")
+            .field("store_request", &self.store_request)
+            .field("override_must_revalidate_on_error", &self.override_must_revalidate_on_error)
+            .field("max_body_size", &self.max_body_size)
+            .field("on_store", &"Fn(&str, StoreOutcome)")
+            .field("should_store_fn", &"Fn(&request::Parts, &HttpResponse) -> bool")
+            .field("response_date_override", &self.response_date_override)
+            .field("cache_variants", &self.cache_variants)
+            .field("variants_lock", &self.variants_lock)
+            .field("max_entries_per_host", &self.max_entries_per_host)
+            .field("host_index", &self.host_index)
+            .field("optional_cache_key", &"Fn(&request::Parts) -> Option<String>")
+            .field("max_background_revalidations", &self.max_background_revalidations)
+            .field("revalidations", &self.revalidations)
+            .field("shared_fn", &"Fn(&request::Parts) -> bool")
+            .field("cacheable_methods", &self.cacheable_methods)
+            .field("default_max_age", &self.default_max_age)
+            .field("bypass_header", &self.bypass_header)
+            .field("minimal_headers", &self.minimal_headers)
+            .field("on_stale_served", &"Fn(&str, StaleReason)")
+            .field("vary_hash_fn", &"Fn(&str) -> String")
+            .field("compression_offload_threshold", &self.compression_offload_threshold)
+            .field("policy_fn", &"Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy")
+            .field("priority_fn", &"Fn(&request::Parts, &HttpResponse) -> u8")
+            .field("key_by_final_url", &self.key_by_final_url)
+            .field("partition_fn", &"Fn(&request::Parts) -> String")
+            .field("permanent_redirect_ttl", &self.permanent_redirect_ttl)
+            .field("validate_cached_fn", &"Fn(&HttpResponse) -> bool")
+            .field("heuristic_fraction", &self.heuristic_fraction)
+            .field("heuristic_max_age", &self.heuristic_max_age)
+            .field("disable_heuristics", &self.disable_heuristics)
+            .field("hard_max_age", &self.hard_max_age)
+            .field("head_refreshes_get", &self.head_refreshes_get)
+            .field("on_key_bypass", &"Fn(&request::Parts, KeyBypass)")
+            .field("recompute_content_length", &self.recompute_content_length)
+            .field("transform_for_storage_fn", &"Fn(HttpResponse) -> HttpResponse")
+            .field("honor_no_transform", &self.honor_no_transform)
+            .finish()
+    }
+}
+
+impl HttpCacheOptions {
+    /// Returns a builder starting from the default options
+    pub fn builder() -> HttpCacheOptionsBuilder {
+        HttpCacheOptionsBuilder::new()
+    }
+
+    // Falls back to the default key when a cache key closure panics, those
+    // requests are kept from using the cache by `can_cache_request`.
     fn create_cache_key(
         &self,
         parts: &request::Parts,
         override_method: Option<&str>,
     ) -> String {
-        if let Some(cache_key) = &self.cache_key {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            self.partitioned_cache_key(parts, override_method)
+        }))
+        .unwrap_or_else(|_| self.default_cache_key(parts, override_method))
+    }
+
+    fn partitioned_cache_key(
+        &self,
+        parts: &request::Parts,
+        override_method: Option<&str>,
+    ) -> String {
+        let cache_key = self.base_cache_key(parts, override_method);
+        match &self.partition_fn {
+            // The length prefix keeps partitions apart even when one
+            // partition is a prefix of another. It's the length of the
+            // partition as it appears in the key, once escaped.
+            Some(partition_fn) => {
+                let partition = partition_fn(parts);
+                let len = if self.cache_key_format.escape {
+                    self.cache_key_format.escape_component(&partition).len()
+                } else {
+                    partition.len()
+                };
+                let prefix = self.cache_key_format.format(&[
+                    &len.to_string(),
+                    &partition,
+                    "",
+                ]);
+                format!("{prefix}{cache_key}")
+            }
+            None => cache_key,
+        }
+    }
+
+    // Splits the key into the prefix of the partition it was made in, if it
+    // has one, and the rest of the key.
+    fn split_partition<'a>(&self, cache_key: &'a str) -> (&'a str, &'a str) {
+        let delimiter = self.cache_key_format.delimiter.as_str();
+        let prefix_len = self.partition_fn.as_ref().and_then(|_| {
+            let (len, rest) = cache_key.split_once(delimiter)?;
+            let len: usize = len.parse().ok()?;
+            rest.get(len..)?.strip_prefix(delimiter)?;
+            Some(cache_key.len() - rest.len() + len + delimiter.len())
+        });
+        match prefix_len {
+            Some(prefix_len) => cache_key.split_at(prefix_len),
+            None => ("", cache_key),
+        }
+    }
+
+    // The key of an entry the cache keeps alongside the one stored under the
+    // key, in the same partition.
+    fn sidecar_key(
+        &self,
+        kind: &str,
+        cache_key: &str,
+        rest: &[&str],
+    ) -> String {
+        let (partition, cache_key) = self.split_partition(cache_key);
+        let components: Vec<&str> =
+            [kind, cache_key].into_iter().chain(rest.iter().copied()).collect();
+        format!("{partition}{}", self.cache_key_format.format(&components))
+    }
+
+    // Builds the cache key for the request, or why it can't be built without
+    // the key closures panicking.
+    fn check_cache_key(
+        &self,
+        parts: &request::Parts,
+    ) -> std::result::Result<String, KeyBypass> {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(optional_cache_key) = &self.optional_cache_key {
+                if optional_cache_key(parts).is_none() {
+                    return Err(KeyBypass::NoKey);
+                }
+            }
+            Ok(self.partitioned_cache_key(parts, None))
+        }))
+        .unwrap_or(Err(KeyBypass::Panicked))
+    }
+
+    fn default_cache_key(
+        &self,
+        parts: &request::Parts,
+        override_method: Option<&str>,
+    ) -> String {
+        self.cache_key_format.format(&[
+            override_method.unwrap_or_else(|| parts.method.as_str()),
+            &canonical_uri(&parts.uri),
+        ])
+    }
+
+    fn base_cache_key(
+        &self,
+        parts: &request::Parts,
+        override_method: Option<&str>,
+    ) -> String {
+        if let Some(cache_key) = self
+            .optional_cache_key
+            .as_ref()
+            .and_then(|optional_cache_key| optional_cache_key(parts))
+        {
+            cache_key
+        } else if let Some(cache_key) = &self.cache_key {
             cache_key(parts)
         } else {
-            format!(
-                "{}:{}",
-                override_method.unwrap_or_else(|| parts.method.as_str()),
-                parts.uri
-            )
+            self.default_cache_key(parts, override_method)
+        }
+    }
+}
+
+/// Builds [`HttpCacheOptions`] starting from the defaults.
+///
+/// ```
+/// use http_cache::HttpCacheOptions;
+///
+/// let options = HttpCacheOptions::builder()
+///     .cache_status_headers(false)
+///     .cache_key(|parts| parts.uri.to_string())
+///     .build();
+/// assert!(!options.cache_status_headers);
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct HttpCacheOptionsBuilder {
+    options: HttpCacheOptions,
+}
+
+impl HttpCacheOptionsBuilder {
+    /// Create a new builder with the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the default cache options.
+    pub fn cache_options(mut self, cache_options: CacheOptions) -> Self {
+        self.options.cache_options = Some(cache_options);
+        self
+    }
+
+    /// Override the default cache key generator.
+    pub fn cache_key(
+        mut self,
+        cache_key: impl Fn(&request::Parts) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.options.cache_key = Some(Arc::new(cache_key));
+        self
+    }
+
+    /// Override the default cache mode.
+    pub fn cache_mode_fn(
+        mut self,
+        cache_mode_fn: impl Fn(&request::Parts) -> CacheMode + Send + Sync + 'static,
+    ) -> Self {
+        self.options.cache_mode_fn = Some(Arc::new(cache_mode_fn));
+        self
+    }
+
+    /// Bust the caches of the returned keys.
+    pub fn cache_bust(
+        mut self,
+        cache_bust: impl Fn(&request::Parts, &Option<CacheKey>, &str) -> Vec<String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.cache_bust = Some(Arc::new(cache_bust));
+        self
+    }
+
+    /// Determines if the cache status headers should be added to the response.
+    pub fn cache_status_headers(mut self, enabled: bool) -> Self {
+        self.options.cache_status_headers = enabled;
+        self
+    }
+
+    /// Determines if conditional requests made by the caller should be
+    /// answered from cache.
+    pub fn answer_client_conditionals(mut self, enabled: bool) -> Self {
+        self.options.answer_client_conditionals = enabled;
+        self
+    }
+
+    /// Determines how the default cache key is formatted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format's delimiter is empty.
+    pub fn cache_key_format(mut self, format: CacheKeyFormat) -> Self {
+        assert!(
+            !format.delimiter.is_empty(),
+            "CacheKeyFormat delimiter must not be empty"
+        );
+        self.options.cache_key_format = format;
+        self
+    }
+
+    /// Determines if CORS preflight (`OPTIONS`) responses should be cached.
+    pub fn cache_preflight(mut self, enabled: bool) -> Self {
+        self.options.cache_preflight = enabled;
+        self
+    }
+
+    /// Determines if responses without a `Content-Length` header should be
+    /// cached.
+    pub fn cache_unknown_length(mut self, enabled: bool) -> Self {
+        self.options.cache_unknown_length = enabled;
+        self
+    }
+
+    /// Rewrite the `Cache-Control` header of responses before they are stored.
+    pub fn response_cache_control_fn(
+        mut self,
+        response_cache_control_fn: impl Fn(&HttpResponse) -> Option<CacheControl>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.response_cache_control_fn =
+            Some(Arc::new(response_cache_control_fn));
+        self
+    }
+
+    /// Determines if the `Surrogate-Control` header should be honored.
+    pub fn honor_surrogate_control(mut self, enabled: bool) -> Self {
+        self.options.honor_surrogate_control = enabled;
+        self
+    }
+
+    /// Determines if a shared cache may store responses carrying a
+    /// `Set-Cookie` header.
+    pub fn cache_set_cookie(mut self, enabled: bool) -> Self {
+        self.options.cache_set_cookie = enabled;
+        self
+    }
+
+    /// Remember failed requests for the provided duration.
+    pub fn error_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.options.error_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Use the provided mode for requests whose path matches the pattern.
+    /// Rules are evaluated in the order they were added.
+    pub fn path_rule(
+        mut self,
+        pattern: impl Into<GlobPattern>,
+        mode: CacheMode,
+    ) -> Self {
+        self.options.path_rules.push((pattern.into(), mode));
+        self
+    }
+
+    /// Customize the requests sent to revalidate stale cached responses.
+    pub fn revalidation_request_fn(
+        mut self,
+        revalidation_request_fn: impl Fn(&mut request::Builder)
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.revalidation_request_fn =
+            Some(Arc::new(revalidation_request_fn));
+        self
+    }
+
+    /// Determines if an RFC 9211 `Cache-Status` header should be added to
+    /// the response.
+    pub fn add_cache_status(mut self, enabled: bool) -> Self {
+        self.options.add_cache_status = enabled;
+        self
+    }
+
+    /// Serve a stale cached response if revalidating it takes longer than
+    /// the provided duration.
+    pub fn serve_stale_after(mut self, timeout: Duration) -> Self {
+        self.options.serve_stale_after = Some(timeout);
+        self
+    }
+
+    /// Revalidate cached responses in the background on every hit.
+    pub fn background_revalidate_always(mut self, enabled: bool) -> Self {
+        self.options.background_revalidate_always = enabled;
+        self
+    }
+
+    /// Normalize request header values before they are matched against the
+    /// `Vary` header of cached responses.
+    pub fn vary_normalize_fn(
+        mut self,
+        vary_normalize_fn: impl Fn(&str, &str) -> Option<String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.vary_normalize_fn = Some(Arc::new(vary_normalize_fn));
+        self
+    }
+
+    /// Store metadata alongside every response that is cached.
+    pub fn entry_metadata_fn(
+        mut self,
+        entry_metadata_fn: impl Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.entry_metadata_fn = Some(Arc::new(entry_metadata_fn));
+        self
+    }
+
+    /// Determines if a `Pragma: no-cache` request header forces cached
+    /// responses to be revalidated.
+    pub fn honor_pragma(mut self, enabled: bool) -> Self {
+        self.options.honor_pragma = enabled;
+        self
+    }
+
+    /// Decide whether a `200 OK` response to a revalidation request replaces
+    /// the cached response.
+    pub fn accept_revalidation_fn(
+        mut self,
+        accept_revalidation_fn: impl Fn(&HttpResponse) -> bool
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.accept_revalidation_fn =
+            Some(Arc::new(accept_revalidation_fn));
+        self
+    }
+
+    /// Store the request that produced every response that is cached.
+    pub fn store_request(mut self, enabled: bool) -> Self {
+        self.options.store_request = enabled;
+        self
+    }
+
+    /// Serve a stale response that must be revalidated when the origin can't
+    /// be reached, against the spec.
+    pub fn override_must_revalidate_on_error(mut self, enabled: bool) -> Self {
+        self.options.override_must_revalidate_on_error = enabled;
+        self
+    }
+
+    /// The largest response body, in bytes, that will be stored.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.options.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Called with the cache key whenever a response is stored or skipped.
+    pub fn on_store(
+        mut self,
+        on_store: impl Fn(&str, StoreOutcome) + Send + Sync + 'static,
+    ) -> Self {
+        self.options.on_store = Some(Arc::new(on_store));
+        self
+    }
+
+    /// Decide whether a response that could be cached is stored.
+    pub fn should_store_fn(
+        mut self,
+        should_store_fn: impl Fn(&request::Parts, &HttpResponse) -> bool
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.should_store_fn = Some(Arc::new(should_store_fn));
+        self
+    }
+
+    /// Treat fetched responses as if they were received at this time.
+    pub fn response_date_override(mut self, date: SystemTime) -> Self {
+        self.options.response_date_override = Some(date);
+        self
+    }
+
+    /// Store every representation of a response that has a `Vary` header
+    /// separately.
+    pub fn cache_variants(mut self, enabled: bool) -> Self {
+        self.options.cache_variants = enabled;
+        self
+    }
+
+    /// The most entries stored for any one host. A cap of 0 is treated as 1.
+    pub fn max_entries_per_host(mut self, max_entries: usize) -> Self {
+        self.options.max_entries_per_host = Some(max_entries);
+        self
+    }
+
+    /// Override the default cache key generator with one that can bypass the
+    /// cache for a request by returning [`None`].
+    pub fn optional_cache_key(
+        mut self,
+        optional_cache_key: impl Fn(&request::Parts) -> Option<String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.optional_cache_key = Some(Arc::new(optional_cache_key));
+        self
+    }
+
+    /// Cache the responses to each `Accept` header separately, for content
+    /// negotiated resources. Requests without an `Accept` header, or
+    /// accepting a wildcard media range such as `*/*` that could be answered
+    /// with any representation, bypass the cache.
+    ///
+    /// This sets `optional_cache_key` to the default key with the `Accept`
+    /// header appended, formatted with the `cache_key_format` set so far.
+    pub fn vary_on_accept(self) -> Self {
+        let format = self.options.cache_key_format.clone();
+        self.optional_cache_key(move |parts| {
+            let accept = canonical_header_value(parts, ACCEPT.as_str());
+            let is_wildcard = |range: &str| {
+                range
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .ends_with("/*")
+            };
+            if accept.is_empty() || accept.split(',').any(is_wildcard) {
+                return None;
+            }
+            Some(format.format(&[
+                parts.method.as_str(),
+                &canonical_uri(&parts.uri),
+                &accept,
+            ]))
+        })
+    }
+
+    /// Limit how many stale responses are revalidated with the origin at
+    /// once, serving those that allow it stale while the limit is reached.
+    pub fn max_background_revalidations(mut self, max: usize) -> Self {
+        self.options.max_background_revalidations = Some(max);
+        self
+    }
+
+    /// Decide per request whether the cache acts as a shared or a private
+    /// cache.
+    pub fn shared_fn(
+        mut self,
+        shared_fn: impl Fn(&request::Parts) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.options.shared_fn = Some(Arc::new(shared_fn));
+        self
+    }
+
+    /// Sets the request methods whose responses may be cached.
+    pub fn cacheable_methods(
+        mut self,
+        methods: impl IntoIterator<Item = Method>,
+    ) -> Self {
+        self.options.cacheable_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Sets the freshness lifetime given to responses that carry no
+    /// explicit one.
+    pub fn default_max_age(mut self, max_age: Duration) -> Self {
+        self.options.default_max_age = Some(max_age);
+        self
+    }
+
+    /// Bypass the cache for requests carrying the header, or only when it
+    /// has the given value.
+    pub fn bypass_header(
+        mut self,
+        name: HeaderName,
+        value: Option<&str>,
+    ) -> Self {
+        self.options.bypass_header = Some((name, value.map(str::to_string)));
+        self
+    }
+
+    /// Only store the headers needed to cache and revalidate a response.
+    pub fn minimal_headers(mut self, minimal_headers: bool) -> Self {
+        self.options.minimal_headers = minimal_headers;
+        self
+    }
+
+    /// Called with the cache key whenever a stale response is served.
+    pub fn on_stale_served(
+        mut self,
+        on_stale_served: impl Fn(&str, StaleReason) + Send + Sync + 'static,
+    ) -> Self {
+        self.options.on_stale_served = Some(Arc::new(on_stale_served));
+        self
+    }
+
+    /// Hash the request header values used in the keys of representations
+    /// stored with `cache_variants`, instead of their SHA-256 digests.
+    pub fn vary_hash_fn(
+        mut self,
+        vary_hash_fn: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.options.vary_hash_fn = Some(Arc::new(vary_hash_fn));
+        self
+    }
+
+    /// Set the size above which bodies are decompressed on a blocking thread.
+    pub fn compression_offload_threshold(mut self, threshold: usize) -> Self {
+        self.options.compression_offload_threshold = threshold;
+        self
+    }
+
+    /// Set a closure that builds the policy of fetched responses.
+    pub fn policy_fn(
+        mut self,
+        policy_fn: impl Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.policy_fn = Some(Arc::new(policy_fn));
+        self
+    }
+
+    /// Set a closure that assigns the priority `max_entries_per_host` evicts
+    /// stored responses by.
+    pub fn priority_fn(
+        mut self,
+        priority_fn: impl Fn(&request::Parts, &HttpResponse) -> u8
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.priority_fn = Some(Arc::new(priority_fn));
+        self
+    }
+
+    /// Determines if redirected responses are keyed by their final url.
+    pub fn key_by_final_url(mut self, key_by_final_url: bool) -> Self {
+        self.options.key_by_final_url = key_by_final_url;
+        self
+    }
+
+    /// Set a closure that returns the partition of each request's entries.
+    pub fn partition_fn(
+        mut self,
+        partition_fn: impl Fn(&request::Parts) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.options.partition_fn = Some(Arc::new(partition_fn));
+        self
+    }
+
+    /// Set the freshness lifetime of permanent redirects without one.
+    pub fn permanent_redirect_ttl(mut self, ttl: Duration) -> Self {
+        self.options.permanent_redirect_ttl = Some(ttl);
+        self
+    }
+
+    /// Set a closure that checks cached responses before they're served.
+    pub fn validate_cached_fn(
+        mut self,
+        validate_cached_fn: impl Fn(&HttpResponse) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.options.validate_cached_fn = Some(Arc::new(validate_cached_fn));
+        self
+    }
+
+    /// Set the fraction of the time since it was last modified that a
+    /// response without an explicit freshness lifetime stays fresh for.
+    pub fn heuristic_fraction(mut self, fraction: f32) -> Self {
+        self.options.heuristic_fraction = Some(fraction);
+        self
+    }
+
+    /// Set the longest heuristic freshness lifetime given to a response.
+    pub fn heuristic_max_age(mut self, max_age: Duration) -> Self {
+        self.options.heuristic_max_age = Some(max_age);
+        self
+    }
+
+    /// Set whether responses without explicit freshness directives are
+    /// treated as stale rather than given a heuristic freshness lifetime.
+    pub fn disable_heuristics(mut self, enabled: bool) -> Self {
+        self.options.disable_heuristics = enabled;
+        self
+    }
+
+    /// Set the oldest a cached entry may be, regardless of its freshness.
+    pub fn hard_max_age(mut self, hard_max_age: Duration) -> Self {
+        self.options.hard_max_age = Some(hard_max_age);
+        self
+    }
+
+    /// Set whether `HEAD` responses refresh the cached `GET` response for
+    /// the same resource.
+    pub fn head_refreshes_get(mut self, enabled: bool) -> Self {
+        self.options.head_refreshes_get = enabled;
+        self
+    }
+
+    /// Set a closure that's called whenever a request bypasses the cache
+    /// because no cache key could be built for it.
+    pub fn on_key_bypass(
+        mut self,
+        on_key_bypass: impl Fn(&request::Parts, KeyBypass) + Send + Sync + 'static,
+    ) -> Self {
+        self.options.on_key_bypass = Some(Arc::new(on_key_bypass));
+        self
+    }
+
+    /// Set whether the `Content-Length` header of responses served for a
+    /// cached entry is always set to the length of their body.
+    pub fn recompute_content_length(mut self, enabled: bool) -> Self {
+        self.options.recompute_content_length = enabled;
+        self
+    }
+
+    /// Set the closure used to transform responses right before they're
+    /// stored.
+    pub fn transform_for_storage_fn(
+        mut self,
+        transform_for_storage_fn: impl Fn(HttpResponse) -> HttpResponse
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.options.transform_for_storage_fn =
+            Some(Arc::new(transform_for_storage_fn));
+        self
+    }
+
+    /// Determines if responses with the `no-transform` directive are served
+    /// and stored as they are.
+    pub fn honor_no_transform(mut self, enabled: bool) -> Self {
+        self.options.honor_no_transform = enabled;
+        self
+    }
+
+    /// Returns the configured options
+    pub fn build(self) -> HttpCacheOptions {
+        self.options
+    }
+}
+
+/// Caches requests according to http spec.
+#[derive(Debug, Clone)]
+pub struct HttpCache<T: CacheManager> {
+    /// Determines the manager behavior.
+    pub mode: CacheMode,
+    /// Manager instance that implements the [`CacheManager`] trait.
+    /// By default, a manager implementation with [`cacache`](https://github.com/zkat/cacache-rs)
+    /// as the backend has been provided, see [`CACacheManager`].
+    pub manager: T,
+    /// Override the default cache options.
+    pub options: HttpCacheOptions,
+}
+
+impl<T: CacheManager> HttpCache<T> {
+    /// Returns a builder for a cache backed by the provided manager
+    pub fn builder(manager: T) -> HttpCacheBuilder<T> {
+        HttpCacheBuilder::new(manager)
+    }
+}
+
+/// Builds an [`HttpCache`] using [`CacheMode::Default`] and the default
+/// [`HttpCacheOptions`] unless overridden.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct HttpCacheBuilder<T: CacheManager> {
+    mode: CacheMode,
+    manager: T,
+    options: HttpCacheOptions,
+}
+
+impl<T: CacheManager> HttpCacheBuilder<T> {
+    /// Create a new builder for a cache backed by the provided manager
+    pub fn new(manager: T) -> Self {
+        Self { mode: CacheMode::Default, manager, options: Default::default() }
+    }
+
+    /// Sets the cache mode.
+    pub fn mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the cache options.
+    pub fn options(mut self, options: HttpCacheOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the configured cache
+    pub fn build(self) -> HttpCache<T> {
+        HttpCache {
+            mode: self.mode,
+            manager: self.manager,
+            options: self.options,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: CacheManager> HttpCache<T> {
+    /// Determines if the request should be cached
+    pub fn can_cache_request(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<bool> {
+        Ok(self.cacheable_key(middleware)?.is_some())
+    }
+
+    /// Returns the cache key of the request if it should be cached, which can
+    /// be passed to [`HttpCache::run_with_key`] so the key closures only run
+    /// once for the request.
+    pub fn cacheable_key(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<Option<String>> {
+        if self.is_bypassed(&middleware.parts()?) {
+            return Ok(None);
+        }
+        let parts = middleware.parts()?;
+        let cache_key = match self.options.check_cache_key(&parts) {
+            Ok(cache_key) => cache_key,
+            Err(bypass) => {
+                if let Some(on_key_bypass) = &self.options.on_key_bypass {
+                    on_key_bypass(&parts, bypass);
+                }
+                return Ok(None);
+            }
+        };
+        let mode = self.cache_mode(middleware)?;
+
+        Ok((mode == CacheMode::IgnoreRules
+            || (self.is_method_cacheable(middleware)?
+                || self.is_preflight(middleware)?)
+                && mode != CacheMode::NoStore)
+            .then_some(cache_key))
+    }
+
+    /// Runs the actions to preform when the client middleware is running without the cache
+    pub async fn run_no_cache(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<()> {
+        if self.is_bypassed(&middleware.parts()?) {
+            return Ok(());
+        }
+        self.delete_entry(
+            &self.options.create_cache_key(&middleware.parts()?, Some("GET")),
+        )
+        .await
+        .ok();
+
+        let cache_key =
+            self.options.create_cache_key(&middleware.parts()?, None);
+
+        if let Some(cache_bust) = &self.options.cache_bust {
+            for key_to_cache_bust in cache_bust(
+                &middleware.parts()?,
+                &self.options.cache_key,
+                &cache_key,
+            ) {
+                self.delete_entry(&key_to_cache_bust).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to run the passed middleware along with the cache
+    pub async fn run(
+        &self,
+        mut middleware: impl Middleware,
+    ) -> Result<HttpResponse>
+    where
+        T: Clone,
+    {
+        match self.cacheable_key(&middleware)? {
+            Some(cache_key) => self.run_with_key(middleware, cache_key).await,
+            None => self.remote_fetch(&mut middleware, "bypass", None).await,
+        }
+    }
+
+    /// Runs the passed middleware along with the cache, using the cache key
+    /// returned for it by [`HttpCache::cacheable_key`].
+    pub async fn run_with_key(
+        &self,
+        mut middleware: impl Middleware,
+        cache_key: String,
+    ) -> Result<HttpResponse>
+    where
+        T: Clone,
+    {
+        if let Some(cache_bust) = &self.options.cache_bust {
+            for key_to_cache_bust in cache_bust(
+                &middleware.parts()?,
+                &self.options.cache_key,
+                &cache_key,
+            ) {
+                self.delete_entry(&key_to_cache_bust).await?;
+            }
+        }
+
+        if self.is_preflight(&middleware)? {
+            return self.preflight_fetch(&mut middleware, cache_key).await;
+        }
+
+        let mode = self.cache_mode(&middleware)?;
+        let lookup_start = Instant::now();
+        let mut entry = self.manager.get(&cache_key).await?;
+        if let Some(primary) = &entry {
+            self.normalize_request_headers(&mut middleware, &primary.response)?;
+        }
+        if self.options.cache_variants {
+            if let Some(primary) = entry {
+                entry = self
+                    .select_variant(&cache_key, primary, &middleware.parts()?)
+                    .await?;
+            }
+        }
+        instrument::lookup_latency(mode, lookup_start.elapsed());
+        let entry = self.validate_cached(&cache_key, entry).await?;
+        if let Some(entry) = entry {
+            #[cfg(feature = "content-encoding")]
+            let parts = middleware.parts()?;
+            let is_head = middleware.method()? == Method::HEAD.as_str();
+            let (mut res, policy) = entry.into_parts();
+            if self.options.cache_status_headers {
+                res.cache_lookup_status(HitOrMiss::HIT);
+            }
+            if let Some(warning_code) = res.warning_code() {
+                // https://tools.ietf.org/html/rfc7234#section-4.3.4
+                //
+                // If a stored response is selected for update, the cache MUST:
+                //
+                // * delete any warning header fields in the stored response with
+                //   warn-code 1xx (see Section 5.5);
+                //
+                // * retain any warning header fields in the stored response with
+                //   warn-code 2xx;
+                //
+                if (100..200).contains(&warning_code) {
+                    res.remove_warning();
+                }
+            }
+
+            let res = match mode {
+                CacheMode::Default => {
+                    self.conditional_fetch(middleware, &cache_key, res, policy)
+                        .await
+                }
+                CacheMode::NoCache => {
+                    instrument::revalidation(mode);
+                    middleware.force_no_cache()?;
+                    let mut res = self
+                        .remote_fetch(
+                            &mut middleware,
+                            "request",
+                            Some(&cache_key),
+                        )
+                        .await?;
+                    if self.options.cache_status_headers {
+                        res.cache_lookup_status(HitOrMiss::HIT);
+                    }
+                    Ok(res)
+                }
+                CacheMode::ForceCache
+                | CacheMode::OnlyIfCached
+                | CacheMode::IgnoreRules => {
+                    //   112 Disconnected operation
+                    // SHOULD be included if the cache is intentionally disconnected from
+                    // the rest of the network for a period of time.
+                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                    res.add_warning(
+                        &res.url.clone(),
+                        112,
+                        "Disconnected operation",
+                    );
+                    if policy.is_stale(SystemTime::now()) {
+                        self.report_stale(
+                            &cache_key,
+                            StaleReason::ForcedOffline,
+                        );
+                    }
+                    if self.options.cache_status_headers {
+                        res.cache_status(HitOrMiss::HIT);
+                    }
+                    self.add_cache_status(&mut res, &hit_status(&policy));
+                    instrument::hit(mode);
+                    Ok(res)
+                }
+                _ => {
+                    instrument::miss(mode);
+                    self.remote_fetch(
+                        &mut middleware,
+                        "request",
+                        Some(&cache_key),
+                    )
+                    .await
+                }
+            }?;
+            #[cfg(feature = "content-encoding")]
+            let res = if self.may_transform(&res) {
+                encoding::negotiate(
+                    &parts,
+                    res,
+                    self.options.compression_offload_threshold,
+                )
+                .await?
+            } else {
+                res
+            };
+            if self.options.recompute_content_length && !is_head {
+                return Ok(with_content_length(res));
+            }
+            Ok(res)
+        } else {
+            instrument::miss(mode);
+            match mode {
+                CacheMode::OnlyIfCached => {
+                    // ENOTCACHED
+                    let mut res = HttpResponse {
+                        body: b"GatewayTimeout".to_vec(),
+                        headers: HashMap::default(),
+                        status: 504,
+                        url: middleware.url()?,
+                        version: HttpVersion::Http11,
+                    };
+                    if self.options.cache_status_headers {
+                        res.cache_status(HitOrMiss::MISS);
+                        res.cache_lookup_status(HitOrMiss::MISS);
+                    }
+                    Ok(res)
+                }
+                _ => self.uncached_fetch(&mut middleware, &cache_key).await,
+            }
+        }
+    }
+
+    /// Returns the metadata stored with the cached entry for the key by
+    /// [`HttpCacheOptions::entry_metadata_fn`], without reading the cached
+    /// response. With [`HttpCacheOptions::cache_variants`], it's the
+    /// metadata of the representation matching the request.
+    pub async fn entry_metadata(
+        &self,
+        cache_key: &str,
+        parts: &request::Parts,
+    ) -> Result<Option<HashMap<String, String>>> {
+        if self.options.entry_metadata_fn.is_none() {
+            return Ok(None);
+        }
+        let Some(cache_key) = self.stored_key(cache_key, parts).await? else {
+            return Ok(None);
+        };
+        Ok(self
+            .manager
+            .get(&self.metadata_key(&cache_key))
+            .await?
+            .map(|metadata| metadata.response.headers))
+    }
+
+    /// Returns the cached entry for the key, along with the metadata and
+    /// request stored with it when [`HttpCacheOptions::entry_metadata_fn`] or
+    /// [`HttpCacheOptions::store_request`] is set.
+    pub async fn get_entry(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        let Some(mut entry) = self.manager.get(cache_key).await? else {
+            return Ok(None);
+        };
+        if self.stores_metadata() {
+            if let Some(metadata) =
+                self.manager.get(&self.metadata_key(cache_key)).await?
+            {
+                if !metadata.response.body.is_empty() {
+                    entry.request =
+                        Some(serde_json::from_slice(&metadata.response.body)?);
+                }
+                entry.metadata = metadata.response.headers;
+            }
+        }
+        Ok(Some(entry))
+    }
+
+    /// Revalidates every stale entry listed by [`CacheManager::stale_keys`],
+    /// sending the requests with `fetch`, up to `concurrency` at once.
+    ///
+    /// The request for an entry is the one stored with it when
+    /// [`HttpCacheOptions::store_request`] is set, and otherwise a `GET`
+    /// request to the url of the cached response, along with the conditional
+    /// headers to revalidate it with. A `304 Not Modified` response freshens
+    /// the entry, other responses replace it or remove it when they can't be
+    /// stored. Entries are kept as they are when `fetch` returns an error or
+    /// the origin answers with a server error, and counted as failed along
+    /// with those the cache manager fails to read or update, which don't
+    /// stop the others from being revalidated. The metadata some options
+    /// store alongside entries, and the representations stored by
+    /// [`HttpCacheOptions::cache_variants`], aren't revalidated.
+    pub async fn revalidate_stale<F, Fut>(
+        &self,
+        fetch: F,
+        concurrency: usize,
+    ) -> Result<RevalidationReport>
+    where
+        F: Fn(request::Parts) -> Fut,
+        Fut: Future<Output = Result<HttpResponse>>,
+    {
+        use futures_util::{stream, StreamExt};
+
+        let keys = self.manager.stale_keys().await?;
+        let mut revalidated = stream::iter(
+            keys.iter().filter(|cache_key| !self.is_index_key(cache_key)),
+        )
+        .map(|cache_key| self.revalidate_entry(cache_key, &fetch))
+        .buffer_unordered(concurrency.max(1));
+        let mut report = RevalidationReport::default();
+        while let Some(outcome) = revalidated.next().await {
+            match outcome {
+                Ok(Some(Revalidated::Refreshed)) => report.refreshed += 1,
+                Ok(Some(Revalidated::Removed)) => report.removed += 1,
+                Ok(Some(Revalidated::Failed)) | Err(_) => report.failed += 1,
+                Ok(None) => {}
+            }
+        }
+        Ok(report)
+    }
+
+    // Revalidates the entry stored under the key, returning `None` when it
+    // was removed or freshened since it was listed.
+    async fn revalidate_entry<F, Fut>(
+        &self,
+        cache_key: &str,
+        fetch: &F,
+    ) -> Result<Option<Revalidated>>
+    where
+        F: Fn(request::Parts) -> Fut,
+        Fut: Future<Output = Result<HttpResponse>>,
+    {
+        let Some(entry) = self.get_entry(cache_key).await? else {
+            return Ok(None);
+        };
+        let parts = match &entry.request {
+            Some(request) => request.parts()?,
+            None => {
+                http::Request::get(entry.response.url.as_str())
+                    .body(())?
+                    .into_parts()
+                    .0
+            }
+        };
+        let BeforeRequest::Stale { request: parts, .. } =
+            entry.policy.before_request(&parts, SystemTime::now())
+        else {
+            return Ok(None);
+        };
+        match fetch(parts.clone()).await {
+            Ok(res) => self
+                .apply_revalidation(cache_key, entry, &parts, res)
+                .await
+                .map(Some),
+            Err(_) => Ok(Some(Revalidated::Failed)),
+        }
+    }
+
+    // Updates the entry with the origin's response to a revalidation request.
+    // A `304` freshens it, other responses replace it, or remove it when they
+    // can't be stored. It's kept as it is after a server error.
+    async fn apply_revalidation(
+        &self,
+        cache_key: &str,
+        entry: CacheEntry,
+        parts: &request::Parts,
+        mut res: HttpResponse,
+    ) -> Result<Revalidated> {
+        if res.status >= 500 {
+            return Ok(Revalidated::Failed);
+        }
+        if res.status == 304 {
+            let (AfterResponse::Modified(policy, updated)
+            | AfterResponse::NotModified(policy, updated)) = entry
+                .policy
+                .after_response(parts, &res.parts()?, self.response_time());
+            let mut cached_res = entry.response;
+            cached_res.update_headers(&updated)?;
+            let stored_key = self
+                .stored_key(cache_key, parts)
+                .await?
+                .unwrap_or_else(|| cache_key.to_string());
+            self.put_refreshed(&stored_key, cached_res, policy).await?;
+            return Ok(Revalidated::Refreshed);
+        }
+        self.rewrite_cache_control(&mut res);
+        self.override_date(&mut res);
+        let res = self.storage_copy(&res).unwrap_or(res);
+        let options = self.cache_options(parts).unwrap_or_default();
+        let policy = match &self.options.policy_fn {
+            Some(policy_fn) => policy_fn(parts, &res, &options),
+            None => CachePolicy::new_options(
+                parts,
+                &res.parts()?,
+                self.response_time(),
+                options,
+            ),
+        };
+        if policy.is_storable() && self.is_storage_allowed(parts, &res) {
+            self.put_entry(parts, cache_key.to_string(), res, policy).await?;
+            Ok(Revalidated::Refreshed)
+        } else {
+            self.delete_entry(cache_key).await?;
+            Ok(Revalidated::Removed)
+        }
+    }
+
+    // Whether the key is one the metadata stored with entries, or the
+    // representations of a response, are stored under.
+    fn is_index_key(&self, cache_key: &str) -> bool {
+        let (_, cache_key) = self.options.split_partition(cache_key);
+        ["metadata", "variant"].into_iter().any(|kind| {
+            let prefix = self.options.cache_key_format.format(&[kind, ""]);
+            cache_key.starts_with(&prefix)
+        })
+    }
+
+    /// Returns every representation stored for the key when
+    /// [`HttpCacheOptions::cache_variants`] is set, along with the metadata
+    /// and request stored with them.
+    pub async fn variants(&self, cache_key: &str) -> Result<Vec<CacheEntry>> {
+        let mut variants = Vec::new();
+        for variant_key in self.variant_keys(cache_key).await? {
+            if let Some(entry) = self.get_entry(&variant_key).await? {
+                variants.push(entry);
+            }
+        }
+        Ok(variants)
+    }
+
+    /// Extends the freshness of the cached entry for the key by `extend_by`
+    /// without refetching it, returning whether the entry existed.
+    ///
+    /// The entry is given a new policy that keeps it fresh for its remaining
+    /// time to live plus `extend_by`. Its `max-age` and `s-maxage` directives
+    /// are replaced to do so, and its other `Cache-Control` directives, such
+    /// as `no-cache` or `must-revalidate`, are kept. The policy is built for
+    /// the request stored with the entry by
+    /// [`HttpCacheOptions::store_request`], so it keeps matching the requests
+    /// its `Vary` header selects. Without it, the policy is built as if the
+    /// response was returned for a `GET` request to its url, like
+    /// [`HttpCache::get_or_fetch`] does.
+    ///
+    /// With [`HttpCacheOptions::cache_variants`], every representation stored
+    /// for the key is extended.
+    pub async fn touch(
+        &self,
+        cache_key: &str,
+        extend_by: Duration,
+    ) -> Result<bool> {
+        let Some(entry) = self.get_entry(cache_key).await? else {
+            return Ok(false);
+        };
+        let Some(variant_keys) = variant_index(&entry.response) else {
+            self.touch_entry(cache_key, entry, extend_by).await?;
+            return Ok(true);
+        };
+        for variant_key in variant_keys {
+            if let Some(entry) = self.get_entry(&variant_key).await? {
+                self.touch_entry(&variant_key, entry, extend_by).await?;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn touch_entry(
+        &self,
+        cache_key: &str,
+        entry: CacheEntry,
+        extend_by: Duration,
+    ) -> Result<()> {
+        let now = SystemTime::now();
+        let ttl = entry.policy.time_to_live(now) + extend_by;
+        let parts = match &entry.request {
+            Some(request) => request.parts()?,
+            None => {
+                http::Request::get(entry.response.url.as_str())
+                    .body(())?
+                    .into_parts()
+                    .0
+            }
+        };
+        let mut res = entry.response.parts()?;
+        for name in [AGE, DATE, EXPIRES] {
+            res.headers.remove(name);
+        }
+        let cache_control = replace_max_age(
+            res.headers.get(CACHE_CONTROL).and_then(|v| v.to_str().ok()),
+            ttl,
+        );
+        res.headers.insert(
+            CACHE_CONTROL,
+            http::HeaderValue::from_str(&cache_control)?,
+        );
+        let policy = CachePolicy::new_options(
+            &parts,
+            &res,
+            now,
+            self.cache_options(&parts).unwrap_or_default(),
+        );
+        self.put_refreshed(cache_key, entry.response, policy).await?;
+        Ok(())
+    }
+
+    /// Returns the cached response for the key if it is present and fresh,
+    /// otherwise runs `fetch` to produce a response, caches it if the response
+    /// is storable, and returns it.
+    ///
+    /// This allows caching semantics to be applied to responses that aren't
+    /// produced by an HTTP client, such as computed responses. The cache policy
+    /// is built as if the response was returned for a `GET` request to its url.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        cache_key: &str,
+        fetch: F,
+    ) -> Result<HttpResponse>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<HttpResponse>>,
+    {
+        let mut entry = self.manager.get(cache_key).await?;
+        if self.options.cache_variants {
+            if let Some(primary) = entry {
+                let parts = http::Request::get(primary.response.url.as_str())
+                    .body(())?
+                    .into_parts()
+                    .0;
+                entry = self.select_variant(cache_key, primary, &parts).await?;
+            }
+        }
+        if let Some(entry) = self.validate_cached(cache_key, entry).await? {
+            if !entry.is_stale(SystemTime::now()) {
+                let mut res = entry.response;
+                if self.options.cache_status_headers {
+                    res.cache_status(HitOrMiss::HIT);
+                    res.cache_lookup_status(HitOrMiss::HIT);
+                }
+                return Ok(res);
+            }
+        }
+        let mut res = fetch().await?;
+        if self.options.cache_status_headers {
+            res.cache_status(HitOrMiss::MISS);
+            res.cache_lookup_status(HitOrMiss::MISS);
+        }
+        self.override_date(&mut res);
+        let parts =
+            http::Request::get(res.url.as_str()).body(())?.into_parts().0;
+        let options = self.cache_options(&parts).unwrap_or_default();
+        let policy = match &self.options.policy_fn {
+            Some(policy_fn) => policy_fn(&parts, &res, &options),
+            None => CachePolicy::new_options(
+                &parts,
+                &res.parts()?,
+                self.response_time(),
+                options,
+            ),
+        };
+        if policy.is_storable() && self.is_storage_allowed(&parts, &res) {
+            self.put_entry(&parts, cache_key.to_string(), res, policy).await
+        } else {
+            Ok(res)
+        }
+    }
+
+    // Fetches a response there is no cached copy of, short-circuiting requests
+    // that recently failed when `error_cache_ttl` is set.
+    async fn uncached_fetch(
+        &self,
+        middleware: &mut impl Middleware,
+        cache_key: &str,
+    ) -> Result<HttpResponse> {
+        let Some(ttl) = self.options.error_cache_ttl else {
+            return self
+                .remote_fetch(middleware, "uri-miss", Some(cache_key))
+                .await;
+        };
+        let failed_requests = &self.options.failed_requests;
+        if let Some(mut res) = failed_requests.get(cache_key, SystemTime::now())
+        {
+            if self.options.cache_status_headers {
+                res.cache_status(HitOrMiss::HIT);
+                res.cache_lookup_status(HitOrMiss::HIT);
+            }
+            self.add_cache_status(&mut res, "hit");
+            return Ok(res);
+        }
+        let (res, failed) = match self
+            .remote_fetch(middleware, "uri-miss", Some(cache_key))
+            .await
+        {
+            Ok(res) if res.status >= 500 => (Ok(res.clone()), res),
+            Ok(res) => {
+                failed_requests.remove(cache_key);
+                return Ok(res);
+            }
+            Err(e) => {
+                let res = HttpResponse {
+                    body: b"GatewayTimeout".to_vec(),
+                    headers: HashMap::default(),
+                    status: 504,
+                    url: middleware.url()?,
+                    version: HttpVersion::Http11,
+                };
+                (Err(e), res)
+            }
+        };
+        let now = SystemTime::now();
+        let expires = now + retry_after(&failed, now).unwrap_or(ttl);
+        failed_requests.insert(cache_key.to_string(), failed, expires);
+        res
+    }
+
+    /// Returns the cache mode that applies to the request
+    pub fn cache_mode(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<CacheMode> {
+        Ok(if let Some(mode) = middleware.overridden_cache_mode() {
+            mode
+        } else if let Some(cache_mode_fn) = &self.options.cache_mode_fn {
+            cache_mode_fn(&middleware.parts()?)
+        } else if let Some((_, mode)) = self.path_rule(middleware)? {
+            *mode
+        } else {
+            self.mode
+        })
+    }
+
+    fn path_rule(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<Option<&(GlobPattern, CacheMode)>> {
+        if self.options.path_rules.is_empty() {
+            return Ok(None);
         }
+        let url = middleware.url()?;
+        Ok(self
+            .options
+            .path_rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches(url.path())))
     }
-}
 
-/// Caches requests according to http spec.
-#[derive(Debug, Clone)]
-pub struct HttpCache<T: CacheManager> {
-    /// Determines the manager behavior.
-    pub mode: CacheMode,
-    /// Manager instance that implements the [`CacheManager`] trait.
-    /// By default, a manager implementation with [`cacache`](https://github.com/zkat/cacache-rs)
-    /// as the backend has been provided, see [`CACacheManager`].
-    pub manager: T,
-    /// Override the default cache options.
-    pub options: HttpCacheOptions,
-}
+    // Rewrites the request headers named by a response's `Vary` header
+    // through `vary_normalize_fn`. Both the policies of stored responses and
+    // the lookups against them are built from the request, so requests
+    // differing only in equivalent header values select the same cached
+    // variant. Repeated header lines are joined into one list first.
+    fn normalize_request_headers(
+        &self,
+        middleware: &mut impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<()> {
+        let (Some(normalize), Some(vary)) =
+            (&self.options.vary_normalize_fn, res.headers.get(VARY.as_str()))
+        else {
+            return Ok(());
+        };
+        let parts = middleware.parts()?;
+        let mut normalized = request::Builder::new().body(())?.into_parts().0;
+        for name in vary.split(',') {
+            let Ok(name) = HeaderName::from_str(name.trim()) else {
+                continue;
+            };
+            let values: Vec<&str> = parts
+                .headers
+                .get_all(&name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+            if let Some(value) = normalize(name.as_str(), &values.join(", ")) {
+                normalized
+                    .headers
+                    .insert(name, http::HeaderValue::from_str(&value)?);
+            }
+        }
+        if normalized.headers.is_empty() {
+            return Ok(());
+        }
+        middleware.update_headers(&normalized)
+    }
 
-#[allow(dead_code)]
-impl<T: CacheManager> HttpCache<T> {
-    /// Determines if the request should be cached
-    pub fn can_cache_request(
+    fn is_preflight(&self, middleware: &impl Middleware) -> Result<bool> {
+        Ok(self.options.cache_preflight
+            && middleware.method()? == Method::OPTIONS.as_str())
+    }
+
+    // OPTIONS isn't a cacheable method, so preflight responses are looked up
+    // and stored with the policy of an equivalent GET request.
+    async fn preflight_fetch(
         &self,
-        middleware: &impl Middleware,
-    ) -> Result<bool> {
+        middleware: &mut impl Middleware,
+        cache_key: String,
+    ) -> Result<HttpResponse> {
         let mode = self.cache_mode(middleware)?;
+        let mut parts = middleware.parts()?;
+        parts.method = Method::GET;
+        self.strip_pragma(&mut parts);
+        let lookup_start = Instant::now();
+        let entry = self.manager.get(&cache_key).await?;
+        instrument::lookup_latency(mode, lookup_start.elapsed());
+        let entry = self.validate_cached(&cache_key, entry).await?;
+        if let Some(entry) = entry {
+            let (mut res, policy) = entry.into_parts();
+            if let BeforeRequest::Fresh(fresh_parts) =
+                policy.before_request(&parts, SystemTime::now())
+            {
+                res.update_headers(&fresh_parts)?;
+                if self.options.cache_status_headers {
+                    res.cache_status(HitOrMiss::HIT);
+                    res.cache_lookup_status(HitOrMiss::HIT);
+                }
+                self.add_cache_status(&mut res, &hit_status(&policy));
+                instrument::hit(mode);
+                return Ok(res);
+            }
+        }
+        instrument::miss(mode);
+        let mut res = middleware.remote_fetch().await?;
+        if self.options.cache_status_headers {
+            res.cache_status(HitOrMiss::MISS);
+            res.cache_lookup_status(HitOrMiss::MISS);
+        }
+        self.override_date(&mut res);
+        let mut res_parts = res.parts()?;
+        if !res_parts.headers.contains_key(CACHE_CONTROL) {
+            let max_age = res_parts
+                .headers
+                .get(ACCESS_CONTROL_MAX_AGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok());
+            if let Some(max_age) = max_age {
+                res_parts.headers.insert(
+                    CACHE_CONTROL,
+                    format!("max-age={max_age}").parse()?,
+                );
+            }
+        }
+        let policy = CachePolicy::new_options(
+            &parts,
+            &res_parts,
+            self.response_time(),
+            self.cache_options(&parts).unwrap_or_default(),
+        );
+        let fwd_status = format!("fwd=uri-miss; fwd-status={}", res.status);
+        if let Some(outcome) =
+            self.skip_reason(&parts, policy.is_storable(), &res)
+        {
+            self.report_store(&cache_key, outcome);
+            self.add_cache_status(&mut res, &fwd_status);
+            Ok(res)
+        } else {
+            let ttl = policy.time_to_live(SystemTime::now()).as_secs();
+            let mut res = self.store_as(&parts, cache_key, res, policy).await?;
+            self.add_cache_status(
+                &mut res,
+                &format!("{fwd_status}; stored; ttl={ttl}"),
+            );
+            Ok(res)
+        }
+    }
+
+    // Stores the response under the cache key of the request. With
+    // `minimal_headers` or `transform_for_storage_fn` the response is still
+    // returned as is, only the stored copy is changed.
+    async fn store(
+        &self,
+        parts: &request::Parts,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        if let Some(stored) = self.storage_copy(&res) {
+            self.store_as(parts, cache_key, stored, policy).await?;
+            return Ok(res);
+        }
+        self.store_as(parts, cache_key, res, policy).await
+    }
 
-        Ok(mode == CacheMode::IgnoreRules
-            || middleware.is_method_get_head() && mode != CacheMode::NoStore)
+    // Whether the response may be transformed, which `no-transform` forbids.
+    // (https://www.rfc-editor.org/rfc/rfc9111#section-5.2.2.6)
+    fn may_transform(&self, res: &HttpResponse) -> bool {
+        !self.options.honor_no_transform || !res.cache_control().no_transform
     }
 
-    /// Runs the actions to preform when the client middleware is running without the cache
-    pub async fn run_no_cache(
+    // The copy of the response to store when it differs from the response,
+    // reduced by `minimal_headers` and then transformed by
+    // `transform_for_storage_fn`. Responses that mustn't be transformed keep
+    // all of their headers too.
+    fn storage_copy(&self, res: &HttpResponse) -> Option<HttpResponse> {
+        let may_transform = self.may_transform(res);
+        let minimal = self.options.minimal_headers && may_transform;
+        let transform = self
+            .options
+            .transform_for_storage_fn
+            .as_ref()
+            .filter(|_| may_transform);
+        if !minimal && transform.is_none() {
+            return None;
+        }
+        let stored = if minimal { res.minimal_headers() } else { res.clone() };
+        Some(match transform {
+            Some(transform) => transform(stored),
+            None => stored,
+        })
+    }
+
+    // Stores the response along with the metadata returned by
+    // `entry_metadata_fn` and the request. The metadata is kept in an entry
+    // of its own, as the headers of a response whose body is the request as
+    // JSON, so managers don't need to know about it. It's written
+    // with the policy of the response, so backends that expire entries by
+    // their policy drop both at once, and removed along with the response.
+    async fn store_as(
         &self,
-        middleware: &mut impl Middleware,
-    ) -> Result<()> {
-        self.manager
-            .delete(
-                &self
+        parts: &request::Parts,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let stored =
+            self.put_entry(parts, cache_key.clone(), res, policy).await;
+        let outcome = match stored {
+            Ok(_) => StoreOutcome::Stored,
+            Err(_) => StoreOutcome::Error,
+        };
+        self.report_store(&cache_key, outcome);
+        stored
+    }
+
+    async fn put_entry(
+        &self,
+        parts: &request::Parts,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.options.failed_requests.remove(&cache_key);
+        // A representation is stored under its own key, and the key of the
+        // request holds the index of them.
+        let stored_key = if self.options.cache_variants {
+            self.index_variant(&cache_key, &res, parts).await?
+        } else {
+            cache_key.clone()
+        };
+        let entry_metadata_fn = self.options.entry_metadata_fn.as_ref();
+        if self.stores_metadata() {
+            let request = if self.options.store_request {
+                serde_json::to_vec(&StoredRequest::new(parts, &res))?
+            } else {
+                Vec::new()
+            };
+            let metadata = HttpResponse {
+                body: request,
+                headers: entry_metadata_fn
+                    .map(|entry_metadata_fn| entry_metadata_fn(parts, &res))
+                    .unwrap_or_default(),
+                status: res.status,
+                url: res.url.clone(),
+                version: res.version,
+            };
+            self.manager
+                .put(self.metadata_key(&stored_key), metadata, policy.clone())
+                .await?;
+        }
+        if let Some(max_entries) = self.options.max_entries_per_host {
+            if let Some(host) = parts.uri.host() {
+                let priority = self
                     .options
-                    .create_cache_key(&middleware.parts()?, Some("GET")),
-            )
-            .await
-            .ok();
+                    .priority_fn
+                    .as_ref()
+                    .map_or(0, |priority_fn| priority_fn(parts, &res));
+                // Hosts are capped within each partition.
+                let (partition, _) = self.options.split_partition(&cache_key);
+                let evicted = self.options.host_index.insert(
+                    &format!("{partition}{host}"),
+                    (&cache_key, priority),
+                    max_entries,
+                );
+                for evicted in evicted {
+                    self.delete_entry(&evicted).await?;
+                }
+            }
+        }
+        self.manager.put(stored_key, res, policy).await
+    }
 
-        let cache_key =
-            self.options.create_cache_key(&middleware.parts()?, None);
+    // Each representation of a response with a `Vary` header is stored under
+    // a key made of the primary cache key and the hashes of the request header
+    // values it was selected by. The primary key holds the index of them, an
+    // empty response with the `Vary` header of the most recent representation,
+    // which tells what headers to select by, and their keys as a JSON body.
+    fn variant_key(
+        &self,
+        cache_key: &str,
+        res: &HttpResponse,
+        parts: &request::Parts,
+    ) -> Option<String> {
+        let vary = res.headers.get(VARY.as_str())?;
+        if vary.trim().is_empty() || vary.contains('*') {
+            return None;
+        }
+        let selector = vary
+            .split(',')
+            .map(|name| {
+                let name = name.trim().to_lowercase();
+                let value = canonical_header_value(parts, &name);
+                let digest = match &self.options.vary_hash_fn {
+                    Some(vary_hash_fn) => vary_hash_fn(&value),
+                    None => Sha256::digest(value.as_bytes())
+                        .iter()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect(),
+                };
+                format!("{name}={digest}")
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        Some(self.options.sidecar_key("variant", cache_key, &[&selector]))
+    }
 
-        if let Some(cache_bust) = &self.options.cache_bust {
-            for key_to_cache_bust in cache_bust(
-                &middleware.parts()?,
-                &self.options.cache_key,
-                &cache_key,
-            ) {
-                self.manager.delete(&key_to_cache_bust).await?;
+    // Returns the keys of the representations stored for the key, or nothing
+    // when it doesn't hold an index of them.
+    async fn variant_keys(&self, cache_key: &str) -> Result<Vec<String>> {
+        Ok(match self.manager.get(cache_key).await? {
+            Some(primary) => {
+                variant_index(&primary.response).unwrap_or_default()
+            }
+            None => Vec::new(),
+        })
+    }
+
+    // Indexes the representation under the primary key, returning the key to
+    // store it under. That's the primary key itself for responses without a
+    // `Vary` header, which replace any representations stored before.
+    // Representations selected by other request headers than the new one are
+    // dropped rather than kept alongside it.
+    async fn index_variant(
+        &self,
+        cache_key: &str,
+        res: &HttpResponse,
+        parts: &request::Parts,
+    ) -> Result<String> {
+        let _lock = self.options.variants_lock.0.lock().await;
+        let primary = self.manager.get(cache_key).await?;
+        let (mut keys, superseded) = match &primary {
+            Some(primary) => match variant_index(&primary.response) {
+                Some(keys)
+                    if vary_names(&primary.response) == vary_names(res) =>
+                {
+                    (keys, Vec::new())
+                }
+                Some(keys) => (Vec::new(), keys),
+                None => (Vec::new(), Vec::new()),
+            },
+            None => (Vec::new(), Vec::new()),
+        };
+        for variant_key in &superseded {
+            self.delete_variant(variant_key).await?;
+        }
+        let Some(variant_key) = self.variant_key(cache_key, res, parts) else {
+            for variant_key in &keys {
+                self.delete_variant(variant_key).await?;
             }
+            return Ok(cache_key.to_string());
+        };
+        if !keys.contains(&variant_key) {
+            keys.push(variant_key.clone());
+        }
+        let mut headers = HashMap::new();
+        if let Some(vary) = res.headers.get(VARY.as_str()) {
+            headers.insert(VARY.to_string(), vary.clone());
         }
+        headers.insert(VARIANTS_INDEX.to_string(), keys.len().to_string());
+        let index = HttpResponse {
+            body: serde_json::to_vec(&keys)?,
+            headers,
+            status: res.status,
+            url: res.url.clone(),
+            version: res.version,
+        };
+        let policy = index_policy(&res.url)?;
+        self.manager.put(cache_key.to_string(), index, policy).await?;
+        Ok(variant_key)
+    }
 
-        Ok(())
+    // Returns the representation matching the request, or nothing if none
+    // has been stored yet. Entries that aren't an index are returned as they
+    // are.
+    async fn select_variant(
+        &self,
+        cache_key: &str,
+        primary: CacheEntry,
+        parts: &request::Parts,
+    ) -> Result<Option<CacheEntry>> {
+        if variant_index(&primary.response).is_none() {
+            return Ok(Some(primary));
+        }
+        match self.variant_key(cache_key, &primary.response, parts) {
+            Some(variant_key) => self.manager.get(&variant_key).await,
+            None => Ok(None),
+        }
     }
 
-    /// Attempts to run the passed middleware along with the cache
-    pub async fn run(
+    // The key the entry answering the request is stored under.
+    async fn stored_key(
         &self,
-        mut middleware: impl Middleware,
-    ) -> Result<HttpResponse> {
-        let is_cacheable = self.can_cache_request(&middleware)?;
-        if !is_cacheable {
-            return self.remote_fetch(&mut middleware).await;
+        cache_key: &str,
+        parts: &request::Parts,
+    ) -> Result<Option<String>> {
+        if !self.options.cache_variants {
+            return Ok(Some(cache_key.to_string()));
+        }
+        let Some(primary) = self.manager.get(cache_key).await? else {
+            return Ok(None);
+        };
+        if variant_index(&primary.response).is_none() {
+            return Ok(Some(cache_key.to_string()));
         }
+        Ok(self.variant_key(cache_key, &primary.response, parts))
+    }
 
-        let cache_key =
-            self.options.create_cache_key(&middleware.parts()?, None);
+    async fn delete_variants(&self, cache_key: &str) -> Result<()> {
+        let _lock = self.options.variants_lock.0.lock().await;
+        for variant_key in self.variant_keys(cache_key).await? {
+            self.delete_variant(&variant_key).await?;
+        }
+        Ok(())
+    }
 
-        if let Some(cache_bust) = &self.options.cache_bust {
-            for key_to_cache_bust in cache_bust(
-                &middleware.parts()?,
-                &self.options.cache_key,
-                &cache_key,
-            ) {
-                self.manager.delete(&key_to_cache_bust).await?;
+    async fn delete_variant(&self, variant_key: &str) -> Result<()> {
+        if self.stores_metadata() {
+            self.manager.delete(&self.metadata_key(variant_key)).await?;
+        }
+        self.manager.delete(variant_key).await
+    }
+
+    // Deletes the cached entry instead of returning it when it isn't usable.
+    async fn validate_cached(
+        &self,
+        cache_key: &str,
+        entry: Option<CacheEntry>,
+    ) -> Result<Option<CacheEntry>> {
+        match entry {
+            Some(entry) if !self.is_usable(&entry) => {
+                self.delete_entry(cache_key).await?;
+                Ok(None)
             }
+            entry => Ok(entry),
         }
+    }
 
-        if let Some(store) = self.manager.get(&cache_key).await? {
-            let (mut res, policy) = store;
-            if self.options.cache_status_headers {
-                res.cache_lookup_status(HitOrMiss::HIT);
+    // Whether a cached entry is within `hard_max_age` and passes
+    // `validate_cached_fn`, whatever its freshness.
+    fn is_usable(&self, entry: &CacheEntry) -> bool {
+        if let Some(hard_max_age) = self.options.hard_max_age {
+            if entry.age(SystemTime::now()) > hard_max_age {
+                return false;
             }
-            if let Some(warning_code) = res.warning_code() {
-                // https://tools.ietf.org/html/rfc7234#section-4.3.4
-                //
-                // If a stored response is selected for update, the cache MUST:
-                //
-                // * delete any warning header fields in the stored response with
-                //   warn-code 1xx (see Section 5.5);
-                //
-                // * retain any warning header fields in the stored response with
-                //   warn-code 2xx;
-                //
-                if (100..200).contains(&warning_code) {
-                    res.remove_warning();
-                }
+        }
+        self.options
+            .validate_cached_fn
+            .as_ref()
+            .map_or(true, |validate_cached_fn| {
+                validate_cached_fn(&entry.response)
+            })
+    }
+
+    // Writes a response that was read from the key back with a new policy,
+    // rewriting the metadata stored with it so it's kept just as long.
+    async fn put_refreshed(
+        &self,
+        cache_key: &str,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        if self.stores_metadata() {
+            let metadata_key = self.metadata_key(cache_key);
+            if let Some(metadata) = self.manager.get(&metadata_key).await? {
+                self.manager
+                    .put(metadata_key, metadata.response, policy.clone())
+                    .await?;
             }
+        }
+        self.manager.put(cache_key.to_string(), res, policy).await
+    }
 
-            match self.cache_mode(&middleware)? {
-                CacheMode::Default => {
-                    self.conditional_fetch(middleware, res, policy).await
-                }
-                CacheMode::NoCache => {
-                    middleware.force_no_cache()?;
-                    let mut res = self.remote_fetch(&mut middleware).await?;
-                    if self.options.cache_status_headers {
-                        res.cache_lookup_status(HitOrMiss::HIT);
-                    }
-                    Ok(res)
-                }
-                CacheMode::ForceCache
-                | CacheMode::OnlyIfCached
-                | CacheMode::IgnoreRules => {
-                    //   112 Disconnected operation
-                    // SHOULD be included if the cache is intentionally disconnected from
-                    // the rest of the network for a period of time.
-                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    res.add_warning(
-                        &res.url.clone(),
-                        112,
-                        "Disconnected operation",
+    // Deletes the entry stored under the key, along with the metadata stored
+    // with it and every representation of it.
+    async fn delete_entry(&self, cache_key: &str) -> Result<()> {
+        self.options.failed_requests.remove(cache_key);
+        self.options.host_index.remove(cache_key);
+        if self.stores_metadata() {
+            self.manager.delete(&self.metadata_key(cache_key)).await?;
+        }
+        if self.options.cache_variants {
+            self.delete_variants(cache_key).await?;
+        }
+        self.manager.delete(cache_key).await
+    }
+
+    fn report_stale(&self, cache_key: &str, reason: StaleReason) {
+        if let Some(on_stale_served) = &self.options.on_stale_served {
+            on_stale_served(cache_key, reason);
+        }
+    }
+
+    fn report_store(&self, cache_key: &str, outcome: StoreOutcome) {
+        if let Some(on_store) = &self.options.on_store {
+            on_store(cache_key, outcome);
+        }
+    }
+
+    // Reports a response that won't be stored under the cache key of the
+    // request.
+    fn skip_store(&self, cache_key: &str, outcome: StoreOutcome) {
+        if self.options.on_store.is_some() {
+            self.report_store(cache_key, outcome);
+        }
+    }
+
+    // Returns why the response won't be stored, if it won't be.
+    fn skip_reason(
+        &self,
+        parts: &request::Parts,
+        cacheable: bool,
+        res: &HttpResponse,
+    ) -> Option<StoreOutcome> {
+        if !cacheable
+            || !self.is_storage_allowed(parts, res)
+            || self
+                .options
+                .should_store_fn
+                .as_ref()
+                .is_some_and(|should_store| !should_store(parts, res))
+        {
+            Some(StoreOutcome::SkippedUncacheable)
+        } else if self
+            .options
+            .max_body_size
+            .is_some_and(|max_body_size| res.body.len() > max_body_size)
+        {
+            Some(StoreOutcome::SkippedTooLarge)
+        } else {
+            None
+        }
+    }
+
+    fn stores_metadata(&self) -> bool {
+        self.options.entry_metadata_fn.is_some() || self.options.store_request
+    }
+
+    fn metadata_key(&self, cache_key: &str) -> String {
+        self.options.sidecar_key("metadata", cache_key, &[])
+    }
+
+    // The policy treats `Pragma: no-cache` like `Cache-Control: no-cache`, but
+    // Pragma is to be ignored when the request has a Cache-Control header.
+    // (https://www.rfc-editor.org/rfc/rfc9111#section-5.4)
+    fn strip_pragma(&self, parts: &mut request::Parts) {
+        if !self.options.honor_pragma
+            || parts.headers.contains_key(CACHE_CONTROL)
+        {
+            parts.headers.remove(PRAGMA);
+        }
+    }
+
+    fn response_time(&self) -> SystemTime {
+        self.options.response_date_override.unwrap_or_else(SystemTime::now)
+    }
+
+    fn override_date(&self, res: &mut HttpResponse) {
+        if let Some(date) = self.options.response_date_override {
+            res.headers.insert(
+                DATE.as_str().to_owned(),
+                httpdate::fmt_http_date(date),
+            );
+        }
+    }
+
+    // Clients build the policy with the current time, so it's only built here
+    // when the response time is overridden.
+    fn response_policy(
+        &self,
+        middleware: &impl Middleware,
+        final_parts: Option<&request::Parts>,
+        res: &HttpResponse,
+    ) -> Result<CachePolicy> {
+        // The policy keeps the headers it was built from, and brings them
+        // back when a revalidated response is merged with it.
+        let stored_res = self.storage_copy(res);
+        let res = stored_res.as_ref().unwrap_or(res);
+        let parts = match final_parts {
+            Some(parts) => parts.clone(),
+            None => middleware.parts()?,
+        };
+        if let Some(policy_fn) = &self.options.policy_fn {
+            let options = self.cache_options(&parts).unwrap_or_default();
+            return Ok(policy_fn(&parts, res, &options));
+        }
+        match (self.options.response_date_override, self.cache_options(&parts))
+        {
+            (Some(date), options) => Ok(CachePolicy::new_options(
+                &parts,
+                &res.parts()?,
+                date,
+                options.unwrap_or_default(),
+            )),
+            // The middleware only knows the request it was given, so the
+            // policy for a redirected response is built here.
+            (None, options) if final_parts.is_some() => {
+                Ok(CachePolicy::new_options(
+                    &parts,
+                    &res.parts()?,
+                    SystemTime::now(),
+                    options.unwrap_or_default(),
+                ))
+            }
+            (None, Some(options)) => {
+                middleware.policy_with_options(res, options)
+            }
+            (None, None) => middleware.policy(res),
+        }
+    }
+
+    fn rewrite_cache_control(&self, res: &mut HttpResponse) {
+        if let Some(cache_control_fn) = &self.options.response_cache_control_fn
+        {
+            if let Some(cache_control) = cache_control_fn(res) {
+                let cache_control = cache_control.to_string();
+                if cache_control.is_empty() {
+                    res.headers.remove(CACHE_CONTROL.as_str());
+                } else {
+                    res.headers.insert(
+                        CACHE_CONTROL.as_str().to_owned(),
+                        cache_control,
                     );
-                    if self.options.cache_status_headers {
-                        res.cache_status(HitOrMiss::HIT);
-                    }
-                    Ok(res)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
             }
-        } else {
-            match self.cache_mode(&middleware)? {
-                CacheMode::OnlyIfCached => {
-                    // ENOTCACHED
-                    let mut res = HttpResponse {
-                        body: b"GatewayTimeout".to_vec(),
-                        headers: HashMap::default(),
-                        status: 504,
-                        url: middleware.url()?,
-                        version: HttpVersion::Http11,
-                    };
-                    if self.options.cache_status_headers {
-                        res.cache_status(HitOrMiss::MISS);
-                        res.cache_lookup_status(HitOrMiss::MISS);
-                    }
-                    Ok(res)
-                }
-                _ => self.remote_fetch(&mut middleware).await,
+        }
+        if let Some(ttl) = self.options.permanent_redirect_ttl {
+            if is_permanent_redirect(res.status) {
+                apply_default_max_age(res, ttl);
+            }
+        }
+        if !self.options.disable_heuristics
+            && (self.options.heuristic_fraction.is_some()
+                || self.options.heuristic_max_age.is_some())
+        {
+            let fraction =
+                self.options.heuristic_fraction.unwrap_or_else(|| {
+                    self.options
+                        .cache_options
+                        .unwrap_or_default()
+                        .cache_heuristic
+                });
+            if let Some(lifetime) = heuristic_lifetime(
+                res,
+                fraction,
+                self.options.heuristic_max_age,
+            ) {
+                apply_default_max_age(res, lifetime);
             }
         }
+        if let Some(max_age) = self.options.default_max_age {
+            apply_default_max_age(res, max_age);
+        }
     }
 
-    fn cache_mode(&self, middleware: &impl Middleware) -> Result<CacheMode> {
-        Ok(if let Some(mode) = middleware.overridden_cache_mode() {
-            mode
-        } else if let Some(cache_mode_fn) = &self.options.cache_mode_fn {
-            cache_mode_fn(&middleware.parts()?)
-        } else {
-            self.mode
+    // Only `200 OK` responses are stored, along with permanent redirects
+    // when `permanent_redirect_ttl` is set.
+    fn is_storable_status(&self, status: u16) -> bool {
+        status == 200
+            || (self.options.permanent_redirect_ttl.is_some()
+                && is_permanent_redirect(status))
+    }
+
+    // Surrogate-Control instructs shared caches separately from Cache-Control.
+    // Returns a copy of the response with its Cache-Control replaced by the
+    // surrogate max-age, to build the policy from, when one applies.
+    fn take_surrogate_control(
+        &self,
+        parts: &request::Parts,
+        res: &mut HttpResponse,
+    ) -> Option<HttpResponse> {
+        if !self.options.honor_surrogate_control {
+            return None;
+        }
+        let surrogate_control = res.headers.remove(SURROGATE_CONTROL)?;
+        if !self.is_shared(parts) {
+            return None;
+        }
+        let max_age = CacheControl::parse(&surrogate_control).max_age?;
+        let mut policy_res = res.clone();
+        policy_res.headers.insert(
+            CACHE_CONTROL.as_str().to_owned(),
+            format!("max-age={}", max_age.as_secs()),
+        );
+        Some(policy_res)
+    }
+
+    // Checks the options that can prevent an otherwise cacheable response
+    // from being stored.
+    fn is_storage_allowed(
+        &self,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> bool {
+        (self.options.cache_unknown_length
+            || res.headers.contains_key(CONTENT_LENGTH.as_str()))
+            && (self.options.cache_set_cookie
+                || !self.is_shared(parts)
+                || !res.headers.contains_key(SET_COOKIE.as_str()))
+    }
+
+    fn is_bypassed(&self, parts: &request::Parts) -> bool {
+        let Some((name, expected)) = &self.options.bypass_header else {
+            return false;
+        };
+        parts.headers.get_all(name).iter().any(|value| match expected {
+            Some(expected) => {
+                value.to_str().is_ok_and(|v| v.trim() == expected)
+            }
+            None => true,
         })
     }
 
+    fn is_method_cacheable(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<bool> {
+        let method = middleware.method()?;
+        Ok(self
+            .options
+            .cacheable_methods
+            .iter()
+            .any(|allowed| allowed.as_str() == method))
+    }
+
+    fn is_shared(&self, parts: &request::Parts) -> bool {
+        self.cache_options(parts).map_or(true, |options| options.shared)
+    }
+
+    // Whether a stale response may be served while it's revalidated, within
+    // the response's `stale-while-revalidate` window or the request's
+    // `max-stale`. Its staleness is counted from its `max-age`, or from when
+    // it was stored if it has none.
+    fn stale_allowed(
+        &self,
+        parts: &request::Parts,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> bool {
+        let cache_control = res
+            .headers
+            .get(CACHE_CONTROL.as_str())
+            .map(|value| CacheControl::parse(value))
+            .unwrap_or_default();
+        let lifetime = if self.cache_options(parts).unwrap_or_default().shared {
+            cache_control.s_maxage.or(cache_control.max_age)
+        } else {
+            cache_control.max_age
+        };
+        let staleness = policy
+            .age(SystemTime::now())
+            .saturating_sub(lifetime.unwrap_or_default());
+        cache_control
+            .stale_while_revalidate
+            .or_else(|| max_stale(parts))
+            .is_some_and(|allowed| staleness <= allowed)
+    }
+
+    // The configured cache options, with `shared` decided by `shared_fn`
+    // when it is set, and without a heuristic when heuristics are disabled.
+    fn cache_options(&self, parts: &request::Parts) -> Option<CacheOptions> {
+        let mut options = match &self.options.shared_fn {
+            Some(shared_fn) => Some(CacheOptions {
+                shared: shared_fn(parts),
+                ..self.options.cache_options.unwrap_or_default()
+            }),
+            None => self.options.cache_options,
+        };
+        if self.options.disable_heuristics {
+            options = Some(CacheOptions {
+                cache_heuristic: 0.0,
+                ..options.unwrap_or_default()
+            });
+        }
+        options
+    }
+
+    // `proxy-revalidate` has the same meaning as `must-revalidate`,
+    // but only applies to shared caches.
+    // (https://www.rfc-editor.org/rfc/rfc9111#section-5.2.2.8)
+    // `no-cache` takes precedence over any freshness, so it never allows a
+    // stale response to be served without revalidation either.
+    // (https://www.rfc-editor.org/rfc/rfc9111#section-5.2.2.4)
+    fn must_revalidate(
+        &self,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> bool {
+        let cache_control = res.cache_control();
+        cache_control.must_revalidate
+            || cache_control.no_cache
+            || (self.is_shared(parts) && cache_control.proxy_revalidate)
+    }
+
+    // With `key_by_final_url`, a response the client reached by following
+    // redirects is treated as the answer to the same request for its final
+    // url. Only a `301`, `302` or `303` may have turned the request into a
+    // `GET`, and the middleware doesn't report which redirects were followed,
+    // so the method is kept as it was.
+    fn final_request_parts(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<Option<request::Parts>> {
+        if !self.options.key_by_final_url || res.url == middleware.url()? {
+            return Ok(None);
+        }
+        let mut parts = middleware.parts()?;
+        parts.uri = res.url.as_str().parse()?;
+        Ok(Some(parts))
+    }
+
+    // `fwd` is the reason the request is forwarded to the origin, as reported
+    // in the `cache-status` header. The response is stored under `cache_key`
+    // when it's given, unless it was redirected and is keyed by its final url.
     async fn remote_fetch(
         &self,
         middleware: &mut impl Middleware,
+        fwd: &str,
+        cache_key: Option<&str>,
     ) -> Result<HttpResponse> {
         let mut res = middleware.remote_fetch().await?;
+        self.normalize_request_headers(middleware, &res)?;
         if self.options.cache_status_headers {
             res.cache_status(HitOrMiss::MISS);
             res.cache_lookup_status(HitOrMiss::MISS);
         }
-        let policy = match self.options.cache_options {
-            Some(options) => middleware.policy_with_options(&res, options)?,
-            None => middleware.policy(&res)?,
-        };
+        self.rewrite_cache_control(&mut res);
+        self.override_date(&mut res);
+        let surrogate_res =
+            self.take_surrogate_control(&middleware.parts()?, &mut res);
+        let policy_res = surrogate_res.as_ref().unwrap_or(&res);
+        let final_parts = self.final_request_parts(middleware, &res)?;
+        let policy =
+            self.response_policy(middleware, final_parts.as_ref(), policy_res)?;
         let is_get_head = middleware.is_method_get_head();
         let mode = self.cache_mode(middleware)?;
-        let mut is_cacheable = is_get_head
+        let mut is_cacheable = self.is_method_cacheable(middleware)?
             && mode != CacheMode::NoStore
-            && res.status == 200
+            && self.is_storable_status(res.status)
             && policy.is_storable();
-        if mode == CacheMode::IgnoreRules && res.status == 200 {
+        if mode == CacheMode::IgnoreRules && self.is_storable_status(res.status)
+        {
             is_cacheable = true;
         }
-        if is_cacheable {
-            Ok(self
-                .manager
-                .put(
-                    self.options.create_cache_key(&middleware.parts()?, None),
-                    res,
-                    policy,
-                )
-                .await?)
+        if self.options.head_refreshes_get
+            && middleware.method()? == Method::HEAD.as_str()
+            && res.status == 200
+        {
+            self.refresh_from_head(&middleware.parts()?, &res).await?;
+        }
+        let fwd_status = format!("fwd={fwd}; fwd-status={}", res.status);
+        let cache_key = match (&final_parts, cache_key) {
+            (None, Some(cache_key)) => cache_key.to_owned(),
+            (Some(parts), _) => self.options.create_cache_key(parts, None),
+            (None, None) => {
+                self.options.create_cache_key(&middleware.parts()?, None)
+            }
+        };
+        let parts = match final_parts {
+            Some(parts) => parts,
+            None => middleware.parts()?,
+        };
+        let skipped = self.skip_reason(&parts, is_cacheable, &res);
+        if let Some(outcome) = skipped {
+            self.skip_store(&cache_key, outcome);
+        }
+        if skipped.is_none() {
+            let ttl = policy.time_to_live(SystemTime::now()).as_secs();
+            let mut res = self.store(&parts, cache_key, res, policy).await?;
+            self.add_cache_status(
+                &mut res,
+                &format!("{fwd_status}; stored; ttl={ttl}"),
+            );
+            Ok(res)
         } else if !is_get_head {
-            self.manager
-                .delete(
-                    &self
-                        .options
-                        .create_cache_key(&middleware.parts()?, Some("GET")),
-                )
-                .await
-                .ok();
+            self.delete_entry(
+                &self
+                    .options
+                    .create_cache_key(&middleware.parts()?, Some("GET")),
+            )
+            .await
+            .ok();
+            self.add_cache_status(&mut res, &fwd_status);
             Ok(res)
         } else {
+            self.add_cache_status(&mut res, &fwd_status);
             Ok(res)
         }
     }
 
+    // Updates the cached `GET` response for the same resource with the
+    // headers of a `HEAD` response, as a `304` would, when its validators
+    // match. (https://www.rfc-editor.org/rfc/rfc9111#section-4.3.5)
+    async fn refresh_from_head(
+        &self,
+        parts: &request::Parts,
+        head: &HttpResponse,
+    ) -> Result<()> {
+        let mut get_parts = parts.clone();
+        get_parts.method = Method::GET;
+        let cache_key = self.options.create_cache_key(parts, Some("GET"));
+        let Some(cache_key) = self.stored_key(&cache_key, &get_parts).await?
+        else {
+            return Ok(());
+        };
+        let Some(entry) = self.manager.get(&cache_key).await? else {
+            return Ok(());
+        };
+        let mut not_modified = head.parts()?;
+        not_modified.status = StatusCode::NOT_MODIFIED;
+        let AfterResponse::NotModified(policy, updated) = entry
+            .policy
+            .after_response(&get_parts, &not_modified, self.response_time())
+        else {
+            return Ok(());
+        };
+        let mut res = entry.response;
+        res.update_headers(&updated)?;
+        self.put_refreshed(&cache_key, res, policy).await?;
+        Ok(())
+    }
+
     async fn conditional_fetch(
         &self,
         mut middleware: impl Middleware,
+        cache_key: &str,
         mut cached_res: HttpResponse,
         mut policy: CachePolicy,
-    ) -> Result<HttpResponse> {
-        let before_req =
-            policy.before_request(&middleware.parts()?, SystemTime::now());
+    ) -> Result<HttpResponse>
+    where
+        T: Clone,
+    {
+        let mut parts = middleware.parts()?;
+        let must_revalidate = self.must_revalidate(&parts, &cached_res);
+        if must_revalidate {
+            // The policy only rejects max-stale for must-revalidate, so strip it
+            // here to make sure a stale entry is always revalidated.
+            remove_max_stale(&mut parts)?;
+        }
+        self.strip_pragma(&mut parts);
+        let before_req = policy.before_request(&parts, SystemTime::now());
         match before_req {
-            BeforeRequest::Fresh(parts) => {
-                cached_res.update_headers(&parts)?;
+            BeforeRequest::Fresh(fresh_parts) => {
+                cached_res.update_headers(&fresh_parts)?;
+                if policy.is_stale(SystemTime::now()) {
+                    self.report_stale(cache_key, StaleReason::MaxStale);
+                }
+                if self.options.answer_client_conditionals
+                    && client_validators_match(&parts, &cached_res)
+                {
+                    cached_res = cached_res.not_modified();
+                }
                 if self.options.cache_status_headers {
                     cached_res.cache_status(HitOrMiss::HIT);
                     cached_res.cache_lookup_status(HitOrMiss::HIT);
                 }
+                self.add_cache_status(&mut cached_res, &hit_status(&policy));
+                instrument::hit(CacheMode::Default);
+                if self.options.background_revalidate_always {
+                    self.revalidate_hit(
+                        &mut middleware,
+                        cache_key,
+                        &parts,
+                        &policy,
+                    )?;
+                }
                 return Ok(cached_res);
             }
             BeforeRequest::Stale { request: parts, matches } => {
@@ -667,11 +4309,70 @@ impl<T: CacheManager> HttpCache<T> {
                 }
             }
         }
+        // Held until the revalidation completes. Past the limit, responses
+        // that can't be served stale wait for a revalidation to finish.
+        let mut permit = match self.options.max_background_revalidations {
+            Some(max) => {
+                let permit = self.options.revalidations.try_acquire(max);
+                if permit.is_none()
+                    && !must_revalidate
+                    && self.stale_allowed(&parts, &cached_res, &policy)
+                {
+                    //   110 Response is Stale
+                    //   MUST be included whenever the returned response is stale.
+                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                    cached_res.add_warning(
+                        &middleware.url()?,
+                        110,
+                        "Response is stale",
+                    );
+                    if self.options.cache_status_headers {
+                        cached_res.cache_status(HitOrMiss::HIT);
+                        cached_res.cache_lookup_status(HitOrMiss::HIT);
+                    }
+                    self.add_cache_status(&mut cached_res, "hit");
+                    instrument::hit(CacheMode::Default);
+                    self.report_stale(cache_key, StaleReason::SwrWindow);
+                    return Ok(cached_res);
+                }
+                match permit {
+                    Some(permit) => Some(permit),
+                    None => Some(self.options.revalidations.acquire(max).await),
+                }
+            }
+            None => None,
+        };
+        instrument::revalidation(CacheMode::Default);
+        self.customize_revalidation(&mut middleware)?;
         let req_url = middleware.url()?;
-        match middleware.remote_fetch().await {
+        let fetched = match self.options.serve_stale_after {
+            Some(timeout) if !must_revalidate => {
+                self.fetch_within(
+                    &mut middleware,
+                    cache_key,
+                    timeout,
+                    &mut permit,
+                )
+                .await?
+            }
+            _ => Some(middleware.remote_fetch().await),
+        };
+        let Some(fetched) = fetched else {
+            //   110 Response is Stale
+            //   MUST be included whenever the returned response is stale.
+            // (https://tools.ietf.org/html/rfc2616#section-14.46)
+            cached_res.add_warning(&req_url, 110, "Response is stale");
+            if self.options.cache_status_headers {
+                cached_res.cache_status(HitOrMiss::HIT);
+            }
+            self.add_cache_status(&mut cached_res, "fwd=stale");
+            self.report_stale(cache_key, StaleReason::Timeout);
+            return Ok(cached_res);
+        };
+        match fetched {
             Ok(mut cond_res) => {
                 let status = StatusCode::from_u16(cond_res.status)?;
-                if status.is_server_error() && cached_res.must_revalidate() {
+                if status.is_server_error() && must_revalidate {
                     //   111 Revalidation failed
                     //   MUST be included if a cache returns a stale response
                     //   because an attempt to revalidate the response failed,
@@ -685,6 +4386,11 @@ impl<T: CacheManager> HttpCache<T> {
                     if self.options.cache_status_headers {
                         cached_res.cache_status(HitOrMiss::HIT);
                     }
+                    self.add_cache_status(
+                        &mut cached_res,
+                        &format!("fwd=stale; fwd-status={}", cond_res.status),
+                    );
+                    self.report_stale(cache_key, StaleReason::IfError);
                     Ok(cached_res)
                 } else if cond_res.status == 304 {
                     let after_res = policy.after_response(
@@ -703,45 +4409,112 @@ impl<T: CacheManager> HttpCache<T> {
                         cached_res.cache_status(HitOrMiss::HIT);
                         cached_res.cache_lookup_status(HitOrMiss::HIT);
                     }
-                    let res = self
-                        .manager
-                        .put(
-                            self.options
-                                .create_cache_key(&middleware.parts()?, None),
+                    let ttl = policy.time_to_live(SystemTime::now()).as_secs();
+                    let mut res = self
+                        .store(
+                            &middleware.parts()?,
+                            cache_key.to_owned(),
                             cached_res,
                             policy,
                         )
                         .await?;
+                    self.add_cache_status(
+                        &mut res,
+                        &format!("fwd=stale; fwd-status=304; ttl={ttl}"),
+                    );
                     Ok(res)
+                } else if cond_res.status == 200
+                    && self
+                        .options
+                        .accept_revalidation_fn
+                        .as_ref()
+                        .is_some_and(|accept| !accept(&cond_res))
+                {
+                    // The replacement was rejected, keep serving the cached
+                    // response as if revalidation had failed.
+                    cached_res.add_warning(
+                        &req_url,
+                        111,
+                        "Revalidation failed",
+                    );
+                    if self.options.cache_status_headers {
+                        cached_res.cache_status(HitOrMiss::HIT);
+                    }
+                    self.add_cache_status(
+                        &mut cached_res,
+                        "fwd=stale; fwd-status=200",
+                    );
+                    self.report_stale(cache_key, StaleReason::IfError);
+                    Ok(cached_res)
                 } else if cond_res.status == 200 {
-                    let policy = match self.options.cache_options {
-                        Some(options) => middleware
-                            .policy_with_options(&cond_res, options)?,
-                        None => middleware.policy(&cond_res)?,
-                    };
+                    self.normalize_request_headers(&mut middleware, &cond_res)?;
+                    self.rewrite_cache_control(&mut cond_res);
+                    self.override_date(&mut cond_res);
+                    let surrogate_res =
+                        self.take_surrogate_control(&parts, &mut cond_res);
+                    let policy_res =
+                        surrogate_res.as_ref().unwrap_or(&cond_res);
+                    let policy =
+                        self.response_policy(&middleware, None, policy_res)?;
                     if self.options.cache_status_headers {
                         cond_res.cache_status(HitOrMiss::MISS);
                         cond_res.cache_lookup_status(HitOrMiss::HIT);
                     }
-                    let res = self
-                        .manager
-                        .put(
-                            self.options
-                                .create_cache_key(&middleware.parts()?, None),
+                    let parts = middleware.parts()?;
+                    if let Some(outcome) =
+                        self.skip_reason(&parts, true, &cond_res)
+                    {
+                        self.skip_store(cache_key, outcome);
+                        self.add_cache_status(
+                            &mut cond_res,
+                            "fwd=stale; fwd-status=200",
+                        );
+                        return Ok(cond_res);
+                    }
+                    let ttl = policy.time_to_live(SystemTime::now()).as_secs();
+                    let mut res = self
+                        .store(
+                            &middleware.parts()?,
+                            cache_key.to_owned(),
                             cond_res,
                             policy,
                         )
                         .await?;
+                    self.add_cache_status(
+                        &mut res,
+                        &format!(
+                            "fwd=stale; fwd-status=200; stored; ttl={ttl}"
+                        ),
+                    );
                     Ok(res)
+                } else if cond_res.status == 206 {
+                    // A partial response to a range request is passed
+                    // through, but never stored over the full response.
+                    if self.options.cache_status_headers {
+                        cond_res.cache_status(HitOrMiss::MISS);
+                        cond_res.cache_lookup_status(HitOrMiss::HIT);
+                    }
+                    self.add_cache_status(
+                        &mut cond_res,
+                        "fwd=stale; fwd-status=206",
+                    );
+                    Ok(cond_res)
                 } else {
                     if self.options.cache_status_headers {
                         cached_res.cache_status(HitOrMiss::HIT);
                     }
+                    self.add_cache_status(
+                        &mut cached_res,
+                        &format!("fwd=stale; fwd-status={}", cond_res.status),
+                    );
+                    self.report_stale(cache_key, StaleReason::IfError);
                     Ok(cached_res)
                 }
             }
             Err(e) => {
-                if cached_res.must_revalidate() {
+                if must_revalidate
+                    && !self.options.override_must_revalidate_on_error
+                {
                     Err(e)
                 } else {
                     //   111 Revalidation failed
@@ -757,11 +4530,466 @@ impl<T: CacheManager> HttpCache<T> {
                     if self.options.cache_status_headers {
                         cached_res.cache_status(HitOrMiss::HIT);
                     }
+                    self.add_cache_status(&mut cached_res, "fwd=stale");
+                    self.report_stale(cache_key, StaleReason::IfError);
                     Ok(cached_res)
                 }
             }
         }
     }
+
+    // Applies `revalidation_request_fn` to the revalidation request.
+    fn customize_revalidation(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<()> {
+        if let Some(revalidation_request_fn) =
+            &self.options.revalidation_request_fn
+        {
+            let parts = middleware.parts()?;
+            let mut builder = request::Builder::new()
+                .method(parts.method)
+                .uri(parts.uri)
+                .version(parts.version);
+            if let Some(headers) = builder.headers_mut() {
+                headers.extend(parts.headers);
+            }
+            revalidation_request_fn(&mut builder);
+            middleware.update_headers(&builder.body(())?.into_parts().0)?;
+        }
+        Ok(())
+    }
+
+    // Starts revalidating a fresh hit in the background for
+    // `background_revalidate_always`, with the validators of the cached
+    // response. Nothing is started when the middleware can't fetch in the
+    // background or `max_background_revalidations` are already in flight.
+    fn revalidate_hit(
+        &self,
+        middleware: &mut impl Middleware,
+        cache_key: &str,
+        parts: &request::Parts,
+        policy: &CachePolicy,
+    ) -> Result<()>
+    where
+        T: Clone,
+    {
+        let permit = match self.options.max_background_revalidations {
+            Some(max) => match self.options.revalidations.try_acquire(max) {
+                Some(permit) => Some(permit),
+                None => return Ok(()),
+            },
+            None => None,
+        };
+        // The policy only builds a revalidation request for requests it
+        // can't answer, so the request is marked as not accepting the cached
+        // response and its own Cache-Control is put back afterwards.
+        let mut no_cache = parts.clone();
+        no_cache
+            .headers
+            .insert(CACHE_CONTROL, http::HeaderValue::from_static("no-cache"));
+        let BeforeRequest::Stale { request: mut revalidation, matches: true } =
+            policy.before_request(&no_cache, SystemTime::now())
+        else {
+            return Ok(());
+        };
+        revalidation.headers.remove(CACHE_CONTROL);
+        for value in parts.headers.get_all(CACHE_CONTROL) {
+            revalidation.headers.append(CACHE_CONTROL, value.clone());
+        }
+        middleware.update_headers(&revalidation)?;
+        self.customize_revalidation(middleware)?;
+        let Some(fetch) = middleware.background_fetch()? else {
+            return Ok(());
+        };
+        instrument::revalidation(CacheMode::Default);
+        self.finish_in_background(middleware, cache_key, fetch, permit)
+    }
+
+    // Fetches the revalidation, giving up on it after `timeout` with `None`.
+    // The fetch then carries on in the background, holding the permit, and
+    // its response updates the entry. Middleware that can't fetch in the
+    // background, or has no timer, always waits for the origin.
+    async fn fetch_within(
+        &self,
+        middleware: &mut impl Middleware,
+        cache_key: &str,
+        timeout: Duration,
+        permit: &mut Option<RevalidationPermit>,
+    ) -> Result<Option<Result<HttpResponse>>>
+    where
+        T: Clone,
+    {
+        let (Some(timer), Some(fetch)) =
+            (middleware.sleep(timeout), middleware.background_fetch()?)
+        else {
+            return Ok(Some(middleware.remote_fetch().await));
+        };
+        let fetch = match future_util::select(fetch, timer).await {
+            Either::Left((fetched, _)) => return Ok(Some(fetched)),
+            Either::Right(((), fetch)) => fetch,
+        };
+        self.finish_in_background(middleware, cache_key, fetch, permit.take())?;
+        Ok(None)
+    }
+
+    // Spawns the rest of the revalidation, holding the permit until its
+    // response has updated the entry.
+    fn finish_in_background(
+        &self,
+        middleware: &impl Middleware,
+        cache_key: &str,
+        fetch: BackgroundFuture<Result<HttpResponse>>,
+        permit: Option<RevalidationPermit>,
+    ) -> Result<()>
+    where
+        T: Clone,
+    {
+        let cache = self.clone();
+        let cache_key = cache_key.to_owned();
+        let parts = middleware.parts()?;
+        middleware.spawn(Box::pin(async move {
+            let _permit = permit;
+            if let Ok(res) = fetch.await {
+                cache.finish_revalidation(&cache_key, &parts, res).await.ok();
+            }
+        }));
+        Ok(())
+    }
+
+    // Updates the entry with the response to a revalidation that finished in
+    // the background, unless `accept_revalidation_fn` rejects it.
+    async fn finish_revalidation(
+        &self,
+        cache_key: &str,
+        parts: &request::Parts,
+        res: HttpResponse,
+    ) -> Result<()> {
+        let rejected = res.status == 200
+            && self
+                .options
+                .accept_revalidation_fn
+                .as_ref()
+                .is_some_and(|accept| !accept(&res));
+        if rejected {
+            return Ok(());
+        }
+        let mut entry = self.manager.get(cache_key).await?;
+        if self.options.cache_variants {
+            if let Some(primary) = entry {
+                entry = self.select_variant(cache_key, primary, parts).await?;
+            }
+        }
+        if let Some(entry) = entry {
+            self.apply_revalidation(cache_key, entry, parts, res).await?;
+        }
+        Ok(())
+    }
+
+    // Adds this cache's entry to the `cache-status` header, after any entries
+    // added by caches closer to the origin. A previous entry of ours, which
+    // may have been stored with the response, is replaced.
+    // (https://www.rfc-editor.org/rfc/rfc9211#section-2)
+    fn add_cache_status(&self, res: &mut HttpResponse, params: &str) {
+        if !self.options.add_cache_status {
+            return;
+        }
+        let mut members: Vec<String> = res
+            .headers
+            .remove(CACHESTATUS)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|member| {
+                        member.split(';').next().map(str::trim)
+                            != Some(CACHE_STATUS_NAME)
+                    })
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        members.push(format!("{CACHE_STATUS_NAME}; {params}"));
+        res.headers.insert(CACHESTATUS.to_string(), members.join(", "));
+    }
+}
+
+// The heuristic freshness lifetime of a response with a `Last-Modified`
+// header, the fraction of the time it had gone unmodified as of its date.
+fn heuristic_lifetime(
+    res: &HttpResponse,
+    fraction: f32,
+    max: Option<Duration>,
+) -> Option<Duration> {
+    let header_date = |name: HeaderName| {
+        res.headers
+            .get(name.as_str())
+            .and_then(|date| httpdate::parse_http_date(date).ok())
+    };
+    let last_modified = header_date(LAST_MODIFIED)?;
+    let date = header_date(DATE).unwrap_or_else(SystemTime::now);
+    let unmodified = date.duration_since(last_modified).ok()?;
+    let lifetime =
+        Duration::try_from_secs_f32(unmodified.as_secs_f32() * fraction)
+            .ok()?;
+    Some(max.map_or(lifetime, |max| lifetime.min(max)))
+}
+
+fn is_permanent_redirect(status: u16) -> bool {
+    status == 301 || status == 308
+}
+
+// Gives the `Cache-Control` header value a `max-age` of `max_age`, replacing
+// those of its `max-age` and `s-maxage` directives and keeping the others.
+fn replace_max_age(cache_control: Option<&str>, max_age: Duration) -> String {
+    let max_age = max_age.as_secs();
+    let mut has_max_age = false;
+    let mut directives: Vec<String> = cache_control
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| {
+            let name = directive.split('=').next().unwrap_or_default().trim();
+            if name.eq_ignore_ascii_case("max-age") {
+                has_max_age = true;
+                format!("max-age={max_age}")
+            } else if name.eq_ignore_ascii_case("s-maxage") {
+                format!("s-maxage={max_age}")
+            } else {
+                directive.to_string()
+            }
+        })
+        .collect();
+    if !has_max_age {
+        directives.push(format!("max-age={max_age}"));
+    }
+    directives.join(", ")
+}
+
+// Adds a max-age directive to responses without an explicit freshness
+// lifetime. no-store responses are left alone since they're never stored.
+fn apply_default_max_age(res: &mut HttpResponse, max_age: Duration) {
+    if res.headers.contains_key(EXPIRES.as_str()) {
+        return;
+    }
+    let cache_control = res.cache_control();
+    if cache_control.max_age.is_some()
+        || cache_control.s_maxage.is_some()
+        || cache_control.no_store
+    {
+        return;
+    }
+    let mut directives: Vec<String> = res
+        .headers
+        .get(CACHE_CONTROL.as_str())
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|directive| !directive.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    directives.push(format!("max-age={}", max_age.as_secs()));
+    res.headers
+        .insert(CACHE_CONTROL.as_str().to_owned(), directives.join(", "));
+}
+
+// The values of a request header as they're hashed into variant keys, with
+// repeated headers joined by commas and runs of whitespace collapsed, so
+// requests differing only in formatting share a key.
+fn canonical_header_value(parts: &request::Parts, name: &str) -> String {
+    parts
+        .headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Sets the `Content-Length` header to the length of the body, for responses
+// with a status that allows a body.
+fn with_content_length(mut res: HttpResponse) -> HttpResponse {
+    if res.status >= 200 && res.status != 204 && res.status != 304 {
+        res.headers.insert(
+            CONTENT_LENGTH.as_str().to_owned(),
+            res.body.len().to_string(),
+        );
+    }
+    res
+}
+
+// How long after `stored_at` a failed response asks not to be retried, going
+// by its `Retry-After` header.
+fn retry_after(res: &HttpResponse, stored_at: SystemTime) -> Option<Duration> {
+    let value = res.headers.get(RETRY_AFTER.as_str())?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()
+            .map(|date| date.duration_since(stored_at).unwrap_or_default()),
+    }
+}
+
+// The uri used by the default cache key, `scheme://host[:port]/path?query`
+// with the default port of the scheme left out. Uris without a scheme or host
+// are used as they are.
+fn canonical_uri(uri: &http::Uri) -> String {
+    let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority())
+    else {
+        return uri.to_string();
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    let userinfo = authority
+        .as_str()
+        .rsplit_once('@')
+        .map_or(String::new(), |(userinfo, _)| format!("{userinfo}@"));
+    let host = authority.host().to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        _ => None,
+    };
+    let port = match authority.port_u16() {
+        Some(port) if Some(port) != default_port => format!(":{port}"),
+        _ => String::new(),
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    format!("{scheme}://{userinfo}{host}{port}{path}")
+}
+
+// The keys listed by an index of representations, or nothing when the
+// response isn't one.
+fn variant_index(res: &HttpResponse) -> Option<Vec<String>> {
+    res.headers.get(VARIANTS_INDEX)?;
+    serde_json::from_slice(&res.body).ok()
+}
+
+// Indexes are kept for as long as a cache can represent, 2^31 seconds, so
+// backends that expire entries by their policy don't drop them while the
+// representations they list are still stored.
+// (https://www.rfc-editor.org/rfc/rfc9111#section-1.2.2)
+fn index_policy(url: &Url) -> Result<CachePolicy> {
+    let parts = http::Request::get(url.as_str()).body(())?.into_parts().0;
+    let res = http::Response::builder()
+        .status(200)
+        .header(CACHE_CONTROL, "max-age=2147483648")
+        .body(())?;
+    Ok(CachePolicy::new(&parts, &res))
+}
+
+// The request header names listed by the `Vary` header of a response, in
+// order and without duplicates so equivalent headers compare equal
+fn vary_names(res: &HttpResponse) -> Vec<String> {
+    let mut names: Vec<String> = res
+        .headers
+        .get(VARY.as_str())
+        .map(|vary| {
+            vary.split(',')
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// The `cache-status` parameters of a response served from cache
+fn hit_status(policy: &CachePolicy) -> String {
+    format!("hit; ttl={}", policy.time_to_live(SystemTime::now()).as_secs())
+}
+
+// Determines if the validators of a conditional request match the response.
+// If-Modified-Since is only evaluated when If-None-Match is absent.
+// (https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2)
+fn client_validators_match(parts: &request::Parts, res: &HttpResponse) -> bool {
+    if let Some(if_none_match) = parts.headers.get(IF_NONE_MATCH) {
+        let (Ok(if_none_match), Some(etag)) =
+            (if_none_match.to_str(), res.headers.get(ETAG.as_str()))
+        else {
+            return false;
+        };
+        // If-None-Match uses the weak comparison function
+        let etag = etag.trim().trim_start_matches("W/");
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+    if let Some(if_modified_since) = parts.headers.get(IF_MODIFIED_SINCE) {
+        let if_modified_since = if_modified_since
+            .to_str()
+            .ok()
+            .and_then(|date| httpdate::parse_http_date(date).ok());
+        let last_modified = res
+            .headers
+            .get(LAST_MODIFIED.as_str())
+            .and_then(|date| httpdate::parse_http_date(date).ok());
+        if let (Some(if_modified_since), Some(last_modified)) =
+            (if_modified_since, last_modified)
+        {
+            return last_modified <= if_modified_since;
+        }
+    }
+    false
+}
+
+// The staleness the request accepts with its `max-stale` directive, which is
+// any staleness when it has no value.
+fn max_stale(parts: &request::Parts) -> Option<Duration> {
+    parts
+        .headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(','))
+        .find_map(|directive| {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (directive, None),
+            };
+            if !name.trim().eq_ignore_ascii_case("max-stale") {
+                return None;
+            }
+            Some(match value {
+                Some(value) => Duration::from_secs(
+                    value.trim().trim_matches('"').parse().ok()?,
+                ),
+                None => Duration::MAX,
+            })
+        })
+}
+
+// Removes the max-stale directive from the request's Cache-Control header(s)
+fn remove_max_stale(parts: &mut request::Parts) -> Result<()> {
+    let directives: Vec<String> = parts
+        .headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(','))
+        .map(str::trim)
+        .filter(|directive| {
+            !directive.is_empty()
+                && !directive.to_lowercase().starts_with("max-stale")
+        })
+        .map(ToString::to_string)
+        .collect();
+    parts.headers.remove(CACHE_CONTROL);
+    if !directives.is_empty() {
+        parts.headers.insert(
+            CACHE_CONTROL,
+            http::HeaderValue::from_str(&directives.join(", "))?,
+        );
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -0,0 +1,131 @@
+use crate::{CacheEntry, CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// A synchronous version of [`CacheManager`], for backends that don't need
+/// to await anything, such as a map behind a mutex. Wrap it in [`Blocking`]
+/// to use it as a [`CacheManager`].
+pub trait BlockingCacheManager: Send + Sync + 'static {
+    /// Attempts to pull a cached response and related policy from cache.
+    fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>>;
+    /// Attempts to cache a response and related policy.
+    fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse>;
+    /// Attempts to remove a record from cache.
+    fn delete(&self, cache_key: &str) -> Result<()>;
+    /// Attempts to pull a cached response's status, headers and policy
+    /// without its body. The body of the returned response may be left
+    /// empty.
+    ///
+    /// The default implementation pulls the full record with
+    /// [`BlockingCacheManager::get`].
+    fn get_metadata(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        self.get(cache_key)
+    }
+    /// Checks if a record exists in cache without needing to return it.
+    ///
+    /// The default implementation pulls the full record with
+    /// [`BlockingCacheManager::get`].
+    fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.get(cache_key)?.is_some())
+    }
+    /// Reports the total number of bytes currently stored, if the backend
+    /// can provide it cheaply. Returns `None` when the size is unknown.
+    fn size_bytes(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+    /// Reports the number of records currently stored, if the backend can
+    /// count them cheaply. Returns `None` when the count is unknown.
+    fn len(&self) -> Result<Option<usize>> {
+        Ok(None)
+    }
+    /// Reports whether no records are stored, going by
+    /// [`BlockingCacheManager::len`].
+    fn is_empty(&self) -> Result<Option<bool>> {
+        Ok(self.len()?.map(|len| len == 0))
+    }
+    /// Removes every record whose response is no longer fresh, returning the
+    /// number of records removed. The default implementation removes nothing
+    /// and returns `Ok(0)`, whether or not any records are stale.
+    fn delete_stale(&self) -> Result<usize> {
+        Ok(0)
+    }
+    /// Returns the key of every stored record, if the backend can list them.
+    /// Returns `None` when it can't, which is the default.
+    fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(None)
+    }
+    /// Returns the keys of every record whose response is no longer fresh.
+    /// The default implementation returns an empty list, whether or not any
+    /// records are stale.
+    fn stale_keys(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Implements [`CacheManager`] for a [`BlockingCacheManager`] by calling its
+/// methods directly.
+///
+/// The calls run on the async task rather than on a blocking thread, so this
+/// is only suited to backends that return quickly, like in-process maps.
+#[derive(Debug, Clone, Default)]
+pub struct Blocking<M: BlockingCacheManager>(pub M);
+
+#[async_trait::async_trait]
+impl<M: BlockingCacheManager> CacheManager for Blocking<M> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        self.0.get(cache_key)
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.0.put(cache_key, res, policy)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.0.delete(cache_key)
+    }
+
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        self.0.get_metadata(cache_key)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.0.contains(cache_key)
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        self.0.size_bytes()
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        self.0.len()
+    }
+
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        self.0.is_empty()
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        self.0.delete_stale()
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        self.0.keys()
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        self.0.stale_keys()
+    }
+}
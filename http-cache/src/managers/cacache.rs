@@ -1,6 +1,9 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-use crate::{CacheManager, HttpResponse, Result};
+use crate::{CacheEntry, CacheManager, HttpResponse, Result};
 
 use http_cache_semantics::CachePolicy;
 use serde::{Deserialize, Serialize};
@@ -25,6 +28,17 @@ struct Store {
     policy: CachePolicy,
 }
 
+// Reads the whole index on a blocking thread, since cacache can only list it
+// synchronously.
+pub(crate) async fn list(path: &Path) -> Result<Vec<cacache::Metadata>> {
+    let path = path.to_owned();
+    let entries = blocking::unblock(move || {
+        cacache::list_sync(path).collect::<cacache::Result<Vec<_>>>()
+    })
+    .await?;
+    Ok(entries)
+}
+
 #[allow(dead_code)]
 impl CACacheManager {
     /// Clears out the entire cache.
@@ -36,17 +50,14 @@ impl CACacheManager {
 
 #[async_trait::async_trait]
 impl CacheManager for CACacheManager {
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
         let store: Store = match cacache::read(&self.path, cache_key).await {
             Ok(d) => bincode::deserialize(&d)?,
             Err(_e) => {
                 return Ok(None);
             }
         };
-        Ok(Some((store.response, store.policy)))
+        Ok(Some(CacheEntry::new(store.response, store.policy)))
     }
 
     async fn put(
@@ -64,4 +75,34 @@ impl CacheManager for CACacheManager {
     async fn delete(&self, cache_key: &str) -> Result<()> {
         Ok(cacache::remove(&self.path, cache_key).await?)
     }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(cacache::metadata(&self.path, cache_key).await?.is_some())
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.stale_keys().await?;
+        for key in &stale {
+            cacache::remove(&self.path, key).await?;
+        }
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        let keys = list(&self.path).await?.into_iter().map(|m| m.key);
+        Ok(Some(keys.collect()))
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        let mut stale = Vec::new();
+        for key in list(&self.path).await?.into_iter().map(|m| m.key) {
+            let store: Store =
+                bincode::deserialize(&cacache::read(&self.path, &key).await?)?;
+            if store.policy.is_stale(now) {
+                stale.push(key);
+            }
+        }
+        Ok(stale)
+    }
 }
@@ -0,0 +1,132 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::{CacheEntry, CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+/// Implements [`CacheManager`] with [`cacache`](https://github.com/zkat/cacache-rs) as the backend,
+/// storing each distinct response body only once.
+///
+/// Bodies are written to cacache's content-addressed store and every cache key
+/// references its body by hash, so keys with identical bodies share a single blob.
+/// Deleting a key leaves its body in place, since other keys may still reference it.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
+#[derive(Debug, Clone)]
+pub struct DedupCACacheManager {
+    /// Directory where the cache will be stored.
+    pub path: PathBuf,
+}
+
+impl Default for DedupCACacheManager {
+    fn default() -> Self {
+        Self { path: "./http-cacache".into() }
+    }
+}
+
+// The response is stored without its body, which lives in the content store.
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl DedupCACacheManager {
+    /// Clears out the entire cache.
+    pub async fn clear(&self) -> Result<()> {
+        cacache::clear(&self.path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for DedupCACacheManager {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        let metadata = match cacache::metadata(&self.path, cache_key).await? {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        };
+        let store: Store = match metadata.raw_metadata {
+            Some(raw) => bincode::deserialize(&raw)?,
+            None => return Ok(None),
+        };
+        let mut response = store.response;
+        response.body =
+            cacache::read_hash(&self.path, &metadata.integrity).await?;
+        Ok(Some(CacheEntry::new(response, store.policy)))
+    }
+
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        let metadata = match cacache::metadata(&self.path, cache_key).await? {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        };
+        let store: Store = match metadata.raw_metadata {
+            Some(raw) => bincode::deserialize(&raw)?,
+            None => return Ok(None),
+        };
+        Ok(Some(CacheEntry::new(store.response, store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let integrity = cacache::write_hash(&self.path, &response.body).await?;
+        let data = Store {
+            response: HttpResponse {
+                body: Vec::new(),
+                headers: response.headers.clone(),
+                status: response.status,
+                url: response.url.clone(),
+                version: response.version,
+            },
+            policy,
+        };
+        let opts = cacache::WriteOpts::new()
+            .integrity(integrity)
+            .size(response.body.len())
+            .raw_metadata(bincode::serialize(&data)?);
+        cacache::index::insert_async(&self.path, &cache_key, opts).await?;
+        Ok(response)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        Ok(cacache::remove(&self.path, cache_key).await?)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(cacache::metadata(&self.path, cache_key).await?.is_some())
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.stale_keys().await?;
+        for key in &stale {
+            cacache::remove(&self.path, key).await?;
+        }
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        let keys = super::cacache::list(&self.path).await?;
+        Ok(Some(keys.into_iter().map(|metadata| metadata.key).collect()))
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        let mut stale = Vec::new();
+        for metadata in super::cacache::list(&self.path).await? {
+            let Some(raw) = metadata.raw_metadata else { continue };
+            let store: Store = bincode::deserialize(&raw)?;
+            if store.policy.is_stale(now) {
+                stale.push(metadata.key);
+            }
+        }
+        Ok(stale)
+    }
+}
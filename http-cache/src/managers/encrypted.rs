@@ -0,0 +1,202 @@
+use std::{collections::HashMap, fmt, time::SystemTime};
+
+use crate::{
+    error::EncryptionError, CacheEntry, CacheManager, HttpResponse, Result,
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use http::{
+    header::{AGE, CACHE_CONTROL, DATE, ETAG, EXPIRES, LAST_MODIFIED},
+    HeaderValue,
+};
+use http_cache_semantics::{CacheOptions, CachePolicy};
+use serde::{Deserialize, Serialize};
+
+// The length of the random nonce stored ahead of each ciphertext.
+const NONCE_LEN: usize = 12;
+
+/// Wraps any [`CacheManager`] and encrypts entries with AES-256-GCM before
+/// they're handed to it, so responses are only stored encrypted.
+///
+/// The response and its policy are serialized and encrypted together, with
+/// the cache key as associated data so an entry can't be moved to another
+/// key. The wrapped manager stores the ciphertext as the body of a response
+/// whose only headers are its `ETag` and `Last-Modified` validators, along
+/// with a policy that only carries the response's freshness, which its
+/// housekeeping like `delete_stale` relies on. Entries that fail to decrypt,
+/// because they were tampered with or encrypted with another key, are
+/// treated as misses.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-encrypted")))]
+#[derive(Clone)]
+pub struct EncryptedManager<M: CacheManager> {
+    /// The wrapped manager that the encrypted entries are stored in.
+    pub inner: M,
+    cipher: Aes256Gcm,
+    cache_options: CacheOptions,
+}
+
+impl<M: CacheManager + fmt::Debug> fmt::Debug for EncryptedManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedManager")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl<M: CacheManager> EncryptedManager<M> {
+    /// Create a new encrypted manager wrapping the provided manager, that
+    /// encrypts entries with the 256 bit key
+    pub fn new(inner: M, key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            cipher: Aes256Gcm::new(key.into()),
+            cache_options: CacheOptions::default(),
+        }
+    }
+
+    /// Sets the options the freshness policy handed to the wrapped manager
+    /// is built with. They should match the `cache_options` of the cache,
+    /// so that, for example, `private` responses stored by a private cache
+    /// aren't considered stale by the wrapped manager.
+    #[must_use]
+    pub fn with_cache_options(mut self, cache_options: CacheOptions) -> Self {
+        self.cache_options = cache_options;
+        self
+    }
+
+    fn encrypt(&self, cache_key: &str, store: &Store) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let plaintext = bincode::serialize(store)?;
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload { msg: &plaintext, aad: cache_key.as_bytes() },
+            )
+            .map_err(|_| EncryptionError)?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    fn decrypt(&self, cache_key: &str, sealed: &[u8]) -> Option<Store> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload { msg: ciphertext, aad: cache_key.as_bytes() },
+            )
+            .ok()?;
+        bincode::deserialize(&plaintext).ok()
+    }
+}
+
+// A policy with only the freshness of the response, so its other headers
+// aren't stored unencrypted.
+fn freshness_policy(
+    response: &HttpResponse,
+    options: CacheOptions,
+) -> Result<CachePolicy> {
+    let req =
+        http::Request::get(response.url.as_str()).body(())?.into_parts().0;
+    let mut res = http::Response::builder()
+        .status(response.status)
+        .body(())?
+        .into_parts()
+        .0;
+    for name in [AGE, CACHE_CONTROL, DATE, EXPIRES, LAST_MODIFIED] {
+        if let Some(value) = response.headers.get(name.as_str()) {
+            res.headers.insert(name, HeaderValue::from_str(value)?);
+        }
+    }
+    Ok(CachePolicy::new_options(&req, &res, SystemTime::now(), options))
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for EncryptedManager<M> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        let Some(entry) = self.inner.get(cache_key).await? else {
+            return Ok(None);
+        };
+        Ok(self.decrypt(cache_key, &entry.response.body).map(|store| {
+            CacheEntry {
+                response: store.response,
+                policy: store.policy,
+                ..entry
+            }
+        }))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let inner_policy = freshness_policy(&response, self.cache_options)?;
+        // The validators let the wrapped manager tell entries that can be
+        // revalidated apart from the ones that can't.
+        let headers: HashMap<String, String> = [ETAG, LAST_MODIFIED]
+            .iter()
+            .filter_map(|name| {
+                let value = response.headers.get(name.as_str())?;
+                Some((name.as_str().to_owned(), value.clone()))
+            })
+            .collect();
+        let store = Store { response, policy };
+        let sealed = HttpResponse {
+            body: self.encrypt(&cache_key, &store)?,
+            headers,
+            status: store.response.status,
+            url: store.response.url.clone(),
+            version: store.response.version,
+        };
+        self.inner.put(cache_key, sealed, inner_policy).await?;
+        Ok(store.response)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.inner.delete(cache_key).await
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.inner.contains(cache_key).await
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        self.inner.size_bytes().await
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        self.inner.len().await
+    }
+
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        self.inner.is_empty().await
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        self.inner.delete_stale().await
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        self.inner.keys().await
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        self.inner.stale_keys().await
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, SystemTime},
+};
+
+use crate::{CacheEntry, CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// Implements [`CacheManager`] with a `HashMap` behind a lock, for tests and
+/// small apps that don't need a cache backend.
+///
+/// Entries are never evicted to make room. Stale entries are kept, since they
+/// can still be revalidated or served by `CacheMode::ForceCache`,
+/// `CacheMode::OnlyIfCached` and `max-stale` requests, unless the manager is
+/// created with [`InMemoryManager::with_expire_after`].
+#[derive(Clone, Default)]
+pub struct InMemoryManager {
+    entries: Arc<RwLock<HashMap<String, CacheEntry>>>,
+    expire_after: Option<Duration>,
+}
+
+impl fmt::Debug for InMemoryManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InMemoryManager").finish_non_exhaustive()
+    }
+}
+
+impl InMemoryManager {
+    /// Create a new, empty manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a new, empty manager that removes stale entries older than
+    /// `expire_after` when they're read, as a hard bound on how long a
+    /// response may be kept past its freshness.
+    pub fn with_expire_after(expire_after: Duration) -> Self {
+        Self { expire_after: Some(expire_after), ..Self::default() }
+    }
+    /// Returns the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+    /// Returns whether the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+    /// Clears out the entire cache.
+    pub fn clear(&self) {
+        self.write().clear();
+    }
+    // The lock is never held across an await, so a panic while holding it
+    // can't leave an entry half written and the poison can be ignored.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, CacheEntry>> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, CacheEntry>> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+    fn is_expired(&self, entry: &CacheEntry, now: SystemTime) -> bool {
+        self.expire_after.is_some_and(|expire_after| {
+            entry.is_stale(now) && entry.age(now) > expire_after
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for InMemoryManager {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        let entry = match self.read().get(cache_key) {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
+        if self.is_expired(&entry, SystemTime::now()) {
+            self.write().remove(cache_key);
+            return Ok(None);
+        }
+        Ok(Some(entry))
+    }
+
+    // Skips cloning the body, leaving expired entries for `get` to remove.
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        let entries = self.read();
+        let Some(entry) = entries.get(cache_key) else { return Ok(None) };
+        if self.is_expired(entry, SystemTime::now()) {
+            return Ok(None);
+        }
+        let response = HttpResponse {
+            body: Vec::new(),
+            headers: entry.response.headers.clone(),
+            status: entry.response.status,
+            url: entry.response.url.clone(),
+            version: entry.response.version,
+        };
+        Ok(Some(CacheEntry::new(response, entry.policy.clone())))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.write()
+            .insert(cache_key, CacheEntry::new(response.clone(), policy));
+        Ok(response)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.write().remove(cache_key);
+        Ok(())
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.read().contains_key(cache_key))
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        Ok(Some(
+            self.read()
+                .values()
+                .map(|entry| entry.response.body.len() as u64)
+                .sum(),
+        ))
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        Ok(Some(self.read().len()))
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let now = SystemTime::now();
+        let mut entries = self.write();
+        let before = entries.len();
+        entries.retain(|_, entry| !entry.is_stale(now));
+        Ok(before - entries.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(Some(self.read().keys().cloned().collect()))
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        Ok(self
+            .read()
+            .iter()
+            .filter(|(_, entry)| entry.is_stale(now))
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+}
@@ -1,5 +1,59 @@
+pub mod blocking;
+
 #[cfg(feature = "manager-cacache")]
 pub mod cacache;
 
+#[cfg(feature = "manager-cacache")]
+pub mod cacache_dedup;
+
+#[cfg(feature = "manager-encrypted")]
+pub mod encrypted;
+
+pub mod in_memory;
+
 #[cfg(feature = "manager-moka")]
 pub mod moka;
+
+#[cfg(feature = "test-util")]
+pub mod recording;
+
+pub mod size_limited;
+
+pub mod timed;
+
+/// An operation performed against a [`CacheManager`](crate::CacheManager), as
+/// reported by
+/// [`TimedManager`](crate::TimedManager) and recorded by `RecordingManager`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Op {
+    /// A call to [`CacheManager::get`](crate::CacheManager::get)
+    Get,
+    /// A call to [`CacheManager::put`](crate::CacheManager::put)
+    Put,
+    /// A call to [`CacheManager::delete`](crate::CacheManager::delete)
+    Delete,
+    /// A call to [`CacheManager::contains`](crate::CacheManager::contains)
+    Contains,
+    /// A call to
+    /// [`CacheManager::get_metadata`](crate::CacheManager::get_metadata)
+    GetMetadata,
+}
+
+impl Op {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Get => "get",
+            Self::Put => "put",
+            Self::Delete => "delete",
+            Self::Contains => "contains",
+            Self::GetMetadata => "get_metadata",
+        }
+    }
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.as_str())
+    }
+}
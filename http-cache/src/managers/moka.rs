@@ -1,9 +1,14 @@
-use crate::{CacheManager, HttpResponse, Result};
+use crate::{CacheEntry, CacheManager, HttpResponse, Result};
 
-use std::{fmt, sync::Arc};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use async_io::Timer;
 use http_cache_semantics::CachePolicy;
-use moka::future::Cache;
+use moka::future::{Cache, CacheBuilder};
 use serde::{Deserialize, Serialize};
 
 /// Implements [`CacheManager`] with [`moka`](https://github.com/moka-rs/moka) as the backend.
@@ -27,6 +32,8 @@ impl Default for MokaManager {
     }
 }
 
+type MokaCache = Cache<String, Arc<Vec<u8>>>;
+
 #[derive(Debug, Deserialize, Serialize)]
 struct Store {
     response: HttpResponse,
@@ -38,25 +45,78 @@ impl MokaManager {
     pub fn new(cache: Cache<String, Arc<Vec<u8>>>) -> Self {
         Self { cache: Arc::new(cache) }
     }
+    /// Create a new manager whose cache holds up to `max_bytes` of
+    /// serialized entries, rather than the 42 entries of
+    /// [`MokaManager::default`].
+    pub fn with_max_bytes(max_bytes: u64) -> Self {
+        Self::new(Self::builder().max_capacity(max_bytes).build())
+    }
+    /// Create a new manager with the default capacity that evicts entries
+    /// which haven't been read or written for the provided duration,
+    /// regardless of whether they are still fresh.
+    pub fn with_time_to_idle(time_to_idle: Duration) -> Self {
+        Self::new(
+            Cache::builder()
+                .max_capacity(42)
+                .time_to_idle(time_to_idle)
+                .build(),
+        )
+    }
+    /// Returns a cache builder that weighs entries by their size in bytes,
+    /// so its capacity and weighted size are in bytes too.
+    pub fn builder() -> CacheBuilder<String, Arc<Vec<u8>>, MokaCache> {
+        Cache::builder().weigher(|key: &String, bytes: &Arc<Vec<u8>>| {
+            (key.len() + bytes.len()).try_into().unwrap_or(u32::MAX)
+        })
+    }
     /// Clears out the entire cache.
     pub async fn clear(&self) -> Result<()> {
         self.cache.invalidate_all();
         self.cache.run_pending_tasks().await;
         Ok(())
     }
+    /// Sheds entries when the stored responses take up more than
+    /// `soft_limit` bytes. Stale entries are removed first, and if that
+    /// isn't enough the entire cache is cleared. Returns whether anything
+    /// was evicted.
+    pub async fn shed_memory(&self, soft_limit: u64) -> Result<bool> {
+        if self.stored_bytes() <= soft_limit {
+            return Ok(false);
+        }
+        self.delete_stale().await?;
+        if self.stored_bytes() > soft_limit {
+            self.clear().await?;
+        }
+        Ok(true)
+    }
+    /// Checks the soft limit with [`MokaManager::shed_memory`] every
+    /// `period`. This never returns unless an error occurs, so it should be
+    /// spawned onto the runtime of your choice.
+    pub async fn watch_memory(
+        &self,
+        soft_limit: u64,
+        period: Duration,
+    ) -> Result<()> {
+        loop {
+            Timer::after(period).await;
+            self.shed_memory(soft_limit).await?;
+        }
+    }
+    // The summed size of the serialized entries, whatever the cache's
+    // capacity is measured in.
+    fn stored_bytes(&self) -> u64 {
+        self.cache.iter().map(|(_, bytes)| bytes.len() as u64).sum()
+    }
 }
 
 #[async_trait::async_trait]
 impl CacheManager for MokaManager {
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
         let store: Store = match self.cache.get(cache_key).await {
             Some(d) => bincode::deserialize(&d)?,
             None => return Ok(None),
         };
-        Ok(Some((store.response, store.policy)))
+        Ok(Some(CacheEntry::new(store.response, store.policy)))
     }
 
     async fn put(
@@ -77,4 +137,43 @@ impl CacheManager for MokaManager {
         self.cache.run_pending_tasks().await;
         Ok(())
     }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.contains_key(cache_key))
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        Ok(Some(self.stored_bytes()))
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        // The count only reflects recent writes once they're applied.
+        self.cache.run_pending_tasks().await;
+        Ok(Some(self.cache.entry_count().try_into()?))
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.stale_keys().await?;
+        for key in &stale {
+            self.cache.invalidate(key.as_str()).await;
+        }
+        self.cache.run_pending_tasks().await;
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        Ok(Some(self.cache.iter().map(|(key, _)| key.to_string()).collect()))
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now();
+        let mut stale = Vec::new();
+        for (key, bytes) in self.cache.iter() {
+            let store: Store = bincode::deserialize(&bytes)?;
+            if store.policy.is_stale(now) {
+                stale.push(key.to_string());
+            }
+        }
+        Ok(stale)
+    }
 }
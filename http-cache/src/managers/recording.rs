@@ -0,0 +1,101 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{CacheEntry, CacheManager, HttpResponse, Op, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// Wraps any [`CacheManager`] and keeps a log of the calls made to it.
+///
+/// Intended for tests that need to assert exactly which manager methods
+/// were called, and in what order, without standing up a mock server.
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+#[derive(Debug, Clone)]
+pub struct RecordingManager<M: CacheManager> {
+    /// The wrapped manager that all calls are forwarded to.
+    pub inner: M,
+    calls: Arc<Mutex<Vec<(Op, String)>>>,
+}
+
+impl<M: CacheManager> RecordingManager<M> {
+    /// Create a new recording manager wrapping the provided manager
+    pub fn new(inner: M) -> Self {
+        Self { inner, calls: Arc::default() }
+    }
+
+    /// Returns the calls recorded so far, oldest first.
+    pub fn calls(&self) -> Vec<(Op, String)> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Clears the recorded calls.
+    pub fn clear_calls(&self) {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    fn record(&self, op: Op, cache_key: &str) {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((op, cache_key.to_string()));
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for RecordingManager<M> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        self.record(Op::Get, cache_key);
+        self.inner.get(cache_key).await
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.record(Op::Put, &cache_key);
+        self.inner.put(cache_key, response, policy).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.record(Op::Delete, cache_key);
+        self.inner.delete(cache_key).await
+    }
+
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        self.record(Op::GetMetadata, cache_key);
+        self.inner.get_metadata(cache_key).await
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.record(Op::Contains, cache_key);
+        self.inner.contains(cache_key).await
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        self.inner.size_bytes().await
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        self.inner.len().await
+    }
+
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        self.inner.is_empty().await
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        self.inner.delete_stale().await
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        self.inner.keys().await
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        self.inner.stale_keys().await
+    }
+}
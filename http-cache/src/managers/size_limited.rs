@@ -0,0 +1,209 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{CacheEntry, CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// Wraps any [`CacheManager`] and caps the total size of the entries stored
+/// through it, deleting the least recently used entries when a new one puts
+/// it over the cap.
+///
+/// Meant for persistent managers like `CACacheManager` that otherwise grow
+/// without bound. The size of an entry is approximated by the length of its
+/// body and headers. Sizes and access order are kept in an in-memory index.
+/// A manager created with [`SizeLimitedManager::new`] only tracks the entries
+/// stored before it was created once they're read, while
+/// [`SizeLimitedManager::load`] seeds the index with every entry the wrapped
+/// manager lists.
+#[derive(Debug, Clone)]
+pub struct SizeLimitedManager<M: CacheManager> {
+    /// The wrapped manager that all calls are forwarded to.
+    pub inner: M,
+    max_size: u64,
+    index: Arc<Mutex<Index>>,
+}
+
+// The approximate size of every tracked entry, ordered by when it was last
+// used.
+#[derive(Debug, Default)]
+struct Index {
+    entries: HashMap<String, (u64, u64)>,
+    by_use: BTreeMap<u64, String>,
+    total: u64,
+    uses: u64,
+}
+
+impl Index {
+    fn touch(&mut self, cache_key: &str, size: u64) {
+        self.remove(cache_key);
+        self.uses += 1;
+        self.entries.insert(cache_key.to_string(), (size, self.uses));
+        self.by_use.insert(self.uses, cache_key.to_string());
+        self.total += size;
+    }
+
+    fn remove(&mut self, cache_key: &str) {
+        if let Some((size, used)) = self.entries.remove(cache_key) {
+            self.by_use.remove(&used);
+            self.total -= size;
+        }
+    }
+
+    // Removes the least recently used entries until the total is within
+    // `max_size`, returning their keys.
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total > max_size {
+            let Some((_, cache_key)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = self.entries.remove(&cache_key) {
+                self.total -= size;
+            }
+            evicted.push(cache_key);
+        }
+        evicted
+    }
+}
+
+fn entry_size(response: &HttpResponse) -> u64 {
+    let headers: usize = response
+        .headers
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+    (response.body.len() + headers) as u64
+}
+
+impl<M: CacheManager> SizeLimitedManager<M> {
+    /// Create a new size limited manager wrapping the provided manager, that
+    /// keeps the entries stored through it within `max_size` bytes
+    pub fn new(inner: M, max_size: u64) -> Self {
+        Self { inner, max_size, index: Arc::default() }
+    }
+
+    /// Create a new size limited manager wrapping the provided manager, with
+    /// its index seeded from the entries the manager already holds, as
+    /// listed by [`CacheManager::keys`]. Their order of use isn't known, so
+    /// they're considered used in the order they're listed. Entries over
+    /// `max_size` are deleted right away.
+    pub async fn load(inner: M, max_size: u64) -> Result<Self> {
+        let manager = Self::new(inner, max_size);
+        for cache_key in manager.inner.keys().await?.unwrap_or_default() {
+            if let Some(entry) = manager.inner.get(&cache_key).await? {
+                manager.touch(&cache_key, entry_size(&entry.response)).await?;
+            }
+        }
+        Ok(manager)
+    }
+
+    /// Returns the approximate total size, in bytes, of the tracked entries.
+    pub fn tracked_size(&self) -> u64 {
+        self.index().total
+    }
+
+    // The lock is never held across an await, so a panic while holding it
+    // can't leave the index half updated and the poison can be ignored.
+    fn index(&self) -> MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn touch(&self, cache_key: &str, size: u64) -> Result<()> {
+        let evicted = {
+            let mut index = self.index();
+            index.touch(cache_key, size);
+            index.evict(self.max_size)
+        };
+        for cache_key in evicted {
+            self.inner.delete(&cache_key).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for SizeLimitedManager<M> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        let entry = self.inner.get(cache_key).await?;
+        match &entry {
+            Some(entry) => {
+                self.touch(cache_key, entry_size(&entry.response)).await?
+            }
+            None => self.index().remove(cache_key),
+        }
+        Ok(entry)
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let size = entry_size(&response);
+        let res = self.inner.put(cache_key.clone(), response, policy).await?;
+        self.touch(&cache_key, size).await?;
+        Ok(res)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.index().remove(cache_key);
+        self.inner.delete(cache_key).await
+    }
+
+    // The body may be left out, so the entry is only marked as used when it's
+    // already tracked.
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        let entry = self.inner.get_metadata(cache_key).await?;
+        let mut index = self.index();
+        match (&entry, index.entries.get(cache_key)) {
+            (Some(_), Some(&(size, _))) => index.touch(cache_key, size),
+            (Some(_), None) => {}
+            (None, _) => index.remove(cache_key),
+        }
+        Ok(entry)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.inner.contains(cache_key).await
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        self.inner.size_bytes().await
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        self.inner.len().await
+    }
+
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        self.inner.is_empty().await
+    }
+
+    // The stale entries are deleted one by one so they're dropped from the
+    // index too, unless the wrapped manager can't list them.
+    async fn delete_stale(&self) -> Result<usize> {
+        let stale = self.inner.stale_keys().await?;
+        if stale.is_empty() {
+            return self.inner.delete_stale().await;
+        }
+        for cache_key in &stale {
+            self.delete(cache_key).await?;
+        }
+        Ok(stale.len())
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        self.inner.keys().await
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        self.inner.stale_keys().await
+    }
+}
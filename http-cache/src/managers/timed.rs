@@ -0,0 +1,129 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{instrument, CacheEntry, CacheManager, HttpResponse, Op, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// A closure that takes the operation performed against a manager, the cache
+/// key it was performed for, and how long it took.
+pub type ManagerTimingFn = Arc<dyn Fn(Op, &str, Duration) + Send + Sync>;
+
+/// Wraps any [`CacheManager`] and times the calls made to it, to measure the
+/// latency of the cache backend separately from the origin.
+///
+/// Durations are recorded in the `http_cache.manager_latency` histogram when
+/// the `metrics` feature is enabled, labeled with the `op`, and passed to the
+/// timing closure when one is set.
+#[derive(Clone)]
+pub struct TimedManager<M: CacheManager> {
+    /// The wrapped manager that all calls are forwarded to.
+    pub inner: M,
+    timing_fn: Option<ManagerTimingFn>,
+}
+
+impl<M: CacheManager + fmt::Debug> fmt::Debug for TimedManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedManager")
+            .field("inner", &self.inner)
+            .field("timing_fn", &"Fn(Op, &str, Duration)")
+            .finish()
+    }
+}
+
+impl<M: CacheManager> TimedManager<M> {
+    /// Create a new timed manager wrapping the provided manager
+    pub fn new(inner: M) -> Self {
+        Self { inner, timing_fn: None }
+    }
+
+    /// Create a new timed manager wrapping the provided manager that also
+    /// passes every duration to the closure
+    pub fn with_timing_fn(
+        inner: M,
+        timing_fn: impl Fn(Op, &str, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        Self { inner, timing_fn: Some(Arc::new(timing_fn)) }
+    }
+
+    fn report(&self, op: Op, cache_key: &str, start: Instant) {
+        let elapsed = start.elapsed();
+        instrument::manager_latency(op, elapsed);
+        if let Some(timing_fn) = &self.timing_fn {
+            timing_fn(op, cache_key, elapsed);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for TimedManager<M> {
+    async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+        let start = Instant::now();
+        let entry = self.inner.get(cache_key).await;
+        self.report(Op::Get, cache_key, start);
+        entry
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let start = Instant::now();
+        let res = self.inner.put(cache_key.clone(), response, policy).await;
+        self.report(Op::Put, &cache_key, start);
+        res
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let start = Instant::now();
+        let res = self.inner.delete(cache_key).await;
+        self.report(Op::Delete, cache_key, start);
+        res
+    }
+
+    async fn get_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<CacheEntry>> {
+        let start = Instant::now();
+        let entry = self.inner.get_metadata(cache_key).await;
+        self.report(Op::GetMetadata, cache_key, start);
+        entry
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let start = Instant::now();
+        let contains = self.inner.contains(cache_key).await;
+        self.report(Op::Contains, cache_key, start);
+        contains
+    }
+
+    async fn size_bytes(&self) -> Result<Option<u64>> {
+        self.inner.size_bytes().await
+    }
+
+    async fn len(&self) -> Result<Option<usize>> {
+        self.inner.len().await
+    }
+
+    async fn is_empty(&self) -> Result<Option<bool>> {
+        self.inner.is_empty().await
+    }
+
+    async fn delete_stale(&self) -> Result<usize> {
+        self.inner.delete_stale().await
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>> {
+        self.inner.keys().await
+    }
+
+    async fn stale_keys(&self) -> Result<Vec<String>> {
+        self.inner.stale_keys().await
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    error, CacheMode, HitOrMiss, HttpCacheOptions, HttpResponse, HttpVersion,
-    Result,
+    error, CacheControl, CacheEntry, CacheKeyFormat, CacheMode, GlobPattern,
+    HitOrMiss, HttpCacheOptions, HttpResponse, HttpVersion, Result,
 };
 use http::{header::CACHE_CONTROL, StatusCode};
 use http_cache_semantics::CacheOptions;
@@ -32,23 +32,345 @@ fn cache_mode() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn cache_mode_names() -> Result<()> {
+    let modes = [
+        (CacheMode::Default, "default"),
+        (CacheMode::NoStore, "no-store"),
+        (CacheMode::Reload, "reload"),
+        (CacheMode::NoCache, "no-cache"),
+        (CacheMode::ForceCache, "force-cache"),
+        (CacheMode::OnlyIfCached, "only-if-cached"),
+        (CacheMode::IgnoreRules, "ignore-rules"),
+    ];
+    for (mode, name) in modes {
+        assert_eq!(mode.to_string(), name);
+        assert_eq!(CacheMode::from_str(name)?, mode);
+        let json = serde_json::to_string(&mode)?;
+        assert_eq!(json, format!("\"{name}\""));
+        assert_eq!(serde_json::from_str::<CacheMode>(&json)?, mode);
+    }
+    assert!(CacheMode::from_str("NoStore").is_err());
+    assert!(serde_json::from_str::<CacheMode>("\"nope\"").is_err());
+    Ok(())
+}
+
 #[test]
 fn cache_options() -> Result<()> {
     // Testing the Debug, Default and Clone traits for the HttpCacheOptions struct
     let mut opts = HttpCacheOptions::default();
-    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true }");
+    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true, answer_client_conditionals: false, cache_key_format: CacheKeyFormat { delimiter: \":\", escape: false }, cache_preflight: false, cache_unknown_length: true, response_cache_control_fn: \"Fn(&HttpResponse) -> Option<CacheControl>\", honor_surrogate_control: false, cache_set_cookie: false, error_cache_ttl: None, failed_requests: FailedRequests { len: 0 }, path_rules: [], revalidation_request_fn: \"Fn(&mut request::Builder)\", add_cache_status: false, serve_stale_after: None, background_revalidate_always: false, vary_normalize_fn: \"Fn(&str, &str) -> Option<String>\", entry_metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", honor_pragma: true, accept_revalidation_fn: \"Fn(&HttpResponse) -> bool\", store_request: false, override_must_revalidate_on_error: false, max_body_size: None, on_store: \"Fn(&str, StoreOutcome)\", should_store_fn: \"Fn(&request::Parts, &HttpResponse) -> bool\", response_date_override: None, cache_variants: false, variants_lock: VariantsLock, max_entries_per_host: None, host_index: HostIndex { hosts: 0 }, optional_cache_key: \"Fn(&request::Parts) -> Option<String>\", max_background_revalidations: None, revalidations: RevalidationCounter { in_flight: 0 }, shared_fn: \"Fn(&request::Parts) -> bool\", cacheable_methods: [GET, HEAD], default_max_age: None, bypass_header: None, minimal_headers: false, on_stale_served: \"Fn(&str, StaleReason)\", vary_hash_fn: \"Fn(&str) -> String\", compression_offload_threshold: 65536, policy_fn: \"Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy\", priority_fn: \"Fn(&request::Parts, &HttpResponse) -> u8\", key_by_final_url: false, partition_fn: \"Fn(&request::Parts) -> String\", permanent_redirect_ttl: None, validate_cached_fn: \"Fn(&HttpResponse) -> bool\", heuristic_fraction: None, heuristic_max_age: None, disable_heuristics: false, hard_max_age: None, head_refreshes_get: false, on_key_bypass: \"Fn(&request::Parts, KeyBypass)\", recompute_content_length: false, transform_for_storage_fn: \"Fn(HttpResponse) -> HttpResponse\", honor_no_transform: true }");
     opts.cache_options = Some(CacheOptions::default());
-    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: Some(CacheOptions { shared: true, cache_heuristic: 0.1, immutable_min_time_to_live: 86400s, ignore_cargo_cult: false }), cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true }");
+    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: Some(CacheOptions { shared: true, cache_heuristic: 0.1, immutable_min_time_to_live: 86400s, ignore_cargo_cult: false }), cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true, answer_client_conditionals: false, cache_key_format: CacheKeyFormat { delimiter: \":\", escape: false }, cache_preflight: false, cache_unknown_length: true, response_cache_control_fn: \"Fn(&HttpResponse) -> Option<CacheControl>\", honor_surrogate_control: false, cache_set_cookie: false, error_cache_ttl: None, failed_requests: FailedRequests { len: 0 }, path_rules: [], revalidation_request_fn: \"Fn(&mut request::Builder)\", add_cache_status: false, serve_stale_after: None, background_revalidate_always: false, vary_normalize_fn: \"Fn(&str, &str) -> Option<String>\", entry_metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", honor_pragma: true, accept_revalidation_fn: \"Fn(&HttpResponse) -> bool\", store_request: false, override_must_revalidate_on_error: false, max_body_size: None, on_store: \"Fn(&str, StoreOutcome)\", should_store_fn: \"Fn(&request::Parts, &HttpResponse) -> bool\", response_date_override: None, cache_variants: false, variants_lock: VariantsLock, max_entries_per_host: None, host_index: HostIndex { hosts: 0 }, optional_cache_key: \"Fn(&request::Parts) -> Option<String>\", max_background_revalidations: None, revalidations: RevalidationCounter { in_flight: 0 }, shared_fn: \"Fn(&request::Parts) -> bool\", cacheable_methods: [GET, HEAD], default_max_age: None, bypass_header: None, minimal_headers: false, on_stale_served: \"Fn(&str, StaleReason)\", vary_hash_fn: \"Fn(&str) -> String\", compression_offload_threshold: 65536, policy_fn: \"Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy\", priority_fn: \"Fn(&request::Parts, &HttpResponse) -> u8\", key_by_final_url: false, partition_fn: \"Fn(&request::Parts) -> String\", permanent_redirect_ttl: None, validate_cached_fn: \"Fn(&HttpResponse) -> bool\", heuristic_fraction: None, heuristic_max_age: None, disable_heuristics: false, hard_max_age: None, head_refreshes_get: false, on_key_bypass: \"Fn(&request::Parts, KeyBypass)\", recompute_content_length: false, transform_for_storage_fn: \"Fn(HttpResponse) -> HttpResponse\", honor_no_transform: true }");
     opts.cache_options = None;
     opts.cache_key = Some(std::sync::Arc::new(|req: &http::request::Parts| {
         format!("{}:{}:{:?}:test", req.method, req.uri, req.version)
     }));
-    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true }");
+    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true, answer_client_conditionals: false, cache_key_format: CacheKeyFormat { delimiter: \":\", escape: false }, cache_preflight: false, cache_unknown_length: true, response_cache_control_fn: \"Fn(&HttpResponse) -> Option<CacheControl>\", honor_surrogate_control: false, cache_set_cookie: false, error_cache_ttl: None, failed_requests: FailedRequests { len: 0 }, path_rules: [], revalidation_request_fn: \"Fn(&mut request::Builder)\", add_cache_status: false, serve_stale_after: None, background_revalidate_always: false, vary_normalize_fn: \"Fn(&str, &str) -> Option<String>\", entry_metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", honor_pragma: true, accept_revalidation_fn: \"Fn(&HttpResponse) -> bool\", store_request: false, override_must_revalidate_on_error: false, max_body_size: None, on_store: \"Fn(&str, StoreOutcome)\", should_store_fn: \"Fn(&request::Parts, &HttpResponse) -> bool\", response_date_override: None, cache_variants: false, variants_lock: VariantsLock, max_entries_per_host: None, host_index: HostIndex { hosts: 0 }, optional_cache_key: \"Fn(&request::Parts) -> Option<String>\", max_background_revalidations: None, revalidations: RevalidationCounter { in_flight: 0 }, shared_fn: \"Fn(&request::Parts) -> bool\", cacheable_methods: [GET, HEAD], default_max_age: None, bypass_header: None, minimal_headers: false, on_stale_served: \"Fn(&str, StaleReason)\", vary_hash_fn: \"Fn(&str) -> String\", compression_offload_threshold: 65536, policy_fn: \"Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy\", priority_fn: \"Fn(&request::Parts, &HttpResponse) -> u8\", key_by_final_url: false, partition_fn: \"Fn(&request::Parts) -> String\", permanent_redirect_ttl: None, validate_cached_fn: \"Fn(&HttpResponse) -> bool\", heuristic_fraction: None, heuristic_max_age: None, disable_heuristics: false, hard_max_age: None, head_refreshes_get: false, on_key_bypass: \"Fn(&request::Parts, KeyBypass)\", recompute_content_length: false, transform_for_storage_fn: \"Fn(HttpResponse) -> HttpResponse\", honor_no_transform: true }");
     opts.cache_status_headers = false;
-    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: false }");
+    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: false, answer_client_conditionals: false, cache_key_format: CacheKeyFormat { delimiter: \":\", escape: false }, cache_preflight: false, cache_unknown_length: true, response_cache_control_fn: \"Fn(&HttpResponse) -> Option<CacheControl>\", honor_surrogate_control: false, cache_set_cookie: false, error_cache_ttl: None, failed_requests: FailedRequests { len: 0 }, path_rules: [], revalidation_request_fn: \"Fn(&mut request::Builder)\", add_cache_status: false, serve_stale_after: None, background_revalidate_always: false, vary_normalize_fn: \"Fn(&str, &str) -> Option<String>\", entry_metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", honor_pragma: true, accept_revalidation_fn: \"Fn(&HttpResponse) -> bool\", store_request: false, override_must_revalidate_on_error: false, max_body_size: None, on_store: \"Fn(&str, StoreOutcome)\", should_store_fn: \"Fn(&request::Parts, &HttpResponse) -> bool\", response_date_override: None, cache_variants: false, variants_lock: VariantsLock, max_entries_per_host: None, host_index: HostIndex { hosts: 0 }, optional_cache_key: \"Fn(&request::Parts) -> Option<String>\", max_background_revalidations: None, revalidations: RevalidationCounter { in_flight: 0 }, shared_fn: \"Fn(&request::Parts) -> bool\", cacheable_methods: [GET, HEAD], default_max_age: None, bypass_header: None, minimal_headers: false, on_stale_served: \"Fn(&str, StaleReason)\", vary_hash_fn: \"Fn(&str) -> String\", compression_offload_threshold: 65536, policy_fn: \"Fn(&request::Parts, &HttpResponse, &CacheOptions) -> CachePolicy\", priority_fn: \"Fn(&request::Parts, &HttpResponse) -> u8\", key_by_final_url: false, partition_fn: \"Fn(&request::Parts) -> String\", permanent_redirect_ttl: None, validate_cached_fn: \"Fn(&HttpResponse) -> bool\", heuristic_fraction: None, heuristic_max_age: None, disable_heuristics: false, hard_max_age: None, head_refreshes_get: false, on_key_bypass: \"Fn(&request::Parts, KeyBypass)\", recompute_content_length: false, transform_for_storage_fn: \"Fn(HttpResponse) -> HttpResponse\", honor_no_transform: true }");
+    Ok(())
+}
+
+#[test]
+fn cache_options_builder() -> Result<()> {
+    // The builder starts from the same defaults as the struct
+    assert_eq!(
+        format!("{:?}", HttpCacheOptions::builder().build()),
+        format!("{:?}", HttpCacheOptions::default()),
+    );
+    let opts = HttpCacheOptions::builder()
+        .cache_options(CacheOptions { shared: false, ..Default::default() })
+        .cache_key(|parts| format!("{}:test", parts.uri))
+        .cache_status_headers(false)
+        .cache_unknown_length(false)
+        .error_cache_ttl(std::time::Duration::from_secs(5))
+        .build();
+    let literal = HttpCacheOptions {
+        cache_options: Some(CacheOptions {
+            shared: false,
+            ..Default::default()
+        }),
+        cache_key: Some(std::sync::Arc::new(|parts: &http::request::Parts| {
+            format!("{}:test", parts.uri)
+        })),
+        cache_status_headers: false,
+        cache_unknown_length: false,
+        error_cache_ttl: Some(std::time::Duration::from_secs(5)),
+        ..Default::default()
+    };
+    assert_eq!(format!("{:?}", opts), format!("{:?}", literal));
+    let parts =
+        http::Request::get("http://example.com").body(())?.into_parts().0;
+    assert_eq!(
+        opts.create_cache_key(&parts, None),
+        literal.create_cache_key(&parts, None),
+    );
+    Ok(())
+}
+
+#[test]
+fn glob_pattern() -> Result<()> {
+    let pattern = GlobPattern::new("/api/v1/static/*");
+    assert_eq!(pattern.as_str(), "/api/v1/static/*");
+    assert!(pattern.matches("/api/v1/static/"));
+    assert!(pattern.matches("/api/v1/static/logo.png"));
+    assert!(!pattern.matches("/api/v1/static/img/logo.png"));
+    assert!(!pattern.matches("/api/v1/dynamic/logo.png"));
+    let pattern = GlobPattern::from("/api/**/*.png");
+    assert!(pattern.matches("/api/v1/static/img/logo.png"));
+    assert!(pattern.matches("/api/logo.png"));
+    assert!(!pattern.matches("/api/v1/logo.jpg"));
+    let pattern = GlobPattern::new("/v?/users");
+    assert!(pattern.matches("/v1/users"));
+    assert!(!pattern.matches("/v10/users"));
+    assert!(!pattern.matches("/v/users"));
     Ok(())
 }
 
+#[test]
+fn cache_control() -> Result<()> {
+    let cache_control = CacheControl::parse(
+        "Public, max-age=\"60\", s-maxage=120, stale-while-revalidate=30, \
+        stale-if-error=600, no-cache=\"set-cookie\", must-revalidate, \
+        proxy-revalidate, immutable, no-transform, max-age=5, unknown=1, \
+        max-stale",
+    );
+    assert_eq!(
+        cache_control,
+        CacheControl {
+            max_age: Some(std::time::Duration::from_secs(60)),
+            s_maxage: Some(std::time::Duration::from_secs(120)),
+            stale_while_revalidate: Some(std::time::Duration::from_secs(30)),
+            stale_if_error: Some(std::time::Duration::from_secs(600)),
+            no_cache: true,
+            no_store: false,
+            must_revalidate: true,
+            proxy_revalidate: true,
+            immutable: true,
+            public: true,
+            private: false,
+            no_transform: true,
+        }
+    );
+
+    // Invalid values are ignored and empty headers have no directives
+    let cache_control = CacheControl::parse("private, no-store, max-age=-1,");
+    assert!(cache_control.private && cache_control.no_store);
+    assert_eq!(cache_control.max_age, None);
+    assert_eq!(CacheControl::parse(""), CacheControl::default());
+
+    // Commas within quoted values don't split directives
+    let cache_control = CacheControl::parse(
+        "no-cache=\"set-cookie, x-foo\", private=\"a, b\", max-age=60",
+    );
+    assert!(cache_control.no_cache && cache_control.private);
+    assert_eq!(cache_control.max_age, Some(std::time::Duration::from_secs(60)));
+    let cache_control =
+        CacheControl::parse("private=\"a\\\", max-age=1\", max-age=2");
+    assert_eq!(cache_control.max_age, Some(std::time::Duration::from_secs(2)));
+
+    // Directives may be split across several header lines
+    let mut headers = http::HeaderMap::new();
+    headers.append(CACHE_CONTROL, "no-cache".parse()?);
+    headers.append(CACHE_CONTROL, "max-age=0".parse()?);
+    let cache_control = CacheControl::from_headers(&headers);
+    assert!(cache_control.no_cache);
+    assert_eq!(cache_control.max_age, Some(std::time::Duration::ZERO));
+
+    // Formatting writes the directives that are set back as a header value
+    let cache_control = CacheControl::parse(
+        "Private, no-cache=\"set-cookie\", max-age=60, x=1",
+    );
+    assert_eq!(cache_control.to_string(), "max-age=60, no-cache, private");
+    assert_eq!(CacheControl::parse(&cache_control.to_string()), cache_control);
+    assert_eq!(CacheControl::default().to_string(), "");
+    Ok(())
+}
+
+#[test]
+fn cache_key_canonical_uri() -> Result<()> {
+    let opts = HttpCacheOptions::default();
+    let key = |uri: &str| -> Result<String> {
+        let parts = http::Request::get(uri).body(())?.into_parts().0;
+        Ok(opts.create_cache_key(&parts, None))
+    };
+
+    // The scheme is always part of the key
+    assert_eq!(key("http://example.com:80/a")?, "GET:http://example.com/a");
+    assert_eq!(key("https://example.com:443/a")?, "GET:https://example.com/a");
+    assert_ne!(key("http://example.com/a")?, key("https://example.com/a")?);
+
+    // Default ports are left out, any other port is kept
+    assert_eq!(key("http://example.com/a")?, key("http://example.com:80/a")?);
+    assert_eq!(
+        key("http://example.com:8080/a?b=c")?,
+        "GET:http://example.com:8080/a?b=c"
+    );
+    assert_ne!(key("https://example.com:80/a")?, key("http://example.com/a")?);
+
+    // The host is lowercased and an empty path is a slash
+    assert_eq!(key("http://EXAMPLE.com")?, "GET:http://example.com/");
+    Ok(())
+}
+
+#[test]
+fn variant_key_canonical_headers() -> Result<()> {
+    use crate::{HttpCache, InMemoryManager};
+
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: InMemoryManager::new(),
+        options: HttpCacheOptions::default(),
+    };
+    let url = Url::parse("http://example.com/")?;
+    let key = |vary: &str, headers: &[(&str, &str)]| -> Result<String> {
+        let mut req = http::Request::get(url.as_str());
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let parts = req.body(())?.into_parts().0;
+        let res = HttpResponse {
+            body: Vec::new(),
+            headers: HashMap::from([("vary".to_string(), vary.to_string())]),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        Ok(cache.variant_key("GET:http://example.com/", &res, &parts).unwrap())
+    };
+
+    // Header names and whitespace in values don't change the key
+    let expected = key("accept-encoding", &[("accept-encoding", "gzip, br")])?;
+    assert_eq!(
+        key("Accept-Encoding", &[("Accept-Encoding", " gzip,br ")])?,
+        expected
+    );
+    assert_eq!(
+        key(
+            "ACCEPT-ENCODING",
+            &[("accept-encoding", "gzip"), ("accept-encoding", "br")]
+        )?,
+        expected
+    );
+
+    // Different values still select different variants
+    assert_ne!(
+        key("accept-encoding", &[("accept-encoding", "gzip")])?,
+        expected
+    );
+    assert_ne!(
+        key("accept-language", &[("accept-language", "en  US")])?,
+        key("accept-language", &[("accept-language", "enUS")])?
+    );
+    Ok(())
+}
+
+#[test]
+fn cache_key_partition() -> Result<()> {
+    let opts = HttpCacheOptions::builder()
+        .cache_key(|parts| parts.uri.path().trim_start_matches('/').to_owned())
+        .partition_fn(|parts| {
+            parts
+                .headers
+                .get("x-tenant")
+                .and_then(|tenant| tenant.to_str().ok())
+                .unwrap_or_default()
+                .to_owned()
+        })
+        .build();
+    let key = |tenant: &str, uri: &str| -> Result<String> {
+        let req = http::Request::get(uri).header("x-tenant", tenant);
+        Ok(opts.create_cache_key(&req.body(())?.into_parts().0, None))
+    };
+
+    // Identical urls in different partitions get different keys
+    assert_eq!(key("alice", "http://example.com/a")?, "5:alice:a");
+    assert_ne!(
+        key("alice", "http://example.com/a")?,
+        key("bob", "http://example.com/a")?
+    );
+
+    // Partitions can't be forged by moving characters into the key
+    assert_ne!(
+        key("a", "http://example.com/b:x")?,
+        key("a:b", "http://example.com/x")?
+    );
+    assert_ne!(
+        key("", "http://example.com/0::a")?,
+        key("0:", "http://example.com/a")?
+    );
+
+    // The entries kept alongside others are in the same partition
+    assert_eq!(opts.split_partition("5:alice:a"), ("5:alice:", "a"));
+    assert_eq!(
+        opts.sidecar_key("metadata", "5:alice:a", &[]),
+        "5:alice:metadata:a"
+    );
+    assert_eq!(opts.split_partition("a"), ("", "a"));
+
+    // The partition is joined with the delimiter of the key format
+    let opts = HttpCacheOptions::builder()
+        .cache_key_format(CacheKeyFormat::new("|"))
+        .partition_fn(|_| "a|b".to_owned())
+        .build();
+    let key = opts.create_cache_key(
+        &http::Request::get("http://example.com/").body(())?.into_parts().0,
+        None,
+    );
+    assert_eq!(key, "5|a%7Cb|GET|http://example.com/");
+    assert_eq!(
+        opts.split_partition(&key),
+        ("5|a%7Cb|", "GET|http://example.com/")
+    );
+    Ok(())
+}
+
+#[test]
+fn cache_key_format() -> Result<()> {
+    let req = http::Request::get("http://example.com/a:b").body(())?;
+    let parts = req.into_parts().0;
+
+    // The default format matches the historical key format
+    let opts = HttpCacheOptions::default();
+    assert_eq!(
+        opts.create_cache_key(&parts, None),
+        "GET:http://example.com/a:b"
+    );
+
+    // Without escaping, components containing the delimiter can collide
+    let format = CacheKeyFormat::default();
+    assert_eq!(
+        format.format(&["GET:http", "x"]),
+        format.format(&["GET", "http:x"])
+    );
+
+    // With escaping, keys are always distinct
+    let format = CacheKeyFormat::new(":");
+    assert_ne!(
+        format.format(&["GET:http", "x"]),
+        format.format(&["GET", "http:x"])
+    );
+    assert_ne!(format.format(&["a%3Ab"]), format.format(&["a:b"]));
+    let opts =
+        HttpCacheOptions { cache_key_format: format, ..Default::default() };
+    assert_eq!(
+        opts.create_cache_key(&parts, None),
+        "GET:http%3A//example.com/a%3Ab"
+    );
+
+    // Multi character delimiters escape each of their characters
+    let format = CacheKeyFormat::new("::");
+    assert_eq!(format.format(&["GET", "http://x"]), "GET::http%3A//x");
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "CacheKeyFormat delimiter must not be empty")]
+fn test_cache_key_format_empty_delimiter() {
+    let _ = CacheKeyFormat::new("");
+}
+
 #[test]
 #[allow(clippy::default_constructed_unit_structs)]
 fn test_errors() -> Result<()> {
@@ -59,6 +381,15 @@ fn test_errors() -> Result<()> {
     let bh = error::BadHeader::default();
     assert_eq!(format!("{:?}", bh.clone()), "BadHeader",);
     assert_eq!(bh.to_string(), "Error parsing header value".to_string(),);
+    let bm = error::BadCacheMode::default();
+    assert_eq!(format!("{:?}", bm.clone()), "BadCacheMode",);
+    assert_eq!(bm.to_string(), "Unknown cache mode".to_string(),);
+    #[cfg(feature = "manager-encrypted")]
+    {
+        let be = error::EncryptionError::default();
+        assert_eq!(format!("{:?}", be.clone()), "EncryptionError",);
+        assert_eq!(be.to_string(), "Error encrypting cache entry".to_string(),);
+    }
     Ok(())
 }
 
@@ -91,6 +422,11 @@ fn response_methods_work() -> Result<()> {
     assert_eq!(res.parts()?.headers, cloned_headers);
     res.headers.remove(CACHE_CONTROL.as_str());
     assert!(!res.must_revalidate());
+    assert!(!res.proxy_revalidate());
+    res.headers
+        .insert(CACHE_CONTROL.as_str().to_string(), "proxy-revalidate".into());
+    assert!(res.proxy_revalidate());
+    assert!(!res.must_revalidate());
     Ok(())
 }
 
@@ -138,6 +474,74 @@ fn can_convert_versions_from_http() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn cache_entry() -> Result<()> {
+    let url = Url::parse("http://example.com")?;
+    let http_res = HttpResponse {
+        body: TEST_BODY.to_vec(),
+        headers: Default::default(),
+        status: 200,
+        url,
+        version: HttpVersion::Http11,
+    };
+    let req = http::Request::get("http://example.com").body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header(CACHE_CONTROL, "max-age=60")
+        .body(())?;
+    let policy = http_cache_semantics::CachePolicy::new(&req, &res);
+    let now = std::time::SystemTime::now();
+
+    let entry = CacheEntry::from((http_res, policy));
+    assert_eq!(entry.response().body, TEST_BODY);
+    assert!(entry.policy().is_storable());
+    assert!(!entry.is_stale(now));
+    assert!(entry.time_to_live(now).as_secs() <= 60);
+    assert!(entry.age(now).as_secs() < 60);
+
+    let (response, policy) = entry.into();
+    let entry = CacheEntry::new(response, policy);
+    let (response, _policy) = entry.into_parts();
+    assert_eq!(response.status, 200);
+    Ok(())
+}
+
+#[test]
+fn can_convert_responses_from_http() -> Result<()> {
+    let url = Url::parse("http://example.com")?;
+    let res = http::Response::builder()
+        .status(200)
+        .version(http::Version::HTTP_2)
+        .header(CACHE_CONTROL, "max-age=60")
+        .body(TEST_BODY.to_vec())?;
+    let http_res = HttpResponse::try_from((res, url.clone()))?;
+    assert_eq!(http_res.status, 200);
+    assert_eq!(http_res.version, HttpVersion::H2);
+    assert_eq!(http_res.url, url);
+    assert_eq!(http_res.body, TEST_BODY);
+    assert_eq!(http_res.headers.get("cache-control").unwrap(), "max-age=60");
+
+    let res = http::Response::<Vec<u8>>::try_from(http_res)?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.version(), http::Version::HTTP_2);
+    assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+    assert_eq!(res.body(), TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "with-http-body")]
+#[tokio::test]
+async fn can_collect_http_body() -> Result<()> {
+    let url = Url::parse("http://example.com")?;
+    let res = http::Response::builder()
+        .status(200)
+        .body(http_body_util::Full::new(TEST_BODY))?;
+    let http_res = HttpResponse::from_body(res, url).await?;
+    assert_eq!(http_res.status, 200);
+    assert_eq!(http_res.body, TEST_BODY);
+    Ok(())
+}
+
 #[cfg(all(test, feature = "with-http-types"))]
 mod with_http_types {
     use super::*;
@@ -176,7 +580,7 @@ mod with_http_types {
 mod with_cacache {
 
     use super::*;
-    use crate::{CACacheManager, CacheManager};
+    use crate::{CACacheManager, CacheManager, DedupCACacheManager};
 
     use http_cache_semantics::CachePolicy;
 
@@ -209,14 +613,17 @@ mod with_cacache {
             .await?;
         let data = manager.get(&format!("{}:{}", GET, &url)).await?;
         assert!(data.is_some());
-        assert_eq!(data.unwrap().0.body, TEST_BODY);
+        assert_eq!(data.unwrap().response.body, TEST_BODY);
+        assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+        assert!(!manager.contains(&format!("{}:{}/absent", GET, &url)).await?);
         let clone = manager.clone();
         let clonedata = clone.get(&format!("{}:{}", GET, &url)).await?;
         assert!(clonedata.is_some());
-        assert_eq!(clonedata.unwrap().0.body, TEST_BODY);
+        assert_eq!(clonedata.unwrap().response.body, TEST_BODY);
         manager.delete(&format!("{}:{}", GET, &url)).await?;
         let data = manager.get(&format!("{}:{}", GET, &url)).await?;
         assert!(data.is_none());
+        assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
 
         manager.put(format!("{}:{}", GET, &url), http_res, policy).await?;
         manager.clear().await?;
@@ -225,15 +632,524 @@ mod with_cacache {
         std::fs::remove_dir_all("./http-cacache-test")?;
         Ok(())
     }
+
+    #[async_test]
+    async fn size_limited_load() -> Result<()> {
+        let inner = CACacheManager { path: "./http-cacache-load-test".into() };
+        crate::test_util::put_fresh_and_stale(&inner).await?;
+
+        // Entries stored before the manager was created are tracked
+        let manager =
+            crate::SizeLimitedManager::load(inner.clone(), 10).await?;
+        assert_eq!(manager.tracked_size(), 8);
+
+        // Deleting the stale entry drops it from the index
+        assert_eq!(manager.delete_stale().await?, 1);
+        assert_eq!(manager.tracked_size(), 4);
+
+        // Entries that don't fit are deleted when the index is seeded
+        crate::test_util::put_fresh_and_stale(&inner).await?;
+        let manager = crate::SizeLimitedManager::load(inner.clone(), 4).await?;
+        assert_eq!(manager.tracked_size(), 4);
+        assert_eq!(inner.keys().await?.map(|keys| keys.len()), Some(1));
+        std::fs::remove_dir_all("./http-cacache-load-test")?;
+        Ok(())
+    }
+
+    #[async_test]
+    async fn size_limited() -> Result<()> {
+        let manager = crate::SizeLimitedManager::new(
+            CACacheManager { path: "./http-cacache-size-test".into() },
+            10,
+        );
+        let req = http::Request::get("http://example.com").body(())?;
+        let res = http::Response::builder().status(200).body(())?;
+        let policy = CachePolicy::new(&req, &res);
+        let mut keys = Vec::new();
+        for path in ["a", "b", "c"] {
+            let url = Url::parse(&format!("http://example.com/{path}"))?;
+            let http_res = HttpResponse {
+                body: TEST_BODY.to_vec(),
+                headers: Default::default(),
+                status: 200,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+            };
+            let key = format!("{}:{}", GET, &url);
+            manager.put(key.clone(), http_res, policy.clone()).await?;
+            keys.push(key);
+            // Reading the first entry makes the second the least recently used
+            if path == "b" {
+                assert!(manager.get(&keys[0]).await?.is_some());
+            }
+        }
+
+        // Three 4 byte bodies don't fit in 10 bytes, so one was evicted
+        assert_eq!(manager.tracked_size(), 8);
+        assert!(manager.inner.get(&keys[0]).await?.is_some());
+        assert!(manager.inner.get(&keys[1]).await?.is_none());
+        assert!(manager.inner.get(&keys[2]).await?.is_some());
+
+        manager.delete(&keys[0]).await?;
+        assert_eq!(manager.tracked_size(), 4);
+        std::fs::remove_dir_all("./http-cacache-size-test")?;
+        Ok(())
+    }
+
+    #[async_test]
+    async fn delete_stale() -> Result<()> {
+        let manager =
+            CACacheManager { path: "./http-cacache-stale-test".into() };
+        crate::test_util::put_fresh_and_stale(&manager).await?;
+        assert_eq!(manager.delete_stale().await?, 1);
+        assert!(manager.contains("GET:http://example.com/fresh").await?);
+        assert!(!manager.contains("GET:http://example.com/stale").await?);
+        std::fs::remove_dir_all("./http-cacache-stale-test")?;
+        Ok(())
+    }
+
+    #[async_test]
+    async fn dedup_delete_stale() -> Result<()> {
+        let manager = DedupCACacheManager {
+            path: "./http-cacache-dedup-stale-test".into(),
+        };
+        crate::test_util::put_fresh_and_stale(&manager).await?;
+        assert_eq!(manager.delete_stale().await?, 1);
+        assert!(manager.contains("GET:http://example.com/fresh").await?);
+        assert!(!manager.contains("GET:http://example.com/stale").await?);
+        std::fs::remove_dir_all("./http-cacache-dedup-stale-test")?;
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacache_snapshot() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let manager =
+            CACacheManager { path: "./http-cacache-snapshot-test".into() };
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let req = http::Request::get("http://example.com").body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, "max-age=3600")
+            .body(())?;
+        manager
+            .put(
+                format!("{}:{}", GET, &url),
+                http_res,
+                CachePolicy::new(&req, &res),
+            )
+            .await?;
+
+        let mut snapshot = Vec::new();
+        manager.export_snapshot(&mut snapshot).await?;
+        manager.clear().await?;
+        assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
+
+        manager.import_snapshot(&mut snapshot.as_slice()).await?;
+        let data = manager.get(&format!("{}:{}", GET, &url)).await?;
+        assert_eq!(data.unwrap().response.body, TEST_BODY);
+        std::fs::remove_dir_all("./http-cacache-snapshot-test")?;
+        Ok(())
+    }
+
+    fn count_files(dir: &std::path::Path) -> Result<usize> {
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            count += if path.is_dir() { count_files(&path)? } else { 1 };
+        }
+        Ok(count)
+    }
+
+    #[async_test]
+    async fn cacache_dedup() -> Result<()> {
+        let manager =
+            DedupCACacheManager { path: "./http-cacache-dedup-test".into() };
+        let req = http::Request::get("http://example.com").body(())?;
+        let res =
+            http::Response::builder().status(200).body(TEST_BODY.to_vec())?;
+        let policy = CachePolicy::new(&req, &res);
+        for path in ["a", "b"] {
+            let url = Url::parse(&format!("http://example.com/{path}"))?;
+            let http_res = HttpResponse {
+                body: TEST_BODY.to_vec(),
+                headers: Default::default(),
+                status: 200,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+            };
+            manager
+                .put(format!("{}:{}", GET, &url), http_res, policy.clone())
+                .await?;
+        }
+
+        // Both keys are readable, sharing a single body blob
+        for path in ["a", "b"] {
+            let url = Url::parse(&format!("http://example.com/{path}"))?;
+            let data = manager.get(&format!("{}:{}", GET, &url)).await?;
+            let entry = data.unwrap();
+            assert_eq!(entry.response.body, TEST_BODY);
+            assert_eq!(entry.response.url, url);
+        }
+        assert_eq!(
+            count_files(std::path::Path::new(
+                "./http-cacache-dedup-test/content-v2"
+            ))?,
+            1
+        );
+
+        // Metadata reads return the headers and policy without the body
+        let url = Url::parse("http://example.com/c")?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: HashMap::from([(
+                "content-type".to_string(),
+                "text/plain".to_string(),
+            )]),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let key = format!("{}:{}", GET, &url);
+        manager.put(key.clone(), http_res, policy.clone()).await?;
+        let entry = manager.get_metadata(&key).await?.unwrap();
+        assert!(entry.response.body.is_empty());
+        assert_eq!(entry.response.headers["content-type"], "text/plain");
+        assert_eq!(entry.response.status, 200);
+        assert_eq!(manager.get(&key).await?.unwrap().response.body, TEST_BODY);
+        manager.delete(&key).await?;
+
+        // Deleting one key leaves the body for the other
+        let url = Url::parse("http://example.com/a")?;
+        manager.delete(&format!("{}:{}", GET, &url)).await?;
+        assert!(manager.get(&format!("{}:{}", GET, &url)).await?.is_none());
+        let url = Url::parse("http://example.com/b")?;
+        assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+        assert!(manager.get(&format!("{}:{}", GET, &url)).await?.is_some());
+        manager.clear().await?;
+        std::fs::remove_dir_all("./http-cacache-dedup-test")?;
+        Ok(())
+    }
+}
+
+mod with_in_memory {
+    use super::*;
+    use crate::{CacheManager, InMemoryManager};
+
+    use http::header::ETAG;
+    use http_cache_semantics::CachePolicy;
+
+    // Stores a response under its path with the given headers and returns the
+    // cache key
+    async fn put(
+        manager: &InMemoryManager,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<String> {
+        let url = Url::parse(&format!("http://example.com/{path}"))?;
+        let req = http::Request::get(url.as_str()).body(())?;
+        let mut res = http::Response::builder().status(200);
+        for (name, value) in headers {
+            res = res.header(*name, *value);
+        }
+        let res = res.body(())?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let key = format!("{}:{}", GET, &url);
+        manager
+            .put(key.clone(), http_res, CachePolicy::new(&req, &res))
+            .await?;
+        Ok(key)
+    }
+
+    #[async_attributes::test]
+    async fn in_memory() -> Result<()> {
+        let manager = InMemoryManager::new();
+        assert!(manager.is_empty());
+        assert!(manager.get("GET:http://example.com/").await?.is_none());
+
+        let key =
+            put(&manager, "", &[("cache-control", "max-age=86400")]).await?;
+        let data = manager.get(&key).await?.unwrap();
+        assert_eq!(data.response.body, TEST_BODY);
+        assert!(manager.contains(&key).await?);
+        assert_eq!(manager.size_bytes().await?, Some(4));
+        assert_eq!(manager.len(), 1);
+
+        manager.delete(&key).await?;
+        assert!(manager.get(&key).await?.is_none());
+        assert!(manager.is_empty());
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn in_memory_expiry() -> Result<()> {
+        let manager = InMemoryManager::new();
+        let fresh =
+            put(&manager, "fresh", &[("cache-control", "max-age=86400")])
+                .await?;
+        let stale =
+            put(&manager, "stale", &[("cache-control", "max-age=0")]).await?;
+
+        // Stale entries are kept, whether or not they can be revalidated
+        assert!(manager.get(&fresh).await?.is_some());
+        assert!(manager.get(&stale).await?.is_some());
+
+        // Removing stale entries leaves the fresh ones
+        assert_eq!(manager.delete_stale().await?, 1);
+        assert!(!manager.contains(&stale).await?);
+        assert_eq!(manager.len(), 1);
+
+        // With a hard bound, entries stale for too long are dropped on read
+        let manager = InMemoryManager::with_expire_after(
+            std::time::Duration::from_secs(60),
+        );
+        let recent =
+            put(&manager, "recent", &[("cache-control", "max-age=0")]).await?;
+        let old = put(
+            &manager,
+            "old",
+            &[
+                ("cache-control", "max-age=0"),
+                (ETAG.as_str(), "\"abc\""),
+                ("age", "120"),
+            ],
+        )
+        .await?;
+        assert!(manager.get(&recent).await?.is_some());
+        assert!(manager.get(&old).await?.is_none());
+        assert!(!manager.contains(&old).await?);
+        Ok(())
+    }
+}
+
+mod with_blocking {
+    use super::*;
+    use crate::{Blocking, BlockingCacheManager, CacheEntry, CacheManager};
+
+    use http_cache_semantics::CachePolicy;
+    use std::{collections::HashMap, sync::Mutex};
+
+    #[derive(Default)]
+    struct MapManager(Mutex<HashMap<String, CacheEntry>>);
+
+    impl BlockingCacheManager for MapManager {
+        fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+            Ok(self.0.lock().unwrap().get(cache_key).cloned())
+        }
+
+        fn put(
+            &self,
+            cache_key: String,
+            res: HttpResponse,
+            policy: CachePolicy,
+        ) -> Result<HttpResponse> {
+            let entry = CacheEntry::new(res.clone(), policy);
+            self.0.lock().unwrap().insert(cache_key, entry);
+            Ok(res)
+        }
+
+        fn delete(&self, cache_key: &str) -> Result<()> {
+            self.0.lock().unwrap().remove(cache_key);
+            Ok(())
+        }
+    }
+
+    #[async_attributes::test]
+    async fn blocking() -> Result<()> {
+        let manager = Blocking(MapManager::default());
+        let url = Url::parse("http://example.com/")?;
+        let key = format!("{}:{}", GET, &url);
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header("cache-control", "max-age=86400")
+            .body(())?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url,
+            version: HttpVersion::Http11,
+        };
+
+        // The synchronous manager is driven through the async trait
+        assert!(manager.get(&key).await?.is_none());
+        manager
+            .put(key.clone(), http_res, CachePolicy::new(&req, &res))
+            .await?;
+        let data = manager.get(&key).await?.unwrap();
+        assert_eq!(data.response.body, TEST_BODY);
+        assert!(manager.contains(&key).await?);
+        assert_eq!(manager.size_bytes().await?, None);
+
+        manager.delete(&key).await?;
+        assert!(!manager.contains(&key).await?);
+        Ok(())
+    }
 }
 
 #[cfg(feature = "manager-moka")]
 mod with_moka {
     use super::*;
-    use crate::{CacheManager, MokaManager};
+    use crate::{
+        CacheManager, HttpCache, MokaManager, RevalidationReport, XCACHE,
+    };
 
     use http_cache_semantics::CachePolicy;
-    use std::sync::Arc;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+
+    #[async_attributes::test]
+    async fn len() -> Result<()> {
+        let manager = MokaManager::default();
+        assert_eq!(manager.len().await?, Some(0));
+        assert_eq!(manager.is_empty().await?, Some(true));
+
+        let req = http::Request::get("http://example.com/").body(())?;
+        let res = http::Response::builder().status(200).body(())?;
+        for (count, path) in ["a", "b", "c"].into_iter().enumerate() {
+            let url = Url::parse(&format!("http://example.com/{path}"))?;
+            let http_res = HttpResponse {
+                body: TEST_BODY.to_vec(),
+                headers: Default::default(),
+                status: 200,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+            };
+            manager
+                .put(
+                    format!("{}:{}", GET, &url),
+                    http_res,
+                    CachePolicy::new(&req, &res),
+                )
+                .await?;
+            assert_eq!(manager.len().await?, Some(count + 1));
+        }
+        assert_eq!(manager.is_empty().await?, Some(false));
+
+        manager.delete("GET:http://example.com/a").await?;
+        assert_eq!(manager.len().await?, Some(2));
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn delete_stale() -> Result<()> {
+        let manager = MokaManager::default();
+        crate::test_util::put_fresh_and_stale(&manager).await?;
+        assert_eq!(manager.delete_stale().await?, 1);
+        assert!(manager.contains("GET:http://example.com/fresh").await?);
+        assert!(!manager.contains("GET:http://example.com/stale").await?);
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn timed_manager() -> Result<()> {
+        let timings = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = timings.clone();
+        let manager = crate::TimedManager::with_timing_fn(
+            MokaManager::default(),
+            move |op, cache_key, elapsed| {
+                recorded.lock().unwrap().push((
+                    op,
+                    cache_key.to_string(),
+                    elapsed,
+                ));
+            },
+        );
+        assert!(manager.get("GET:http://example.com/").await?.is_none());
+        let timings = timings.lock().unwrap();
+        assert_eq!(timings.len(), 1);
+        let (op, cache_key, elapsed) = &timings[0];
+        assert_eq!(*op, crate::Op::Get);
+        assert_eq!(cache_key, "GET:http://example.com/");
+        assert!(*elapsed > Duration::ZERO);
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn shed_memory() -> Result<()> {
+        let manager = MokaManager::default();
+        crate::test_util::put_fresh_and_stale(&manager).await?;
+        let total = manager.size_bytes().await?.unwrap();
+
+        // Nothing is evicted while under the limit
+        assert!(!manager.shed_memory(total).await?);
+        assert!(manager.contains("GET:http://example.com/stale").await?);
+
+        // Removing the stale entry is enough to get back under the limit
+        assert!(manager.shed_memory(total - 1).await?);
+        assert!(manager.contains("GET:http://example.com/fresh").await?);
+        assert!(!manager.contains("GET:http://example.com/stale").await?);
+
+        // Otherwise the entire cache is cleared
+        let watch = manager.watch_memory(0, Duration::from_millis(10));
+        let wait = async {
+            while manager.size_bytes().await? != Some(0) {
+                async_io::Timer::after(Duration::from_millis(10)).await;
+            }
+            Ok(())
+        };
+        futures_lite::future::or(watch, wait).await?;
+        assert!(!manager.contains("GET:http://example.com/fresh").await?);
+        Ok(())
+    }
+
+    #[cfg(feature = "value-cache")]
+    #[async_attributes::test]
+    async fn value_cache() -> Result<()> {
+        #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+        struct Reply {
+            id: u32,
+            name: String,
+        }
+
+        let manager = MokaManager::default();
+        let cache =
+            crate::ValueCache::new(manager.clone(), Duration::from_secs(60));
+        let reply = Reply { id: 1, name: "one".into() };
+
+        // Fresh values are returned as stored
+        cache.put("reply:1", &reply).await?;
+        assert_eq!(cache.get("reply:1").await?, Some(reply));
+        assert_eq!(cache.get("reply:2").await?, None);
+
+        // Expired values are not returned and are removed from the manager
+        let expired = Reply { id: 2, name: "two".into() };
+        cache.put_with_ttl("reply:2", &expired, Duration::ZERO).await?;
+        assert_eq!(cache.get("reply:2").await?, None);
+        assert!(!manager.contains("reply:2").await?);
+
+        // Sub-second time to live values are rounded up, not down to zero
+        let brief = Reply { id: 3, name: "three".into() };
+        cache
+            .put_with_ttl("reply:3", &brief, Duration::from_millis(500))
+            .await?;
+        assert_eq!(cache.get("reply:3").await?, Some(brief));
+
+        cache.delete("reply:1").await?;
+        assert_eq!(cache.get("reply:1").await?, None);
+        Ok(())
+    }
 
     #[async_attributes::test]
     async fn moka() -> Result<()> {
@@ -258,19 +1174,637 @@ mod with_moka {
             .await?;
         let data = manager.get(&format!("{}:{}", GET, &url)).await?;
         assert!(data.is_some());
-        assert_eq!(data.unwrap().0.body, TEST_BODY);
+        assert_eq!(data.unwrap().response.body, TEST_BODY);
+        assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
+        assert!(!manager.contains(&format!("{}:{}/absent", GET, &url)).await?);
         let clone = manager.clone();
         let clonedata = clone.get(&format!("{}:{}", GET, &url)).await?;
         assert!(clonedata.is_some());
-        assert_eq!(clonedata.unwrap().0.body, TEST_BODY);
+        assert_eq!(clonedata.unwrap().response.body, TEST_BODY);
         manager.delete(&format!("{}:{}", GET, &url)).await?;
         let data = manager.get(&format!("{}:{}", GET, &url)).await?;
         assert!(data.is_none());
+        assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
+
+        assert_eq!(manager.size_bytes().await?, Some(0));
 
         manager.put(format!("{}:{}", GET, &url), http_res, policy).await?;
+        assert!(manager.size_bytes().await?.is_some_and(|size| size > 0));
         manager.clear().await?;
         let data = manager.get(&format!("{}:{}", GET, &url)).await?;
         assert!(data.is_none());
         Ok(())
     }
+
+    #[async_attributes::test]
+    async fn with_max_bytes() -> Result<()> {
+        // The default capacity counts entries, however large they are
+        let manager = MokaManager::default();
+        crate::test_util::put_fresh_and_stale(&manager).await?;
+        assert_eq!(manager.len().await?, Some(2));
+
+        // Entries bigger than a byte capacity are never admitted
+        let manager = MokaManager::with_max_bytes(1);
+        crate::test_util::put_fresh_and_stale(&manager).await?;
+        assert_eq!(manager.len().await?, Some(0));
+        assert_eq!(manager.size_bytes().await?, Some(0));
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn time_to_idle() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let manager = MokaManager::with_time_to_idle(Duration::from_millis(50));
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let req = http::Request::get("http://example.com").body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, "max-age=3600")
+            .body(())?;
+        let policy = CachePolicy::new(&req, &res);
+        manager.put(format!("{}:{}", GET, &url), http_res, policy).await?;
+        assert!(manager.get(&format!("{}:{}", GET, &url)).await?.is_some());
+
+        // The entry is still fresh, but hasn't been touched for too long
+        async_std::task::sleep(Duration::from_millis(100)).await;
+        assert!(manager.get(&format!("{}:{}", GET, &url)).await?.is_none());
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn snapshot() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let manager = MokaManager::default();
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let req = http::Request::get("http://example.com").body(())?;
+        let fresh = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, "max-age=3600")
+            .body(())?;
+        let stale = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, "max-age=0")
+            .body(())?;
+        manager
+            .put(
+                format!("{}:{}", GET, &url),
+                http_res.clone(),
+                CachePolicy::new(&req, &fresh),
+            )
+            .await?;
+        manager
+            .put(
+                format!("{}:{}stale", GET, &url),
+                http_res,
+                CachePolicy::new(&req, &stale),
+            )
+            .await?;
+
+        let mut snapshot = Vec::new();
+        manager.export_snapshot(&mut snapshot).await?;
+
+        // Only fresh entries are loaded into the new manager
+        let restored = MokaManager::default();
+        restored.import_snapshot(&mut snapshot.as_slice()).await?;
+        let data = restored.get(&format!("{}:{}", GET, &url)).await?;
+        assert_eq!(data.unwrap().response.body, TEST_BODY);
+        assert!(!restored.contains(&format!("{}:{}stale", GET, &url)).await?);
+
+        // Snapshots aren't tied to the manager they were exported from
+        let restored = crate::InMemoryManager::new();
+        restored.import_snapshot(&mut snapshot.as_slice()).await?;
+        assert!(restored.contains(&format!("{}:{}", GET, &url)).await?);
+
+        // A manager that can't list its keys can't export a snapshot
+        struct UnlistedManager(MokaManager);
+
+        #[async_trait::async_trait]
+        impl CacheManager for UnlistedManager {
+            async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+                self.0.get(cache_key).await
+            }
+
+            async fn put(
+                &self,
+                cache_key: String,
+                res: HttpResponse,
+                policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                self.0.put(cache_key, res, policy).await
+            }
+
+            async fn delete(&self, cache_key: &str) -> Result<()> {
+                self.0.delete(cache_key).await
+            }
+        }
+
+        let error = UnlistedManager(manager)
+            .export_snapshot(&mut Vec::new())
+            .await
+            .unwrap_err();
+        assert!(error.is::<crate::SnapshotUnsupported>());
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn get_or_fetch() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let manager = MokaManager::default();
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        };
+        let fetches = AtomicUsize::new(0);
+        let fetch = |cache_control: &'static str| {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            let url = url.clone();
+            async move {
+                Ok(HttpResponse {
+                    body: TEST_BODY.to_vec(),
+                    headers: HashMap::from([(
+                        CACHE_CONTROL.as_str().to_string(),
+                        cache_control.to_string(),
+                    )]),
+                    status: 200,
+                    url,
+                    version: HttpVersion::Http11,
+                })
+            }
+        };
+
+        // Nothing cached yet, so the response is computed and stored
+        let res =
+            cache.get_or_fetch("computed", || fetch("max-age=3600")).await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert_eq!(res.headers.get(XCACHE).unwrap(), "MISS");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(manager.get("computed").await?.is_some());
+
+        // The fresh entry is returned without computing it again
+        let res =
+            cache.get_or_fetch("computed", || fetch("max-age=3600")).await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert_eq!(res.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // Responses that can't be stored are computed every time
+        cache.get_or_fetch("uncacheable", || fetch("no-store")).await?;
+        cache.get_or_fetch("uncacheable", || fetch("no-store")).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        assert!(manager.get("uncacheable").await?.is_none());
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn touch() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let manager = MokaManager::default();
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        };
+        assert!(!cache.touch("missing", Duration::from_secs(60)).await?);
+
+        // Store an entry that is about to expire
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder()
+            .header(CACHE_CONTROL, "max-age=60")
+            .header("age", "59")
+            .body(())?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: HashMap::from([(
+                CACHE_CONTROL.as_str().to_string(),
+                "max-age=60".to_string(),
+            )]),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        manager
+            .put("touched".into(), http_res, CachePolicy::new(&req, &res))
+            .await?;
+        let ttl = |entry: CacheEntry| {
+            entry.policy.time_to_live(SystemTime::now()).as_secs()
+        };
+        assert!(ttl(manager.get("touched").await?.unwrap()) <= 1);
+
+        // Touching the entry keeps it fresh without refetching
+        assert!(cache.touch("touched", Duration::from_secs(3600)).await?);
+        assert!(ttl(manager.get("touched").await?.unwrap()) >= 3599);
+        let res = cache
+            .get_or_fetch("touched", || async {
+                Err::<HttpResponse, _>("the entry should be fresh".into())
+            })
+            .await?;
+        assert_eq!(res.body, TEST_BODY);
+        assert_eq!(res.headers.get(XCACHE).unwrap(), "HIT");
+
+        // Only the freshness lifetime is replaced, so an entry that must be
+        // revalidated still is
+        let res = http::Response::builder()
+            .header(CACHE_CONTROL, "no-cache, max-age=60")
+            .body(())?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: HashMap::from([(
+                CACHE_CONTROL.as_str().to_string(),
+                "no-cache, max-age=60".to_string(),
+            )]),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        manager
+            .put("no-cache".into(), http_res, CachePolicy::new(&req, &res))
+            .await?;
+        assert!(cache.touch("no-cache", Duration::from_secs(3600)).await?);
+        let entry = manager.get("no-cache").await?.unwrap();
+        assert!(matches!(
+            entry.policy.before_request(&req, SystemTime::now()),
+            http_cache_semantics::BeforeRequest::Stale { .. }
+        ));
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn revalidate_stale() -> Result<()> {
+        let manager = MokaManager::default();
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        };
+        let response =
+            |url: &Url, status: u16, cache_control: &str| HttpResponse {
+                body: TEST_BODY.to_vec(),
+                headers: HashMap::from([
+                    (CACHE_CONTROL.as_str().to_string(), cache_control.into()),
+                    ("etag".to_string(), "\"v1\"".to_string()),
+                ]),
+                status,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+            };
+        // Store stale entries for the origin to answer in different ways,
+        // and a fresh one that's left alone
+        for path in ["not-modified", "modified", "gone", "down", "fresh"] {
+            let url = Url::parse(&format!("http://example.com/{path}"))?;
+            let max_age = if path == "fresh" { 3600 } else { 0 };
+            let res = response(&url, 200, &format!("max-age={max_age}"));
+            let req = http::Request::get(url.as_str()).body(())?;
+            let policy = CachePolicy::new(&req, &res.parts()?);
+            manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+        }
+
+        let in_flight = AtomicUsize::new(0);
+        let most_in_flight = AtomicUsize::new(0);
+        let fetch = |parts: http::request::Parts| {
+            let in_flight = &in_flight;
+            let most_in_flight = &most_in_flight;
+            async move {
+                let count = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most_in_flight.fetch_max(count, Ordering::SeqCst);
+                async_io::Timer::after(Duration::from_millis(10)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                assert_eq!(parts.headers["if-none-match"], "\"v1\"");
+                let url = Url::parse(&parts.uri.to_string())?;
+                let mut res = match url.path() {
+                    "/not-modified" => response(&url, 304, "max-age=3600"),
+                    "/modified" => response(&url, 200, "max-age=3600"),
+                    "/gone" => response(&url, 200, "no-store"),
+                    "/down" => response(&url, 503, "no-store"),
+                    path => panic!("{path} should not be revalidated"),
+                };
+                if res.status == 200 && url.path() == "/modified" {
+                    res.body = b"updated".to_vec();
+                }
+                Ok(res)
+            }
+        };
+
+        let report = cache.revalidate_stale(fetch, 2).await?;
+        assert_eq!(
+            report,
+            RevalidationReport { refreshed: 2, removed: 1, failed: 1 }
+        );
+        assert!(most_in_flight.load(Ordering::SeqCst) <= 2);
+
+        // Refreshed entries are fresh again, with the body from the origin
+        let now = SystemTime::now();
+        let entry = manager.get("GET:http://example.com/not-modified").await?;
+        let entry = entry.unwrap();
+        assert!(!entry.is_stale(now));
+        assert_eq!(entry.response.body, TEST_BODY);
+        let entry = manager.get("GET:http://example.com/modified").await?;
+        let entry = entry.unwrap();
+        assert!(!entry.is_stale(now));
+        assert_eq!(entry.response.body, b"updated");
+
+        // The entry that can no longer be stored is removed, the one the
+        // origin failed to answer for is kept stale
+        assert!(!manager.contains("GET:http://example.com/gone").await?);
+        let entry = manager.get("GET:http://example.com/down").await?;
+        assert!(entry.unwrap().is_stale(now));
+        assert_eq!(
+            manager.stale_keys().await?,
+            ["GET:http://example.com/down"]
+        );
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn revalidate_stale_manager_error() -> Result<()> {
+        // A manager that fails to store the entry for one of the urls
+        struct FailingManager(MokaManager);
+
+        #[async_trait::async_trait]
+        impl CacheManager for FailingManager {
+            async fn get(&self, cache_key: &str) -> Result<Option<CacheEntry>> {
+                self.0.get(cache_key).await
+            }
+
+            async fn put(
+                &self,
+                cache_key: String,
+                res: HttpResponse,
+                policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                if cache_key.ends_with("/broken") {
+                    return Err("the entry can't be stored".into());
+                }
+                self.0.put(cache_key, res, policy).await
+            }
+
+            async fn delete(&self, cache_key: &str) -> Result<()> {
+                self.0.delete(cache_key).await
+            }
+
+            async fn stale_keys(&self) -> Result<Vec<String>> {
+                self.0.stale_keys().await
+            }
+        }
+
+        let manager = MokaManager::default();
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: FailingManager(manager.clone()),
+            options: HttpCacheOptions::default(),
+        };
+        let response = |url: &Url, max_age: u32| HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: HashMap::from([(
+                CACHE_CONTROL.as_str().to_string(),
+                format!("max-age={max_age}"),
+            )]),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        for path in ["broken", "working"] {
+            let url = Url::parse(&format!("http://example.com/{path}"))?;
+            let res = response(&url, 0);
+            let req = http::Request::get(url.as_str()).body(())?;
+            let policy = CachePolicy::new(&req, &res.parts()?);
+            manager.put(format!("{}:{}", GET, &url), res, policy).await?;
+        }
+
+        // The entry that can't be stored is counted as failed, without
+        // keeping the other one from being refreshed
+        let report = cache
+            .revalidate_stale(
+                |parts| async move {
+                    Ok(response(&Url::parse(&parts.uri.to_string())?, 3600))
+                },
+                1,
+            )
+            .await?;
+        assert_eq!(
+            report,
+            RevalidationReport { refreshed: 1, removed: 0, failed: 1 }
+        );
+        assert_eq!(
+            manager.stale_keys().await?,
+            ["GET:http://example.com/broken"]
+        );
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "test-util", feature = "manager-moka"))]
+mod with_recording {
+    use super::*;
+    use crate::{CacheManager, MokaManager, Op, RecordingManager};
+
+    use http_cache_semantics::CachePolicy;
+
+    #[async_attributes::test]
+    async fn recording() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let manager = RecordingManager::new(MokaManager::default());
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let req = http::Request::get("http://example.com").body(())?;
+        let res =
+            http::Response::builder().status(200).body(TEST_BODY.to_vec())?;
+        let policy = CachePolicy::new(&req, &res);
+        let key = format!("{}:{}", GET, &url);
+        assert!(manager.get(&key).await?.is_none());
+        manager.put(key.clone(), http_res, policy).await?;
+        assert!(manager.get(&key).await?.is_some());
+        manager.delete(&key).await?;
+
+        // Clones share the same log
+        let clone = manager.clone();
+        assert!(clone.get(&key).await?.is_none());
+        assert_eq!(
+            manager.calls(),
+            vec![
+                (Op::Get, key.clone()),
+                (Op::Put, key.clone()),
+                (Op::Get, key.clone()),
+                (Op::Delete, key.clone()),
+                (Op::Get, key.clone()),
+            ]
+        );
+        manager.clear_calls();
+        assert!(manager.calls().is_empty());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "manager-encrypted")]
+mod with_encrypted {
+    use super::*;
+    use crate::{CacheManager, EncryptedManager, InMemoryManager};
+
+    use http_cache_semantics::CachePolicy;
+
+    const KEY: &[u8; 32] = b"an example very very secret key.";
+
+    // Stores a fresh response through the manager and returns the cache key
+    async fn put(manager: &impl CacheManager) -> Result<String> {
+        let url = Url::parse("http://example.com/")?;
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header("cache-control", "max-age=86400")
+            .header("x-secret", "hunter2")
+            .body(())?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: HashMap::from([
+                ("cache-control".to_string(), "max-age=86400".to_string()),
+                ("x-secret".to_string(), "hunter2".to_string()),
+            ]),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let key = format!("{}:{}", GET, &url);
+        manager
+            .put(key.clone(), http_res, CachePolicy::new(&req, &res))
+            .await?;
+        Ok(key)
+    }
+
+    #[async_attributes::test]
+    async fn encrypted_round_trip() -> Result<()> {
+        let manager = EncryptedManager::new(InMemoryManager::new(), KEY);
+        let key = put(&manager).await?;
+
+        // The response is decrypted as it was stored
+        let entry = manager.get(&key).await?.unwrap();
+        assert_eq!(entry.response.body, TEST_BODY);
+        assert_eq!(entry.response.headers["x-secret"], "hunter2");
+        assert!(!entry.is_stale(std::time::SystemTime::now()));
+
+        // But the inner manager only holds the ciphertext and freshness
+        let stored = manager.inner.get(&key).await?.unwrap();
+        assert!(!stored.response.headers.contains_key("x-secret"));
+        assert_ne!(stored.response.body, TEST_BODY);
+        assert!(
+            !String::from_utf8_lossy(&stored.response.body).contains("hunter2")
+        );
+        let policy = bincode::serialize(&stored.policy)?;
+        assert!(!String::from_utf8_lossy(&policy).contains("hunter2"));
+        assert!(!stored.is_stale(std::time::SystemTime::now()));
+
+        manager.delete(&key).await?;
+        assert!(manager.get(&key).await?.is_none());
+        Ok(())
+    }
+
+    // Stores a response with the headers through the manager built with the
+    // options, and returns the entry kept by the wrapped manager
+    async fn put_with(
+        headers: &[(&str, &str)],
+        options: CacheOptions,
+    ) -> Result<(EncryptedManager<InMemoryManager>, String)> {
+        let url = Url::parse("http://example.com/")?;
+        let key = format!("{}:{}", GET, &url);
+        let req = http::Request::get(url.as_str()).body(())?;
+        let mut res = http::Response::builder().status(200);
+        for (name, value) in headers {
+            res = res.header(*name, *value);
+        }
+        let res = res.body(())?;
+        let http_res = HttpResponse {
+            body: TEST_BODY.to_vec(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let manager = EncryptedManager::new(InMemoryManager::new(), KEY)
+            .with_cache_options(options);
+        let policy = CachePolicy::new_options(
+            &req,
+            &res,
+            std::time::SystemTime::now(),
+            options,
+        );
+        manager.put(key.clone(), http_res, policy).await?;
+        Ok((manager, key))
+    }
+
+    #[async_attributes::test]
+    async fn encrypted_inner_policy() -> Result<()> {
+        let now = std::time::SystemTime::now();
+
+        // A stale entry keeps its validators so it can be revalidated
+        let (manager, key) = put_with(
+            &[("cache-control", "no-cache"), ("etag", "\"abc\"")],
+            Default::default(),
+        )
+        .await?;
+        let entry = manager.get(&key).await?.unwrap();
+        assert_eq!(entry.response.body, TEST_BODY);
+        let stored = manager.inner.get(&key).await?.unwrap();
+        assert_eq!(stored.response.headers["etag"], "\"abc\"");
+        assert!(stored.is_stale(now));
+
+        // A private response stored by a private cache is fresh to the
+        // wrapped manager too
+        let private = CacheOptions { shared: false, ..Default::default() };
+        let (manager, key) =
+            put_with(&[("cache-control", "private, max-age=600")], private)
+                .await?;
+        let stored = manager.inner.get(&key).await?.unwrap();
+        assert!(!stored.is_stale(now));
+        assert_eq!(manager.delete_stale().await?, 0);
+        Ok(())
+    }
+
+    #[async_attributes::test]
+    async fn encrypted_tamper_detection() -> Result<()> {
+        let manager = EncryptedManager::new(InMemoryManager::new(), KEY);
+        let key = put(&manager).await?;
+
+        // A different key can't decrypt the entry
+        let other = EncryptedManager::new(manager.inner.clone(), &[7; 32]);
+        assert!(other.get(&key).await?.is_none());
+
+        // Nor can the entry be read under another cache key
+        let entry = manager.inner.get(&key).await?.unwrap();
+        manager
+            .inner
+            .put(
+                "GET:http://example.com/other".into(),
+                entry.response.clone(),
+                entry.policy.clone(),
+            )
+            .await?;
+        assert!(manager.get("GET:http://example.com/other").await?.is_none());
+
+        // A modified ciphertext is treated as a miss
+        let mut tampered = entry.response;
+        let last = tampered.body.len() - 1;
+        tampered.body[last] ^= 1;
+        manager.inner.put(key.clone(), tampered, entry.policy).await?;
+        assert!(manager.get(&key).await?.is_none());
+        Ok(())
+    }
 }
@@ -0,0 +1,40 @@
+//! Fixtures for testing [`CacheManager`] implementations.
+
+use crate::{CacheManager, HttpResponse, HttpVersion, Result};
+
+use http::header::CACHE_CONTROL;
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+/// The key of the fresh entry stored by [`put_fresh_and_stale`].
+pub const FRESH_KEY: &str = "GET:http://example.com/fresh";
+
+/// The key of the stale entry stored by [`put_fresh_and_stale`].
+pub const STALE_KEY: &str = "GET:http://example.com/stale";
+
+/// Stores a response that stays fresh for a day under [`FRESH_KEY`], and one
+/// that is already stale under [`STALE_KEY`], for testing housekeeping such
+/// as [`CacheManager::delete_stale`]. Both have a 4 byte body.
+pub async fn put_fresh_and_stale(manager: &impl CacheManager) -> Result<()> {
+    let req = http::Request::get("http://example.com").body(())?;
+    for (path, cache_control) in
+        [("fresh", "max-age=86400"), ("stale", "max-age=0")]
+    {
+        let url = Url::parse(&format!("http://example.com/{path}"))?;
+        let http_res = HttpResponse {
+            body: b"test".to_vec(),
+            headers: Default::default(),
+            status: 200,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+        };
+        let res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, cache_control)
+            .body(())?;
+        manager
+            .put(format!("GET:{url}"), http_res, CachePolicy::new(&req, &res))
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{
+    marker::PhantomData,
+    time::{Duration, SystemTime},
+};
+
+use http::header::CACHE_CONTROL;
+use http_cache_semantics::CachePolicy;
+use serde::{de::DeserializeOwned, Serialize};
+use url::Url;
+
+use crate::{CacheManager, HttpResponse, HttpVersion, Result};
+
+/// A cache of arbitrary serializable values that is backed by any
+/// [`CacheManager`], for reusing the cache managers outside of HTTP.
+///
+/// Values are serialized with bincode and stored as the body of a synthetic
+/// response whose policy expires after the given time to live, so they share
+/// the freshness rules used for HTTP responses.
+#[cfg_attr(docsrs, doc(cfg(feature = "value-cache")))]
+pub struct ValueCache<T, M: CacheManager> {
+    /// Manager instance that implements the [`CacheManager`] trait.
+    pub manager: M,
+    /// Time to live used for values stored with [`ValueCache::put`].
+    pub ttl: Duration,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T, M: CacheManager + Clone> Clone for ValueCache<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            manager: self.manager.clone(),
+            ttl: self.ttl,
+            value: PhantomData,
+        }
+    }
+}
+
+impl<T, M: CacheManager + std::fmt::Debug> std::fmt::Debug
+    for ValueCache<T, M>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValueCache")
+            .field("manager", &self.manager)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl<T, M> ValueCache<T, M>
+where
+    T: Serialize + DeserializeOwned,
+    M: CacheManager,
+{
+    /// Create a new cache using the provided manager and default time to live
+    pub fn new(manager: M, ttl: Duration) -> Self {
+        Self { manager, ttl, value: PhantomData }
+    }
+
+    /// Returns the value stored under the key if it is still fresh, stale
+    /// values are removed from the manager.
+    pub async fn get(&self, key: &str) -> Result<Option<T>> {
+        let Some(entry) = self.manager.get(key).await? else {
+            return Ok(None);
+        };
+        if entry.policy.is_stale(SystemTime::now()) {
+            self.manager.delete(key).await?;
+            return Ok(None);
+        }
+        Ok(Some(bincode::deserialize(&entry.response.body)?))
+    }
+
+    /// Stores the value under the key using the default time to live.
+    pub async fn put(&self, key: impl Into<String>, value: &T) -> Result<()> {
+        self.put_with_ttl(key, value, self.ttl).await
+    }
+
+    /// Stores the value under the key, keeping it fresh for `ttl`. Freshness
+    /// is tracked in whole seconds, so a `ttl` with a fractional part is
+    /// rounded up to the next second.
+    pub async fn put_with_ttl(
+        &self,
+        key: impl Into<String>,
+        value: &T,
+        ttl: Duration,
+    ) -> Result<()> {
+        let secs = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+        let cache_control = format!("max-age={secs}");
+        let req = http::Request::get("/").body(())?.into_parts().0;
+        let res = http::Response::builder()
+            .header(CACHE_CONTROL, &cache_control)
+            .body(())?
+            .into_parts()
+            .0;
+        let policy = CachePolicy::new(&req, &res);
+        let response = HttpResponse {
+            body: bincode::serialize(value)?,
+            headers: [(CACHE_CONTROL.to_string(), cache_control)].into(),
+            status: 200,
+            url: Url::parse("http-cache:value")?,
+            version: HttpVersion::Http11,
+        };
+        self.manager.put(key.into(), response, policy).await?;
+        Ok(())
+    }
+
+    /// Removes the value stored under the key.
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.manager.delete(key).await
+    }
+}